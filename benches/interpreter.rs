@@ -0,0 +1,173 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use lc3rs::{NoPlugins, ScriptedIOHandle, VM};
+
+// The programs shared with the crate's tests
+#[allow(dead_code)]
+#[path = "../src/fixtures.rs"]
+mod fixtures;
+
+// Adds 3 to R0 ten thousand times: about 30,000 instructions of pure
+// register arithmetic and branching.
+fn arithmetic_loop() -> Vec<u16> {
+    vec![
+        // AND R0, R0, #0
+        0b0101_0000_0010_0000,
+        // LD R1, the loop count below
+        0b0010_0010_0000_0100,
+        // ADD R0, R0, #3
+        0b0001_0000_0010_0011,
+        // ADD R1, R1, #-1
+        0b0001_0010_0111_1111,
+        // BRp back to the first ADD
+        0b0000_0011_1111_1101,
+        // HALT
+        0xF025,
+        10_000,
+    ]
+}
+
+// The same loop run 100 times over: about 3 million instructions, long
+// enough for compiling to pay off.
+fn nested_loop() -> Vec<u16> {
+    vec![
+        // AND R0, R0, #0
+        0b0101_0000_0010_0000,
+        // LD R2, the outer count
+        0b0010_0100_0000_0111,
+        // LD R1, the inner count
+        0b0010_0010_0000_0111,
+        // ADD R0, R0, #3
+        0b0001_0000_0010_0011,
+        // ADD R1, R1, #-1
+        0b0001_0010_0111_1111,
+        // BRp back to the first ADD
+        0b0000_0011_1111_1101,
+        // ADD R2, R2, #-1
+        0b0001_0100_1011_1111,
+        // BRp back to the inner count LD
+        0b0000_0011_1111_1010,
+        // HALT
+        0xF025,
+        100,
+        10_000,
+    ]
+}
+
+// Prints a 14 character string 200 times with PUTS, so most of the time
+// goes on the trap and the IO handle.
+fn puts_output() -> Vec<u16> {
+    let mut program = vec![
+        // LEA R0, MSG
+        0b1110_0000_0000_0110,
+        // LD R1, the repeat count below
+        0b0010_0010_0000_0100,
+        // PUTS
+        0xF022,
+        // ADD R1, R1, #-1
+        0b0001_0010_0111_1111,
+        // BRp back to PUTS
+        0b0000_0011_1111_1101,
+        // HALT
+        0xF025,
+        200,
+    ];
+    program.extend("Hello, World!\n".chars().map(|ch| ch as u16));
+    program.push(0);
+    program
+}
+
+// Fills 1,000 words then copies them somewhere else, one LDR and STR at a
+// time.
+fn memory_copy() -> Vec<u16> {
+    vec![
+        // LD R1, SRC then LD R2, DST then LD R3, COUNT
+        0b0010_0010_0000_1111,
+        0b0010_0100_0000_1111,
+        0b0010_0110_0000_1111,
+        // FILL: STR R3, R1, #0
+        0b0111_0110_0100_0000,
+        // ADD R1, R1, #1 then ADD R3, R3, #-1
+        0b0001_0010_0110_0001,
+        0b0001_0110_1111_1111,
+        // BRp FILL
+        0b0000_0011_1111_1100,
+        // LD R1, SRC then LD R3, COUNT
+        0b0010_0010_0000_1000,
+        0b0010_0110_0000_1001,
+        // COPY: LDR R4, R1, #0 then STR R4, R2, #0
+        0b0110_1000_0100_0000,
+        0b0111_1000_1000_0000,
+        // ADD R1, R1, #1 then ADD R2, R2, #1 then ADD R3, R3, #-1
+        0b0001_0010_0110_0001,
+        0b0001_0100_1010_0001,
+        0b0001_0110_1111_1111,
+        // BRp COPY
+        0b0000_0011_1111_1010,
+        // HALT
+        0xF025,
+        // SRC, DST and COUNT
+        0x4000,
+        0x5000,
+        1000,
+    ]
+}
+
+fn run_program(program: &[u16]) {
+    let mut vm = VM::new_with_io(ScriptedIOHandle::new());
+    vm.load_program(program).unwrap();
+    vm.run().unwrap();
+}
+
+fn run_program_without_plugins(program: &[u16]) {
+    let mut vm = VM::new_with_plugins(ScriptedIOHandle::new(), NoPlugins);
+    vm.load_program(program).unwrap();
+    vm.run().unwrap();
+}
+
+fn run_program_without_decode_cache(program: &[u16]) {
+    let mut vm = VM::new_with_plugins(ScriptedIOHandle::new(), NoPlugins);
+    vm.set_decode_cache(false);
+    vm.load_program(program).unwrap();
+    vm.run().unwrap();
+}
+
+#[cfg(feature = "jit")]
+fn run_program_with_jit(program: &[u16]) {
+    let mut vm = VM::new_with_plugins(ScriptedIOHandle::new(), NoPlugins);
+    vm.enable_jit().unwrap();
+    vm.load_program(program).unwrap();
+    vm.run().unwrap();
+}
+
+// Benchmarks a program with dynamic plugins, with NoPlugins, with
+// NoPlugins decoding every instruction afresh and, when built with the jit
+// feature, compiled
+fn bench_program(c: &mut Criterion, name: &str, program: &[u16]) {
+    let mut group = c.benchmark_group(name);
+    group.bench_function("plugins", |b| b.iter(|| run_program(program)));
+    group.bench_function("no_plugins", |b| b.iter(|| run_program_without_plugins(program)));
+    group.bench_function("no_decode_cache", |b| b.iter(|| run_program_without_decode_cache(program)));
+    #[cfg(feature = "jit")]
+    group.bench_function("jit", |b| b.iter(|| run_program_with_jit(program)));
+    group.finish();
+}
+
+fn interpreter_benchmarks(c: &mut Criterion) {
+    bench_program(c, "arithmetic_loop", &arithmetic_loop());
+    bench_program(c, "puts_output", &puts_output());
+    bench_program(c, "memory_copy", &memory_copy());
+    // Sums 1..=2000, 2000 calls deep
+    bench_program(c, "recursion", &fixtures::sum_program(2000));
+
+    let program = nested_loop();
+    let mut group = c.benchmark_group("nested_loop");
+    group.sample_size(10);
+    group.bench_function("no_plugins", |b| b.iter(|| run_program_without_plugins(&program)));
+    group.bench_function("no_decode_cache", |b| b.iter(|| run_program_without_decode_cache(&program)));
+    #[cfg(feature = "jit")]
+    group.bench_function("jit", |b| b.iter(|| run_program_with_jit(&program)));
+    group.finish();
+}
+
+criterion_group!(benches, interpreter_benchmarks);
+criterion_main!(benches);
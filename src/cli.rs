@@ -1,15 +1,386 @@
+use std::convert::TryFrom;
+use std::str::FromStr;
+use std::time::Duration;
+
 use structopt::StructOpt;
 
+use crate::device::{parse_start_time, SerialEndpoint, TimerMode};
 use crate::error::{BoxErrors, PublicResult};
+use crate::limits::Limits;
+use crate::network::Topology;
+use crate::plugin::jsonlogger::EventKind;
+use crate::protection::Protection;
+use crate::trace::{parse_address, AddressRange};
+use crate::translate::Language;
 
 #[derive(StructOpt)]
 #[structopt(rename_all = "kebab-case")]
 pub struct Options {
-    pub path: String,
+    #[structopt(subcommand)]
+    pub command: Option<Command>,
+    /// Program to run when no subcommand is given
+    pub path: Option<String>,
     #[structopt(long, short)]
     pub debug_log_path: Option<String>,
+    /// Write each event to this file as a line of JSON
+    #[structopt(long)]
+    pub event_log: Option<String>,
+    /// Only log these kinds of event, such as command,mem-set
+    #[structopt(long, use_delimiter = true)]
+    pub event_kinds: Vec<EventKind>,
+    /// Only log events raised by instructions at these addresses, such as
+    /// x3000-x30FF
+    #[structopt(long)]
+    pub event_pc: Option<AddressRange>,
+    /// Only log memory reads and writes at these addresses
+    #[structopt(long)]
+    pub event_memory: Option<AddressRange>,
+    #[structopt(short, long)]
+    pub little_endian: bool,
+    /// Protect a range of memory, such as x0000-x00FF:read-only. Either
+    /// read-only, no-execute, execute-only or no-access. Can be repeated.
+    #[structopt(long, number_of_values = 1)]
+    pub protect: Vec<ProtectedRegion>,
+    /// Add an interval timer with its registers from this address, such
+    /// as xFE08
+    #[structopt(long, parse(try_from_str = parse_address))]
+    pub timer: Option<u16>,
+    /// What the timer counts, either instructions or ms
+    #[structopt(long, default_value = "instructions")]
+    pub timer_mode: TimerMode,
+    /// Interrupt vector the timer raises, such as x81
+    #[structopt(long, default_value = "x81", parse(try_from_str = parse_vector))]
+    pub timer_vector: u8,
+    /// Priority of the timer's interrupt, 1 to 7
+    #[structopt(long, default_value = "4", parse(try_from_str = parse_priority))]
+    pub timer_priority: u8,
+    /// Add a disk backed by this image file, which is created if it
+    /// doesn't exist
+    #[structopt(long)]
+    pub disk: Option<String>,
+    /// Address of the disk's registers
+    #[structopt(long, default_value = "xFE14", parse(try_from_str = parse_address))]
+    pub disk_address: u16,
+    /// Instructions each disk command takes to complete
+    #[structopt(long, default_value = "0")]
+    pub disk_latency: u64,
+    /// Interrupt vector the disk raises when a command completes
+    #[structopt(long, default_value = "x82", parse(try_from_str = parse_vector))]
+    pub disk_vector: u8,
+    /// Priority of the disk's interrupt, 1 to 7
+    #[structopt(long, default_value = "3", parse(try_from_str = parse_priority))]
+    pub disk_priority: u8,
+    /// Add a real-time clock with its registers from this address, such
+    /// as xFE18
+    #[structopt(long, parse(try_from_str = parse_address))]
+    pub rtc: Option<u16>,
+    /// Start the clock at this time, such as 2024-01-31T09:30:00, rather
+    /// than following the host's
+    #[structopt(long, parse(try_from_str = parse_start_time))]
+    pub rtc_start: Option<u64>,
+    /// Instructions per second of the clock started with --rtc-start
+    #[structopt(long, default_value = "1000000")]
+    pub rtc_rate: u64,
+    /// Add a random number register at this address, such as xFE1E
+    #[structopt(long, parse(try_from_str = parse_address))]
+    pub rng: Option<u16>,
+    /// Seed the random numbers so every run gets the same ones
+    #[structopt(long)]
+    pub rng_seed: Option<u64>,
+    /// Add a serial port linked to tcp:HOST:PORT, tcp-listen:PORT,
+    /// unix:PATH, unix-listen:PATH or pipe:RECEIVE,SEND. Listening waits
+    /// for the other end to connect.
+    #[structopt(long)]
+    pub serial: Option<SerialEndpoint>,
+    /// Address of the serial port's registers
+    #[structopt(long, default_value = "xFE20", parse(try_from_str = parse_address))]
+    pub serial_address: u16,
+    /// Interrupt vector the serial port raises when a byte arrives
+    #[structopt(long, default_value = "x83", parse(try_from_str = parse_vector))]
+    pub serial_vector: u8,
+    /// Priority of the serial port's interrupt, 1 to 7
+    #[structopt(long, default_value = "4", parse(try_from_str = parse_priority))]
+    pub serial_priority: u8,
+    /// Save the framebuffer at xC000 to this .png or .ppm file when the
+    /// program stops. Frames the program asks for by writing to xFE10 are
+    /// saved alongside it, numbered from 1.
+    #[structopt(long)]
+    pub framebuffer: Option<String>,
+    /// Draw the framebuffer in the terminal when the program stops and
+    /// whenever the program writes to xFE10
+    #[structopt(long)]
+    pub framebuffer_terminal: bool,
+    /// Abort after executing this many instructions
+    #[structopt(long)]
+    pub max_instructions: Option<u64>,
+    /// Abort after running for this many milliseconds
+    #[structopt(long)]
+    pub max_time_ms: Option<u64>,
+    /// Abort once the program tries to print more than this many characters
+    #[structopt(long)]
+    pub max_output: Option<u64>,
+    /// Write an execution profile to this file when the program stops
+    #[structopt(long)]
+    pub profile: Option<String>,
+    /// Profile format: flat, call-graph or folded (for flamegraph tools)
+    #[structopt(long, default_value = "flat")]
+    pub profile_format: ProfileFormat,
+    /// Symbol table (.sym) written by the assembler, used to label reports
+    #[structopt(long)]
+    pub symbols: Option<String>,
+    /// Write code coverage in lcov .info format to this file when the
+    /// program stops
+    #[structopt(long)]
+    pub coverage: Option<String>,
+    /// Write a listing annotated with execution and branch counts to this
+    /// file when the program stops
+    #[structopt(long)]
+    pub listing: Option<String>,
+    /// Assembly source of the program, for mapping coverage to source lines
+    #[structopt(long)]
+    pub source: Option<String>,
+    /// File mapping each instruction address to a source line, such as
+    /// "x3000 12" per line. Worked out from --source when not given.
+    #[structopt(long)]
+    pub line_map: Option<String>,
+    /// Write a binary trace of every instruction executed to this file, for
+    /// viewing with the trace command
+    #[structopt(long)]
+    pub trace: Option<String>,
+    /// Compress the trace written with --trace
+    #[structopt(long)]
+    pub trace_compress: bool,
+    /// Write subroutine calls and traps to this file as Chrome Trace Event
+    /// JSON, for Perfetto or chrome://tracing
+    #[structopt(long)]
+    pub timeline: Option<String>,
+    /// Report reads of uninitialized registers and memory that decide what
+    /// the program does to stderr when the program stops
+    #[structopt(long)]
+    pub sanitize: bool,
+    /// Check subroutines keep to the R6 stack calling convention and
+    /// report violations to stderr when the program stops
+    #[structopt(long)]
+    pub stack_check: bool,
+    /// Addresses a push through R6 must not reach, such as x3000-x3FFF.
+    /// Defaults to the loaded program.
+    #[structopt(long)]
+    pub stack_guard: Option<AddressRange>,
+    /// Print the number of instructions executed, the run time and MIPS
+    /// to stderr when the program stops
+    #[structopt(long)]
+    pub stats: bool,
+    /// Compile hot code to native code. Not used while a debug log is written.
+    #[cfg(feature = "jit")]
+    #[structopt(long)]
+    pub jit: bool,
+}
+
+fn parse_vector(text: &str) -> Result<u8, String> {
+    let vector = parse_address(text)?;
+    u8::try_from(vector).map_err(|_| format!("Interrupt vector {} is more than xFF", text))
+}
+
+fn parse_priority(text: &str) -> Result<u8, String> {
+    match text.parse::<u8>() {
+        Ok(priority @ 1..=7) => Ok(priority),
+        _ => Err(format!("Priority {:?} isn't between 1 and 7", text)),
+    }
+}
+
+impl Options {
+    pub fn limits(&self) -> Limits {
+        Limits {
+            max_instructions: self.max_instructions,
+            max_duration: self.max_time_ms.map(Duration::from_millis),
+            max_output_chars: self.max_output,
+        }
+    }
+}
+
+#[derive(StructOpt)]
+#[structopt(rename_all = "kebab-case")]
+pub enum Command {
+    /// Run a program against every case in a TOML or JSON test spec
+    Grade(GradeOptions),
+    /// Translate a program to Rust or C source code
+    Translate(TranslateOptions),
+    /// Filter, search and print a trace written with --trace
+    Trace(TraceOptions),
+    /// Run programs on VMs linked by a virtual network, one VM each
+    Network(NetworkOptions),
+}
+
+#[derive(StructOpt)]
+#[structopt(rename_all = "kebab-case")]
+pub struct GradeOptions {
+    pub path: String,
+    pub spec_path: String,
     #[structopt(short, long)]
-    pub little_endian: bool, 
+    pub little_endian: bool,
+    /// Report format, either json or junit
+    #[structopt(long, default_value = "json")]
+    pub format: ReportFormat,
+    /// Where to write the report. Defaults to stdout.
+    #[structopt(long, short)]
+    pub output: Option<String>,
+}
+
+#[derive(StructOpt)]
+#[structopt(rename_all = "kebab-case")]
+pub struct TranslateOptions {
+    pub path: String,
+    #[structopt(short, long)]
+    pub little_endian: bool,
+    /// Language to write, either rust or c
+    #[structopt(long, default_value = "rust")]
+    pub lang: Language,
+    /// Where to write the source code. Defaults to stdout.
+    #[structopt(long, short)]
+    pub output: Option<String>,
+}
+
+#[derive(StructOpt)]
+#[structopt(rename_all = "kebab-case")]
+pub struct TraceOptions {
+    pub path: String,
+    /// Output format, either text or json (one object per line)
+    #[structopt(long, default_value = "text")]
+    pub format: TraceFormat,
+    /// Only instructions at these addresses, such as x3000-x30FF
+    #[structopt(long)]
+    pub pc: Option<AddressRange>,
+    /// Only instructions that write to memory in this range, such as
+    /// x4000-x4FFF
+    #[structopt(long)]
+    pub writes: Option<AddressRange>,
+    /// Start from the first instruction executed at this address
+    #[structopt(long, parse(try_from_str = parse_address))]
+    pub after: Option<u16>,
+    /// Stop once the instruction at this address has executed
+    #[structopt(long, parse(try_from_str = parse_address))]
+    pub until: Option<u16>,
+    /// Only instructions whose disassembly contains this text, ignoring case
+    #[structopt(long)]
+    pub search: Option<String>,
+    /// Where to write the trace. Defaults to stdout.
+    #[structopt(long, short)]
+    pub output: Option<String>,
+}
+
+#[derive(StructOpt)]
+#[structopt(rename_all = "kebab-case")]
+pub struct NetworkOptions {
+    /// Programs to run. Each is a node, numbered from 0 in this order.
+    #[structopt(required = true)]
+    pub paths: Vec<String>,
+    #[structopt(short, long)]
+    pub little_endian: bool,
+    /// How the nodes are linked: full, ring, line or star (with node 0
+    /// in the middle)
+    #[structopt(long, default_value = "full")]
+    pub topology: Topology,
+    /// Instructions a word spends on a link
+    #[structopt(long, default_value = "0")]
+    pub latency: u64,
+    /// Chance of a link losing each word, from 0 to 1
+    #[structopt(long, default_value = "0", parse(try_from_str = parse_drop_rate))]
+    pub drop_rate: f64,
+    /// Seed for deciding which words are lost
+    #[structopt(long, default_value = "0")]
+    pub seed: u64,
+    /// Address of each node's network registers
+    #[structopt(long, default_value = "xFE24", parse(try_from_str = parse_address))]
+    pub address: u16,
+    /// Interrupt vector a node's interface raises when a word arrives
+    #[structopt(long, default_value = "x84", parse(try_from_str = parse_vector))]
+    pub vector: u8,
+    /// Priority of the interface's interrupt, 1 to 7
+    #[structopt(long, default_value = "4", parse(try_from_str = parse_priority))]
+    pub priority: u8,
+    /// Abort once any node has executed this many instructions
+    #[structopt(long)]
+    pub max_instructions: Option<u64>,
+}
+
+fn parse_drop_rate(text: &str) -> Result<f64, String> {
+    match text.parse::<f64>() {
+        Ok(rate) if (0.0..=1.0).contains(&rate) => Ok(rate),
+        _ => Err(format!("Drop rate {:?} isn't between 0 and 1", text)),
+    }
+}
+
+pub struct ProtectedRegion {
+    pub addresses: AddressRange,
+    pub protection: Protection,
+}
+
+impl FromStr for ProtectedRegion {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let (addresses, protection) = text
+            .rsplit_once(':')
+            .ok_or_else(|| format!("Expected addresses:protection, got {:?}", text))?;
+
+        Ok(Self {
+            addresses: addresses.parse()?,
+            protection: protection.parse()?,
+        })
+    }
+}
+
+pub enum ReportFormat {
+    Json,
+    JUnit,
+}
+
+impl FromStr for ReportFormat {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text {
+            "json" => Ok(Self::Json),
+            "junit" => Ok(Self::JUnit),
+            other => Err(format!("Unknown report format {:?}", other)),
+        }
+    }
+}
+
+pub enum ProfileFormat {
+    Flat,
+    CallGraph,
+    Folded,
+}
+
+impl FromStr for ProfileFormat {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text {
+            "flat" => Ok(Self::Flat),
+            "call-graph" => Ok(Self::CallGraph),
+            "folded" => Ok(Self::Folded),
+            other => Err(format!("Unknown profile format {:?}", other)),
+        }
+    }
+}
+
+pub enum TraceFormat {
+    Text,
+    Json,
+}
+
+impl FromStr for TraceFormat {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            other => Err(format!("Unknown trace format {:?}", other)),
+        }
+    }
 }
 
 pub fn read_program(path: &String, little_endian: bool) -> PublicResult<Vec<u16>> {
@@ -15,6 +15,7 @@ impl Command {
         Ok(self.bit_slice(0, 3)? as u8)
     }
 
+    #[cfg(test)]
     pub(crate) fn get_bytes(&self) -> u16 {
         self.bytes
     }
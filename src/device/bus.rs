@@ -0,0 +1,143 @@
+use std::ops::RangeInclusive;
+
+use super::{Device, Display, Interrupt, Keyboard, DDR, DSR, KBDR, KBSR};
+use crate::error::{LC3Error, LC3Result};
+use crate::vm::MEMORY_SIZE;
+
+const MAX_DEVICES: usize = u8::MAX as usize;
+
+// Routes addresses to the devices that own them. Every address has an
+// entry, so finding out an address is plain memory is a single lookup.
+pub struct MemoryBus {
+    // One plus the index of the device at each address, 0 for memory
+    map: Vec<u8>,
+    // None while the device is handling an access, see VM::mem_read
+    devices: Vec<Option<Box<dyn Device>>>,
+    // Indices of the devices that want ticks
+    ticking: Vec<usize>,
+    // Indices of the devices that can raise interrupts
+    interrupting: Vec<usize>,
+    lowest_address: Option<u16>,
+}
+
+impl MemoryBus {
+    pub fn new() -> Self {
+        Self {
+            map: vec![0; MEMORY_SIZE],
+            devices: Vec::new(),
+            ticking: Vec::new(),
+            interrupting: Vec::new(),
+            lowest_address: None,
+        }
+    }
+
+    // A bus with the keyboard and display registers, which every VM
+    // starts with
+    pub fn with_console() -> Self {
+        let mut bus = Self::new();
+        bus.attach(KBSR..=KBDR, Box::new(Keyboard::new()));
+        bus.attach(DSR..=DDR, Box::new(Display::new()));
+        bus
+    }
+
+    // Map a device over a range of addresses. Ranges can't overlap.
+    pub fn add(&mut self, addresses: RangeInclusive<u16>, device: Box<dyn Device>) -> LC3Result<()> {
+        if self.devices.len() >= MAX_DEVICES {
+            return Err(LC3Error::Other(format!("A VM can't have more than {} devices", MAX_DEVICES)));
+        }
+        if let Some(address) = addresses.clone().find(|&address| self.is_mapped(address)) {
+            return Err(LC3Error::DeviceOverlap { address });
+        }
+
+        self.attach(addresses, device);
+
+        Ok(())
+    }
+
+    fn attach(&mut self, addresses: RangeInclusive<u16>, device: Box<dyn Device>) {
+        if device.ticks() {
+            self.ticking.push(self.devices.len());
+        }
+        if device.interrupts() {
+            self.interrupting.push(self.devices.len());
+        }
+        self.devices.push(Some(device));
+        let entry = self.devices.len() as u8;
+        for address in addresses.clone() {
+            self.map[address as usize] = entry;
+        }
+
+        let start = *addresses.start();
+        if !addresses.is_empty() && self.lowest_address.is_none_or(|lowest| start < lowest) {
+            self.lowest_address = Some(start);
+        }
+    }
+
+    #[inline]
+    pub fn is_mapped(&self, address: u16) -> bool {
+        self.map[address as usize] != 0
+    }
+
+    // The lowest address any device is mapped at
+    pub fn lowest_address(&self) -> Option<u16> {
+        self.lowest_address
+    }
+
+    #[inline]
+    pub(crate) fn device_at(&self, address: u16) -> Option<usize> {
+        match self.map[address as usize] {
+            0 => None,
+            index => Some(index as usize - 1),
+        }
+    }
+
+    pub(crate) fn take(&mut self, index: usize) -> LC3Result<Box<dyn Device>> {
+        self.devices[index]
+            .take()
+            .ok_or_else(|| LC3Error::Internal("A device was accessed while handling an access".to_string()))
+    }
+
+    pub(crate) fn put_back(&mut self, index: usize, device: Box<dyn Device>) {
+        self.devices[index] = Some(device);
+    }
+
+    pub fn tick(&mut self) -> LC3Result<()> {
+        for &index in &self.ticking {
+            if let Some(device) = self.devices[index].as_mut() {
+                device.tick()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    #[inline]
+    pub fn has_ticking_devices(&self) -> bool {
+        !self.ticking.is_empty()
+    }
+
+    // The highest priority interrupt any device is raising, with the
+    // priority cut to the three bits the PSR has room for
+    #[inline]
+    pub fn pending_interrupt(&self) -> Option<Interrupt> {
+        if self.interrupting.is_empty() {
+            return None;
+        }
+
+        self.interrupting
+            .iter()
+            .filter_map(|&index| self.devices[index].as_ref())
+            .filter_map(|device| device.interrupt())
+            .map(|interrupt| Interrupt {
+                priority: interrupt.priority & 0x7,
+                ..interrupt
+            })
+            .max_by_key(|interrupt| interrupt.priority)
+    }
+}
+
+impl Default for MemoryBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,172 @@
+use std::ops::RangeInclusive;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::{Device, DeviceContext};
+use crate::error::LC3Result;
+
+// Offsets of the clock's registers from its base address
+pub const RTC_SECONDS: u16 = 0;
+pub const RTC_MINUTES: u16 = 1;
+pub const RTC_HOURS: u16 = 2;
+pub const RTC_DAY: u16 = 3;
+pub const RTC_MONTH: u16 = 4;
+pub const RTC_YEAR: u16 = 5;
+const NUM_REGISTERS: u16 = 6;
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ClockSource {
+    // The host's clock
+    System,
+    // Starts at a fixed time, in seconds since the Unix epoch, and moves on
+    // a second every so many instructions, so runs are repeatable
+    Simulated { start: u64, instructions_per_second: u64 },
+}
+
+// A real-time clock giving the UTC date and time in six registers from its
+// base address: seconds, minutes, hours, day of the month, month and year.
+// Reading the seconds register latches the time, so the rest read the
+// same moment even if a second passes in between. Writes are ignored.
+pub struct RealTimeClock {
+    base: u16,
+    source: ClockSource,
+    // Seconds since the epoch as of the last latch
+    latched: u64,
+}
+
+impl RealTimeClock {
+    pub fn new(base: u16, source: ClockSource) -> Self {
+        Self {
+            base,
+            source,
+            latched: 0,
+        }
+    }
+
+    // The addresses to map the clock over
+    pub fn addresses(&self) -> RangeInclusive<u16> {
+        self.base..=self.base.saturating_add(NUM_REGISTERS - 1)
+    }
+
+    fn now(&self, context: &dyn DeviceContext) -> u64 {
+        match self.source {
+            // A clock set before 1970 reads as the epoch
+            ClockSource::System => SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_secs()),
+            ClockSource::Simulated {
+                start,
+                instructions_per_second,
+            } => start + context.instructions_executed() / instructions_per_second.max(1),
+        }
+    }
+}
+
+impl Device for RealTimeClock {
+    fn read(&mut self, address: u16, context: &mut dyn DeviceContext) -> LC3Result<u16> {
+        let offset = address.wrapping_sub(self.base);
+        if offset == RTC_SECONDS {
+            self.latched = self.now(context);
+        }
+
+        let time_of_day = self.latched % SECONDS_PER_DAY;
+        let (year, month, day) = civil_from_days(self.latched / SECONDS_PER_DAY);
+        let value = match offset {
+            RTC_SECONDS => time_of_day % 60,
+            RTC_MINUTES => time_of_day / 60 % 60,
+            RTC_HOURS => time_of_day / 3600,
+            RTC_DAY => day,
+            RTC_MONTH => month,
+            RTC_YEAR => year,
+            _ => 0,
+        };
+
+        Ok(value as u16)
+    }
+
+    fn write(&mut self, _: u16, _: u16, _: &mut dyn DeviceContext) -> LC3Result<()> {
+        Ok(())
+    }
+}
+
+// Year, month and day of a number of days since 1970-01-01, from Howard
+// Hinnant's date algorithms
+// (https://howardhinnant.github.io/date_algorithms.html)
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    // Count from 0000-03-01, so leap days fall at the end of a year
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+
+    (year, month, day)
+}
+
+// The inverse of civil_from_days
+fn days_from_civil(year: u64, month: u64, day: u64) -> u64 {
+    let year = year - u64::from(month <= 2);
+    let era = year / 400;
+    let year_of_era = year % 400;
+    let month_index = if month > 2 { month - 3 } else { month + 9 };
+    let day_of_year = (153 * month_index + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    era * 146_097 + day_of_era - 719_468
+}
+
+fn days_in_month(year: u64, month: u64) -> u64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// A start time for the simulated clock as seconds since the epoch, or a
+// UTC date such as 2024-01-31 or 2024-01-31T09:30:00
+pub fn parse_start_time(text: &str) -> Result<u64, String> {
+    if let Ok(seconds) = text.parse::<u64>() {
+        return Ok(seconds);
+    }
+
+    let invalid = || {
+        format!(
+            "Invalid start time {:?}, expected seconds or YYYY-MM-DD[THH:MM:SS]",
+            text
+        )
+    };
+    let (date, time) = match text.split_once('T') {
+        Some((date, time)) => (date, time),
+        None => (text, "00:00:00"),
+    };
+    let numbers = |text: &str, separator: char| -> Result<Vec<u64>, String> {
+        let numbers: Result<Vec<u64>, _> = text.split(separator).map(|part| part.parse::<u64>()).collect();
+        numbers.ok().filter(|numbers| numbers.len() == 3).ok_or_else(invalid)
+    };
+
+    let (date, time) = (numbers(date, '-')?, numbers(time, ':')?);
+    let (year, month, day) = (date[0], date[1], date[2]);
+    let (hours, minutes, seconds) = (time[0], time[1], time[2]);
+    if year < 1970
+        || !(1..=12).contains(&month)
+        || !(1..=days_in_month(year, month)).contains(&day)
+        || hours > 23
+        || minutes > 59
+        || seconds > 59
+    {
+        return Err(invalid());
+    }
+
+    Ok(days_from_civil(year, month, day) * SECONDS_PER_DAY + hours * 3600 + minutes * 60 + seconds)
+}
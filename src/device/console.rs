@@ -0,0 +1,89 @@
+use super::{Device, DeviceContext};
+use crate::error::LC3Result;
+
+// The console registers from the LC-3 spec
+// (https://justinmeiners.github.io/lc3-vm/supplies/lc3-isa.pdf)
+pub const KBSR: u16 = 0xFE00; // Keyboard Status Register
+pub const KBDR: u16 = 0xFE02; // Keyboard Data Register
+pub const DSR: u16 = 0xFE04; // Display Status Register
+pub const DDR: u16 = 0xFE06; // Display Data Register
+
+const READY: u16 = 1 << 15;
+
+// The keyboard registers, mapped over KBSR to KBDR. Reading the status
+// register asks the IO handle whether a key is down and, if one is, reads
+// it into the data register.
+#[derive(Default)]
+pub struct Keyboard {
+    status: u16,
+    data: u16,
+}
+
+impl Keyboard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Device for Keyboard {
+    fn read(&mut self, address: u16, context: &mut dyn DeviceContext) -> LC3Result<u16> {
+        match address {
+            KBSR => {
+                // TODO: Right now, I think there's a bug here. If the key
+                // being pressed is not a key handled by getchar()
+                // then the vm will fill the status register and pause
+                // waiting for the user to press one of those keys before
+                // actually doing anything. Not a show stopper, but one to
+                // watch.
+                if context.is_key_down()? {
+                    self.status |= READY;
+                    self.data = context.getchar()? as u16;
+                } else {
+                    self.status &= !READY;
+                }
+                Ok(self.status)
+            }
+            KBDR => Ok(self.data),
+            _ => Ok(0),
+        }
+    }
+
+    fn write(&mut self, address: u16, value: u16, _: &mut dyn DeviceContext) -> LC3Result<()> {
+        match address {
+            KBSR => self.status = value,
+            KBDR => self.data = value,
+            _ => {}
+        }
+
+        Ok(())
+    }
+}
+
+// The display registers, mapped over DSR to DDR. The display is always
+// ready, and a character written to the data register is printed straight
+// away.
+#[derive(Default)]
+pub struct Display {}
+
+impl Display {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Device for Display {
+    fn read(&mut self, address: u16, _: &mut dyn DeviceContext) -> LC3Result<u16> {
+        match address {
+            DSR => Ok(READY),
+            _ => Ok(0),
+        }
+    }
+
+    fn write(&mut self, address: u16, value: u16, context: &mut dyn DeviceContext) -> LC3Result<()> {
+        if address == DDR {
+            context.putchar(value as u8 as char)?;
+        }
+
+        Ok(())
+    }
+}
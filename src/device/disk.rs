@@ -0,0 +1,190 @@
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+use super::{Device, DeviceContext, Interrupt};
+use crate::error::{BoxErrors, LC3Result};
+
+// Offsets of the disk's registers from its base address
+pub const DISK_STATUS: u16 = 0;
+pub const DISK_COMMAND: u16 = 1;
+pub const DISK_ADDRESS: u16 = 2;
+pub const DISK_SECTOR: u16 = 3;
+const NUM_REGISTERS: u16 = 4;
+
+// Status bits. Ready is clear while a command is in progress. Done is set
+// when one finishes and cleared by writing to the status register, which
+// also sets whether interrupts are enabled. Error says the last command
+// failed.
+pub const DISK_READY: u16 = 1 << 15;
+pub const DISK_INTERRUPT_ENABLE: u16 = 1 << 14;
+pub const DISK_DONE: u16 = 1 << 13;
+pub const DISK_ERROR: u16 = 1;
+
+// Commands
+pub const DISK_READ: u16 = 1;
+pub const DISK_WRITE: u16 = 2;
+
+pub const SECTOR_WORDS: usize = 256;
+const SECTOR_BYTES: u64 = SECTOR_WORDS as u64 * 2;
+
+// A disk backed by an image file on the host, in 256-word sectors of
+// big-endian words. Writing a command moves a whole sector between the
+// image and the buffer at the address register, without the program
+// copying a word at a time, then the disk stays busy for its latency in
+// instructions before signalling it's done. Sectors past the end of the
+// image read as zeros, and writing one grows the image.
+pub struct Disk {
+    base: u16,
+    image: File,
+    latency: u64,
+    interrupt: Interrupt,
+    status: u16,
+    address: u16,
+    sector: u16,
+    // Instructions left until the command in progress is done
+    busy_for: u64,
+}
+
+impl Disk {
+    // Open the image at path, creating an empty one if it doesn't exist
+    pub fn open(base: u16, path: impl AsRef<Path>, latency: u64, interrupt: Interrupt) -> LC3Result<Self> {
+        let image = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+            .map_io_error()?;
+
+        Ok(Self {
+            base,
+            image,
+            latency,
+            interrupt,
+            status: DISK_READY,
+            address: 0,
+            sector: 0,
+            busy_for: 0,
+        })
+    }
+
+    // The addresses to map the disk over
+    pub fn addresses(&self) -> RangeInclusive<u16> {
+        self.base..=self.base.saturating_add(NUM_REGISTERS - 1)
+    }
+
+    fn seek_sector(&mut self) -> std::io::Result<()> {
+        self.image.seek(SeekFrom::Start(self.sector as u64 * SECTOR_BYTES))?;
+        Ok(())
+    }
+
+    fn read_sector(&mut self) -> std::io::Result<Vec<u16>> {
+        let mut bytes = Vec::with_capacity(SECTOR_BYTES as usize);
+        self.seek_sector()?;
+        (&mut self.image).take(SECTOR_BYTES).read_to_end(&mut bytes)?;
+        bytes.resize(SECTOR_BYTES as usize, 0);
+
+        Ok(bytes.chunks_exact(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]])).collect())
+    }
+
+    fn write_sector(&mut self, words: &[u16]) -> std::io::Result<()> {
+        let bytes: Vec<u8> = words.iter().flat_map(|word| word.to_be_bytes()).collect();
+        self.seek_sector()?;
+        self.image.write_all(&bytes)
+    }
+
+    // Move the sector, reporting a failure in the status register rather
+    // than stopping the VM. Errors from the VM's side of the transfer,
+    // such as a plugin failing, still stop it.
+    fn transfer(&mut self, command: u16, context: &mut dyn DeviceContext) -> LC3Result<bool> {
+        let buffer = self.address;
+        match command {
+            DISK_READ => {
+                let words = match self.read_sector() {
+                    Ok(words) => words,
+                    Err(_) => return Ok(false),
+                };
+                for (offset, word) in words.into_iter().enumerate() {
+                    context.dma_write(buffer.wrapping_add(offset as u16), word)?;
+                }
+                Ok(true)
+            }
+            DISK_WRITE => {
+                let mut words = Vec::with_capacity(SECTOR_WORDS);
+                for offset in 0..SECTOR_WORDS {
+                    words.push(context.dma_read(buffer.wrapping_add(offset as u16))?);
+                }
+                Ok(self.write_sector(&words).is_ok())
+            }
+            _ => Ok(false),
+        }
+    }
+
+    fn finish(&mut self) {
+        self.status |= DISK_READY | DISK_DONE;
+    }
+}
+
+impl Device for Disk {
+    fn read(&mut self, address: u16, _: &mut dyn DeviceContext) -> LC3Result<u16> {
+        let value = match address.wrapping_sub(self.base) {
+            DISK_STATUS => self.status,
+            DISK_ADDRESS => self.address,
+            DISK_SECTOR => self.sector,
+            _ => 0,
+        };
+
+        Ok(value)
+    }
+
+    fn write(&mut self, address: u16, value: u16, context: &mut dyn DeviceContext) -> LC3Result<()> {
+        match address.wrapping_sub(self.base) {
+            DISK_STATUS => {
+                self.status &= !(DISK_DONE | DISK_INTERRUPT_ENABLE);
+                self.status |= value & DISK_INTERRUPT_ENABLE;
+            }
+            // Commands are ignored while the disk is busy
+            DISK_COMMAND if self.status & DISK_READY != 0 => {
+                self.status &= !(DISK_READY | DISK_DONE | DISK_ERROR);
+                if !self.transfer(value, context)? {
+                    self.status |= DISK_ERROR;
+                }
+                self.busy_for = self.latency;
+                if self.busy_for == 0 {
+                    self.finish();
+                }
+            }
+            DISK_ADDRESS => self.address = value,
+            DISK_SECTOR => self.sector = value,
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    fn ticks(&self) -> bool {
+        self.latency > 0
+    }
+
+    fn tick(&mut self) -> LC3Result<()> {
+        if self.busy_for > 0 {
+            self.busy_for -= 1;
+            if self.busy_for == 0 {
+                self.finish();
+            }
+        }
+
+        Ok(())
+    }
+
+    fn interrupts(&self) -> bool {
+        true
+    }
+
+    fn interrupt(&self) -> Option<Interrupt> {
+        let raised = self.status & DISK_DONE != 0 && self.status & DISK_INTERRUPT_ENABLE != 0;
+        raised.then_some(self.interrupt)
+    }
+}
@@ -0,0 +1,159 @@
+use std::io::{self, Write};
+use std::path::Path;
+
+use crossterm::queue;
+use crossterm::style::{Color, Print, ResetColor, SetBackgroundColor, SetForegroundColor};
+use flate2::write::ZlibEncoder;
+use flate2::{Compression, Crc};
+
+use super::{Device, DeviceContext};
+use crate::error::{BoxErrors, LC3Error, LC3Result};
+
+// The framebuffer the popular LC-3 simulators map, one word per pixel
+// from the top left, filling xC000 to xFDFF
+pub const FRAMEBUFFER_START: u16 = 0xC000;
+pub const FRAMEBUFFER_WIDTH: usize = 128;
+pub const FRAMEBUFFER_HEIGHT: usize = 124;
+
+// Writing here asks for a frame, see FrameCapture
+pub const FRAMEBUFFER_CONTROL: u16 = 0xFE10;
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+
+// A copy of the framebuffer. Pixels are 15-bit RGB, five bits each of red,
+// green and blue from bit 14 down, and bit 15 is ignored.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pixels: Vec<u16>,
+}
+
+impl Frame {
+    // Copy the framebuffer out of memory, given a way to read a word
+    // without side effects such as VM::peek_memory
+    pub fn from_memory(memory: impl Fn(u16) -> u16) -> Self {
+        let len = FRAMEBUFFER_WIDTH * FRAMEBUFFER_HEIGHT;
+        let pixels = (0..len).map(|index| memory(FRAMEBUFFER_START + index as u16)).collect();
+        Self { pixels }
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> u16 {
+        self.pixels[y * FRAMEBUFFER_WIDTH + x]
+    }
+
+    // A pixel scaled up to 8 bits a channel
+    pub fn rgb(&self, x: usize, y: usize) -> [u8; 3] {
+        let pixel = self.pixel(x, y);
+        let channel = |shift: u16| {
+            let value = ((pixel >> shift) & 0x1F) as u8;
+            (value << 3) | (value >> 2)
+        };
+        [channel(10), channel(5), channel(0)]
+    }
+
+    fn rows(&self) -> impl Iterator<Item = impl Iterator<Item = [u8; 3]> + '_> + '_ {
+        (0..FRAMEBUFFER_HEIGHT).map(move |y| (0..FRAMEBUFFER_WIDTH).map(move |x| self.rgb(x, y)))
+    }
+
+    // Binary PPM, which almost anything can convert
+    pub fn to_ppm(&self) -> Vec<u8> {
+        let mut ppm = format!("P6\n{} {}\n255\n", FRAMEBUFFER_WIDTH, FRAMEBUFFER_HEIGHT).into_bytes();
+        ppm.extend(self.rows().flatten().flatten());
+        ppm
+    }
+
+    pub fn to_png(&self) -> Vec<u8> {
+        let mut header = Vec::new();
+        header.extend(&(FRAMEBUFFER_WIDTH as u32).to_be_bytes());
+        header.extend(&(FRAMEBUFFER_HEIGHT as u32).to_be_bytes());
+        // 8 bits a channel, RGB, then the standard compression, filter
+        // and no interlacing
+        header.extend(&[8, 2, 0, 0, 0]);
+
+        // Each row starts with its filter type, which is always none
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        let mut data = Vec::with_capacity(FRAMEBUFFER_HEIGHT * (FRAMEBUFFER_WIDTH * 3 + 1));
+        for row in self.rows() {
+            data.push(0);
+            data.extend(row.flatten());
+        }
+        // Writing to a Vec can't fail
+        encoder.write_all(&data).expect("Compressing to memory failed");
+        let data = encoder.finish().expect("Compressing to memory failed");
+
+        let mut png = PNG_SIGNATURE.to_vec();
+        png_chunk(&mut png, b"IHDR", &header);
+        png_chunk(&mut png, b"IDAT", &data);
+        png_chunk(&mut png, b"IEND", &[]);
+        png
+    }
+
+    // Write the frame as a PNG or PPM, going by the path's extension
+    pub fn save(&self, path: impl AsRef<Path>) -> LC3Result<()> {
+        let path = path.as_ref();
+        let image = match path.extension().and_then(|extension| extension.to_str()) {
+            Some("png") => self.to_png(),
+            Some("ppm") => self.to_ppm(),
+            _ => {
+                let message = format!("Can't tell the image format of {}, use .png or .ppm", path.display());
+                return Err(LC3Error::Other(message));
+            }
+        };
+        std::fs::write(path, image).map_io_error()
+    }
+
+    // Draw the frame with upper half blocks, two rows of pixels to a line
+    // of text, coloured with 24-bit terminal colours
+    pub fn write_terminal(&self, out: &mut impl Write) -> io::Result<()> {
+        let color = |[r, g, b]: [u8; 3]| Color::Rgb { r, g, b };
+        for y in (0..FRAMEBUFFER_HEIGHT).step_by(2) {
+            for x in 0..FRAMEBUFFER_WIDTH {
+                queue!(
+                    out,
+                    SetForegroundColor(color(self.rgb(x, y))),
+                    SetBackgroundColor(color(self.rgb(x, y + 1))),
+                    Print('\u{2580}')
+                )
+                .map_err(io::Error::other)?;
+            }
+            queue!(out, ResetColor, Print("\r\n")).map_err(io::Error::other)?;
+        }
+        out.flush()
+    }
+}
+
+fn png_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend(kind);
+    png.extend(data);
+
+    let mut crc = Crc::new();
+    crc.update(&png[start..]);
+    png.extend(&crc.sum().to_be_bytes());
+}
+
+pub type FrameSink = Box<dyn FnMut(&Frame) -> LC3Result<()>>;
+
+// A register a program writes to when a frame is ready, so it can be
+// saved or shown part way through a run. Each write hands a copy of the
+// framebuffer to the sink. Reads return 0.
+pub struct FrameCapture {
+    sink: FrameSink,
+}
+
+impl FrameCapture {
+    pub fn new(sink: FrameSink) -> Self {
+        Self { sink }
+    }
+}
+
+impl Device for FrameCapture {
+    fn read(&mut self, _: u16, _: &mut dyn DeviceContext) -> LC3Result<u16> {
+        Ok(0)
+    }
+
+    fn write(&mut self, _: u16, _: u16, context: &mut dyn DeviceContext) -> LC3Result<()> {
+        let frame = Frame::from_memory(|address| context.peek_memory(address));
+        (self.sink)(&frame)
+    }
+}
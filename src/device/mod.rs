@@ -0,0 +1,98 @@
+mod bus;
+mod clock;
+mod console;
+mod disk;
+mod framebuffer;
+mod random;
+mod serial;
+#[cfg(test)]
+mod test;
+mod timer;
+
+use crate::error::LC3Result;
+
+pub use bus::MemoryBus;
+pub use clock::{
+    parse_start_time, ClockSource, RealTimeClock, RTC_DAY, RTC_HOURS, RTC_MINUTES, RTC_MONTH, RTC_SECONDS, RTC_YEAR,
+};
+pub use console::{Display, Keyboard, DDR, DSR, KBDR, KBSR};
+pub use disk::{
+    Disk, DISK_ADDRESS, DISK_COMMAND, DISK_DONE, DISK_ERROR, DISK_INTERRUPT_ENABLE, DISK_READ, DISK_READY, DISK_SECTOR,
+    DISK_STATUS, DISK_WRITE, SECTOR_WORDS,
+};
+pub use framebuffer::{
+    Frame, FrameCapture, FrameSink, FRAMEBUFFER_CONTROL, FRAMEBUFFER_HEIGHT, FRAMEBUFFER_START, FRAMEBUFFER_WIDTH,
+};
+pub(crate) use random::SplitMix64;
+pub use random::RandomNumbers;
+pub use serial::{
+    Serial, SerialEndpoint, SERIAL_CLOSED, SERIAL_INTERRUPT_ENABLE, SERIAL_READY, SERIAL_RX_DATA, SERIAL_RX_STATUS,
+    SERIAL_TX_DATA, SERIAL_TX_STATUS,
+};
+pub use timer::{
+    Timer, TimerMode, TIMER_CONTROL, TIMER_COUNT, TIMER_ENABLE, TIMER_EXPIRED, TIMER_INTERRUPT_ENABLE, TIMER_RELOAD,
+    TIMER_REPEAT, TIMER_STATUS,
+};
+
+// A peripheral that owns a range of addresses. Reads and writes to those
+// addresses go to the device instead of memory, and still raise MemGet and
+// MemSet events.
+pub trait Device {
+    fn read(&mut self, address: u16, context: &mut dyn DeviceContext) -> LC3Result<u16>;
+
+    fn write(&mut self, address: u16, value: u16, context: &mut dyn DeviceContext) -> LC3Result<()>;
+
+    // Devices that return true are ticked after every instruction the VM
+    // executes. Everything else is left alone, so idle devices cost
+    // nothing between accesses.
+    fn ticks(&self) -> bool {
+        false
+    }
+
+    fn tick(&mut self) -> LC3Result<()> {
+        Ok(())
+    }
+
+    // Devices that return true are asked for an interrupt before every
+    // instruction. The rest are never asked.
+    fn interrupts(&self) -> bool {
+        false
+    }
+
+    // The interrupt the device is raising, if any
+    fn interrupt(&self) -> Option<Interrupt> {
+        None
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Interrupt {
+    // Index into the interrupt vector table at x0100
+    pub vector: u8,
+    // 0 to 7, and only the low three bits count. Interrupts are only taken
+    // above the running priority.
+    pub priority: u8,
+}
+
+// What a device can reach of the VM it's attached to while it handles an
+// access. Console IO goes through here so plugins see it and limits apply.
+pub trait DeviceContext {
+    fn getchar(&mut self) -> LC3Result<char>;
+
+    fn putchar(&mut self, ch: char) -> LC3Result<()>;
+
+    fn is_key_down(&mut self) -> LC3Result<bool>;
+
+    fn instructions_executed(&self) -> u64;
+
+    // A word of memory, read without raising an event. Device addresses
+    // read as whatever is in memory underneath them.
+    fn peek_memory(&self, address: u16) -> u16;
+
+    // Memory accesses made by the device itself, for devices that move
+    // data in and out of memory. They raise MemGet and MemSet events like
+    // the program's own accesses, but aren't subject to memory protection.
+    fn dma_read(&mut self, address: u16) -> LC3Result<u16>;
+
+    fn dma_write(&mut self, address: u16, value: u16) -> LC3Result<()>;
+}
@@ -0,0 +1,63 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::{Device, DeviceContext};
+use crate::error::LC3Result;
+
+// A register that returns a new random word on every read. Writing a word
+// reseeds it with that word, so a program can replay a sequence.
+pub struct RandomNumbers {
+    generator: SplitMix64,
+}
+
+impl RandomNumbers {
+    // The same seed gives the same words, so runs are repeatable. Without
+    // one the seed comes from the host's clock.
+    pub fn new(seed: Option<u64>) -> Self {
+        let seed = seed.unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_nanos() as u64)
+        });
+
+        Self {
+            generator: SplitMix64::new(seed),
+        }
+    }
+}
+
+impl Device for RandomNumbers {
+    fn read(&mut self, _: u16, _: &mut dyn DeviceContext) -> LC3Result<u16> {
+        // The top bits are the best mixed
+        Ok((self.generator.next() >> 48) as u16)
+    }
+
+    fn write(&mut self, _: u16, value: u16, _: &mut dyn DeviceContext) -> LC3Result<()> {
+        self.generator = SplitMix64::new(value as u64);
+        Ok(())
+    }
+}
+
+// splitmix64, which is quick and plenty random for games and simulations
+// but no good for anything secret
+pub(crate) struct SplitMix64 {
+    state: u64,
+}
+
+impl SplitMix64 {
+    pub(crate) fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub(crate) fn next(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut value = self.state;
+        value = (value ^ (value >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        value = (value ^ (value >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        value ^ (value >> 31)
+    }
+
+    // A number from 0 up to but not including 1
+    pub(crate) fn next_fraction(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
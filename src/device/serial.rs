@@ -0,0 +1,263 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::ops::RangeInclusive;
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+use super::{Device, DeviceContext, Interrupt};
+use crate::error::{BoxErrors, LC3Result};
+
+// Offsets of the serial port's registers from its base address
+pub const SERIAL_RX_STATUS: u16 = 0;
+pub const SERIAL_RX_DATA: u16 = 1;
+pub const SERIAL_TX_STATUS: u16 = 2;
+pub const SERIAL_TX_DATA: u16 = 3;
+const NUM_REGISTERS: u16 = 4;
+
+// Status bits. Ready is set in the receive status while a byte is waiting
+// in the data register, and is always set in the transmit status. Writing
+// to the receive status sets whether the port interrupts when a byte
+// arrives. Closed is set once the other end has hung up.
+pub const SERIAL_READY: u16 = 1 << 15;
+pub const SERIAL_INTERRUPT_ENABLE: u16 = 1 << 14;
+pub const SERIAL_CLOSED: u16 = 1;
+
+// Where the other end of the serial link is
+#[derive(Debug, Clone, PartialEq)]
+pub enum SerialEndpoint {
+    // Connect to a listening TCP socket, such as 127.0.0.1:9000
+    Tcp(String),
+    // Listen on a loopback TCP port and wait for one connection
+    TcpListen(u16),
+    // Connect to a listening Unix domain socket
+    Unix(PathBuf),
+    // Create a Unix domain socket and wait for one connection
+    UnixListen(PathBuf),
+    // Receive from one named pipe and send to another
+    Pipe { receive: PathBuf, send: PathBuf },
+}
+
+impl FromStr for SerialEndpoint {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "Invalid serial endpoint {:?}, expected tcp:HOST:PORT, tcp-listen:PORT, unix:PATH, \
+                 unix-listen:PATH or pipe:RECEIVE,SEND",
+                text
+            )
+        };
+        let (kind, rest) = text.split_once(':').ok_or_else(invalid)?;
+        match kind {
+            "tcp" => Ok(SerialEndpoint::Tcp(rest.to_string())),
+            "tcp-listen" => rest.parse().map(SerialEndpoint::TcpListen).map_err(|_| invalid()),
+            "unix" => Ok(SerialEndpoint::Unix(rest.into())),
+            "unix-listen" => Ok(SerialEndpoint::UnixListen(rest.into())),
+            "pipe" => {
+                let (receive, send) = rest.split_once(',').ok_or_else(invalid)?;
+                Ok(SerialEndpoint::Pipe {
+                    receive: receive.into(),
+                    send: send.into(),
+                })
+            }
+            _ => Err(invalid()),
+        }
+    }
+}
+
+type Link = (Box<dyn Read + Send>, Box<dyn Write>);
+
+impl SerialEndpoint {
+    // Connect, waiting for the other end when listening
+    fn connect(&self) -> io::Result<Link> {
+        match self {
+            SerialEndpoint::Tcp(address) => {
+                let stream = TcpStream::connect(address)?;
+                Ok((Box::new(stream.try_clone()?), Box::new(stream)))
+            }
+            SerialEndpoint::TcpListen(port) => {
+                let (stream, _) = TcpListener::bind(("127.0.0.1", *port))?.accept()?;
+                Ok((Box::new(stream.try_clone()?), Box::new(stream)))
+            }
+            #[cfg(unix)]
+            SerialEndpoint::Unix(path) => {
+                let stream = UnixStream::connect(path)?;
+                Ok((Box::new(stream.try_clone()?), Box::new(stream)))
+            }
+            #[cfg(unix)]
+            SerialEndpoint::UnixListen(path) => {
+                let (stream, _) = UnixListener::bind(path)?.accept()?;
+                // Nothing else can connect, so the socket's file is no use
+                let _ = std::fs::remove_file(path);
+                Ok((Box::new(stream.try_clone()?), Box::new(stream)))
+            }
+            #[cfg(not(unix))]
+            SerialEndpoint::Unix(_) | SerialEndpoint::UnixListen(_) => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "Unix domain sockets aren't supported on this platform",
+            )),
+            // Opening a pipe waits for the other end to open it too, so the
+            // receiving end is opened on the reading thread. Otherwise two
+            // VMs that both open their receiving pipe first would wait on
+            // each other forever.
+            SerialEndpoint::Pipe { receive, send } => {
+                let receive = LazyPipe {
+                    path: receive.clone(),
+                    file: None,
+                };
+                let send = OpenOptions::new().write(true).open(send)?;
+                Ok((Box::new(receive), Box::new(send)))
+            }
+        }
+    }
+}
+
+// A named pipe opened for reading on the first read
+struct LazyPipe {
+    path: PathBuf,
+    file: Option<File>,
+}
+
+impl Read for LazyPipe {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        match &mut self.file {
+            Some(file) => file.read(buffer),
+            None => self.file.insert(File::open(&self.path)?).read(buffer),
+        }
+    }
+}
+
+// A UART with a one-byte receive register, bridged to a socket or pipe on
+// the host. Bytes are received on a thread of their own and picked up
+// between instructions, so the program never waits on the host unless it
+// polls for a byte. Bytes written to the transmit register are sent
+// straight away.
+pub struct Serial {
+    base: u16,
+    interrupt: Interrupt,
+    incoming: Receiver<u8>,
+    outgoing: Box<dyn Write>,
+    status: u16,
+    data: u16,
+}
+
+impl Serial {
+    // Connect to the endpoint, which waits for the other end to connect
+    // when it's listening
+    pub fn open(base: u16, endpoint: &SerialEndpoint, interrupt: Interrupt) -> LC3Result<Self> {
+        let (receive, send) = endpoint.connect().map_io_error()?;
+        Ok(Self::new(base, receive, send, interrupt))
+    }
+
+    pub fn new(base: u16, mut receive: Box<dyn Read + Send>, send: Box<dyn Write>, interrupt: Interrupt) -> Self {
+        let (sender, incoming) = mpsc::channel();
+        // The thread stops when the other end hangs up, or at the next byte
+        // after the port is dropped
+        thread::spawn(move || {
+            let mut buffer = [0; 256];
+            loop {
+                let len = match receive.read(&mut buffer) {
+                    Ok(0) | Err(_) => break,
+                    Ok(len) => len,
+                };
+                if buffer[..len].iter().any(|&byte| sender.send(byte).is_err()) {
+                    break;
+                }
+            }
+        });
+
+        Self {
+            base,
+            interrupt,
+            incoming,
+            outgoing: send,
+            status: 0,
+            data: 0,
+        }
+    }
+
+    // The addresses to map the serial port over
+    pub fn addresses(&self) -> RangeInclusive<u16> {
+        self.base..=self.base.saturating_add(NUM_REGISTERS - 1)
+    }
+
+    // Move the next byte into the data register if it's free
+    fn receive(&mut self) {
+        if self.status & SERIAL_READY != 0 {
+            return;
+        }
+        match self.incoming.try_recv() {
+            Ok(byte) => {
+                self.data = byte as u16;
+                self.status |= SERIAL_READY;
+            }
+            Err(TryRecvError::Disconnected) => self.status |= SERIAL_CLOSED,
+            Err(TryRecvError::Empty) => {}
+        }
+    }
+
+    fn send(&mut self, byte: u8) {
+        let sent = self.outgoing.write_all(&[byte]).and_then(|_| self.outgoing.flush());
+        if sent.is_err() {
+            self.status |= SERIAL_CLOSED;
+        }
+    }
+}
+
+impl Device for Serial {
+    fn read(&mut self, address: u16, _: &mut dyn DeviceContext) -> LC3Result<u16> {
+        let value = match address.wrapping_sub(self.base) {
+            SERIAL_RX_STATUS => {
+                self.receive();
+                self.status
+            }
+            SERIAL_RX_DATA => {
+                self.status &= !SERIAL_READY;
+                self.data
+            }
+            SERIAL_TX_STATUS => SERIAL_READY | (self.status & SERIAL_CLOSED),
+            _ => 0,
+        };
+
+        Ok(value)
+    }
+
+    fn write(&mut self, address: u16, value: u16, _: &mut dyn DeviceContext) -> LC3Result<()> {
+        match address.wrapping_sub(self.base) {
+            SERIAL_RX_STATUS => {
+                self.status &= !SERIAL_INTERRUPT_ENABLE;
+                self.status |= value & SERIAL_INTERRUPT_ENABLE;
+            }
+            SERIAL_TX_DATA => self.send(value as u8),
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    // Ticking picks up bytes as they arrive, so the interrupt is raised
+    // without the program polling
+    fn ticks(&self) -> bool {
+        true
+    }
+
+    fn tick(&mut self) -> LC3Result<()> {
+        self.receive();
+        Ok(())
+    }
+
+    fn interrupts(&self) -> bool {
+        true
+    }
+
+    fn interrupt(&self) -> Option<Interrupt> {
+        let raised = self.status & SERIAL_READY != 0 && self.status & SERIAL_INTERRUPT_ENABLE != 0;
+        raised.then_some(self.interrupt)
+    }
+}
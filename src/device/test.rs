@@ -0,0 +1,392 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use std::io::{Cursor, Read, Write};
+
+use flate2::read::ZlibDecoder;
+
+use super::{
+    parse_start_time, ClockSource, Device, DeviceContext, Disk, Frame, FrameCapture, Interrupt, MemoryBus, RandomNumbers,
+    RealTimeClock, Serial, SerialEndpoint, Timer, TimerMode, DDR, DISK_DONE, DISK_READY, FRAMEBUFFER_CONTROL, FRAMEBUFFER_HEIGHT,
+    FRAMEBUFFER_WIDTH, KBSR,
+};
+use crate::error::{BoxErrors, LC3Error, LC3Result};
+use crate::io::ScriptedIOHandle;
+use crate::vm::VM;
+
+// Remembers the last word written to it and counts ticks. Reads return the
+// word plus the address's offset into the device.
+#[derive(Default)]
+struct Latch {
+    value: u16,
+    ticks: Rc<RefCell<u64>>,
+    interrupt: Option<Interrupt>,
+}
+
+impl Device for Latch {
+    fn read(&mut self, address: u16, _: &mut dyn DeviceContext) -> LC3Result<u16> {
+        Ok(self.value + (address & 0xF))
+    }
+
+    fn write(&mut self, _: u16, value: u16, _: &mut dyn DeviceContext) -> LC3Result<()> {
+        self.value = value;
+        Ok(())
+    }
+
+    fn ticks(&self) -> bool {
+        true
+    }
+
+    fn tick(&mut self) -> LC3Result<()> {
+        *self.ticks.borrow_mut() += 1;
+        Ok(())
+    }
+
+    fn interrupts(&self) -> bool {
+        true
+    }
+
+    fn interrupt(&self) -> Option<Interrupt> {
+        self.interrupt
+    }
+}
+
+#[test]
+fn can_route_accesses_to_devices() -> LC3Result<()> {
+    let program = vec![
+        // LD R0, SEVEN then STI R0, LATCH
+        0b0010_0000_0000_0100,
+        0b1011_0000_0000_0101,
+        // LDI R1, LATCH2 then ST R1, OUT
+        0b1010_0010_0000_0101,
+        0b0011_0010_0000_0010,
+        // HALT
+        0xF025,
+        // SEVEN
+        7,
+        // OUT
+        0,
+        // LATCH and LATCH2
+        0x5000,
+        0x5002,
+    ];
+
+    let latch = Latch::default();
+    let ticks = latch.ticks.clone();
+    let mut vm = VM::new_with_io(ScriptedIOHandle::new());
+    vm.add_device(0x5000..=0x500F, Box::new(latch))?;
+    vm.load_program(&program)?;
+    vm.run()?;
+
+    assert_eq!(vm.mem_read(0x3006)?, 9);
+    assert_eq!(vm.peek_memory(0x5000), 0);
+    assert_eq!(*ticks.borrow(), 5);
+
+    Ok(())
+}
+
+#[test]
+fn can_write_to_display() -> LC3Result<()> {
+    let program = vec![
+        // POLL: LDI R1, DSR then BRzp POLL
+        0b1010_0010_0000_0100,
+        0b0000_0111_1111_1110,
+        // LD R0, CHAR then STI R0, DDR
+        0b0010_0000_0000_0100,
+        0b1011_0000_0000_0010,
+        // HALT
+        0xF025,
+        // DSR, DDR and CHAR
+        0xFE04,
+        DDR,
+        'A' as u16,
+    ];
+
+    let mut vm = VM::new_with_io(ScriptedIOHandle::new());
+    vm.load_program(&program)?;
+    vm.run()?;
+    assert_eq!(vm.into_io_handle().into_output(), "A");
+
+    Ok(())
+}
+
+#[test]
+fn can_reject_overlapping_devices() {
+    let mut vm = VM::new_with_io(ScriptedIOHandle::new());
+    let result = vm.add_device(0xFDF0..=KBSR, Box::new(Latch::default()));
+
+    assert!(matches!(result, Err(LC3Error::DeviceOverlap { address: KBSR })));
+}
+
+#[test]
+fn can_pick_highest_priority_interrupt() -> LC3Result<()> {
+    let mut bus = MemoryBus::new();
+    assert_eq!(bus.pending_interrupt(), None);
+
+    // 9 is 1 once it's cut down to the three bits of a priority
+    for (address, priority) in [(0x5000, 2), (0x5001, 6), (0x5002, 4), (0x5003, 9)] {
+        let interrupt = Some(Interrupt { vector: address as u8, priority });
+        bus.add(address..=address, Box::new(Latch { interrupt, ..Latch::default() }))?;
+    }
+    assert_eq!(bus.pending_interrupt(), Some(Interrupt { vector: 0x01, priority: 6 }));
+    assert_eq!(bus.lowest_address(), Some(0x5000));
+
+    Ok(())
+}
+
+#[test]
+fn can_take_timer_interrupts() -> LC3Result<()> {
+    let program = vec![
+        // LEA R0, HANDLER then STI R0, VECTOR
+        0xE009, 0xB00D,
+        // Reload every 20 instructions, then enable with interrupts and
+        // repeat
+        0x200D, 0xB00D, 0x200D, 0xB00D,
+        // LOOP: LD R2, TICKS then ADD R2, R2, #-3 and BRn LOOP
+        0x240D, 0x14BD, 0x09FD,
+        // HALT
+        0xF025,
+        // HANDLER: count the tick in TICKS, clear the status and RTI
+        0x2609, 0x16E1, 0x3607, 0xB607, 0x8000,
+        // VECTOR, the reload value and address, control value and
+        // address, TICKS and the status address
+        0x0181, 20, 0xFE0A, 0xE000, 0xFE09, 0, 0xFE08,
+    ];
+
+    let timer = Timer::new(0xFE08, TimerMode::Instructions, Interrupt { vector: 0x81, priority: 4 });
+    let mut vm = VM::new_with_io(ScriptedIOHandle::new());
+    vm.add_device(timer.addresses(), Box::new(timer))?;
+    vm.load_program(&program)?;
+    vm.run()?;
+
+    assert_eq!(vm.peek_memory(0x3014), 3);
+    // The PSR pushed on the supervisor stack came from user mode, and R6
+    // is back to the user stack pointer
+    assert_eq!(vm.peek_memory(0x2FFF) & 0x8000, 0x8000);
+    assert_eq!(vm.reg_index_read(6)?, 0);
+
+    Ok(())
+}
+
+#[test]
+fn can_reject_rti_outside_interrupts() {
+    let mut vm = VM::new_with_io(ScriptedIOHandle::new());
+    let result = vm.load_program(&[0x8000]).and_then(|_| vm.run());
+
+    assert!(matches!(result, Err(LC3Error::PrivilegeViolation { pc: 0x3000 })));
+}
+
+#[test]
+fn can_capture_framebuffer() -> LC3Result<()> {
+    let program = vec![
+        // LD R0, RED then LD R1, FRAMEBUFFER
+        0x2004, 0x2204,
+        // STR R0, R1, #1 colours the second pixel, then STI R0, CONTROL
+        0x7041, 0xB003,
+        // HALT
+        0xF025,
+        // RED, FRAMEBUFFER and CONTROL
+        0x7C00, 0xC000, FRAMEBUFFER_CONTROL,
+    ];
+
+    let frames = Rc::new(RefCell::new(Vec::new()));
+    let sink = frames.clone();
+    let capture = FrameCapture::new(Box::new(move |frame: &Frame| {
+        sink.borrow_mut().push(frame.clone());
+        Ok(())
+    }));
+    let mut vm = VM::new_with_io(ScriptedIOHandle::new());
+    vm.add_device(FRAMEBUFFER_CONTROL..=FRAMEBUFFER_CONTROL, Box::new(capture))?;
+    vm.load_program(&program)?;
+    vm.run()?;
+
+    let frames = frames.borrow();
+    assert_eq!(frames.len(), 1);
+    let frame = &frames[0];
+    assert_eq!(frame.rgb(0, 0), [0, 0, 0]);
+    assert_eq!(frame.rgb(1, 0), [255, 0, 0]);
+    assert_eq!(*frame, Frame::from_memory(|address| vm.peek_memory(address)));
+
+    let ppm = frame.to_ppm();
+    let header = b"P6\n128 124\n255\n";
+    assert_eq!(&ppm[..header.len()], header);
+    assert_eq!(&ppm[header.len() + 3..header.len() + 6], [255, 0, 0]);
+
+    // The PNG's one IDAT chunk starts after the signature and IHDR
+    let png = frame.to_png();
+    assert_eq!(&png[12..16], b"IHDR");
+    assert_eq!(&png[16..24], [0, 0, 0, 128, 0, 0, 0, 124]);
+    let idat_len = u32::from_be_bytes([png[33], png[34], png[35], png[36]]) as usize;
+    assert_eq!(&png[37..41], b"IDAT");
+    let mut data = Vec::new();
+    ZlibDecoder::new(&png[41..41 + idat_len]).read_to_end(&mut data).unwrap();
+    assert_eq!(data.len(), FRAMEBUFFER_HEIGHT * (FRAMEBUFFER_WIDTH * 3 + 1));
+    assert_eq!(&data[..7], [0, 0, 0, 0, 255, 0, 0]);
+
+    let mut terminal = Vec::new();
+    frame.write_terminal(&mut terminal).unwrap();
+    let blocks = String::from_utf8(terminal).unwrap().matches('\u{2580}').count();
+    assert_eq!(blocks, FRAMEBUFFER_WIDTH * FRAMEBUFFER_HEIGHT / 2);
+
+    Ok(())
+}
+
+#[test]
+fn can_move_sectors_between_disk_and_memory() -> LC3Result<()> {
+    let program = vec![
+        // LD R0, BUFFER then STI R0, ADDRESS
+        0x200D, 0xB00D,
+        // Read sector 1: set R0 to 1 then STI R0, SECTOR and STI R0, COMMAND
+        0x5020, 0x1021, 0xB00B, 0xB00B,
+        // POLL: LDI R1, STATUS then BRzp POLL
+        0xA20B, 0x07FE,
+        // Write it to sector 2: ADD R0, R0, #1 then the same stores and poll
+        0x1021, 0xB006, 0xB006, 0xA206, 0x07FE,
+        // HALT
+        0xF025,
+        // BUFFER then the address, sector, command and status registers
+        0x4000, 0xFE16, 0xFE17, 0xFE15, 0xFE14,
+    ];
+
+    let path = std::env::temp_dir().join(format!("lc3rs-disk-{}.img", std::process::id()));
+    let mut image = vec![0u8; 512];
+    image.extend((1..=256u16).flat_map(|word| word.to_be_bytes()));
+    std::fs::write(&path, &image).map_io_error()?;
+
+    let disk = Disk::open(0xFE14, &path, 10, Interrupt { vector: 0x82, priority: 3 })?;
+    let mut vm = VM::new_with_io(ScriptedIOHandle::new());
+    vm.add_device(disk.addresses(), Box::new(disk))?;
+    vm.load_program(&program)?;
+    vm.run()?;
+    let status = vm.mem_read(0xFE14)?;
+    let buffer = (vm.peek_memory(0x4000), vm.peek_memory(0x40FF));
+    drop(vm);
+    let written = std::fs::read(&path).map_io_error()?;
+    std::fs::remove_file(&path).map_io_error()?;
+
+    assert_eq!(status, DISK_READY | DISK_DONE);
+    assert_eq!(buffer, (1, 256));
+    assert_eq!(written.len(), 3 * 512);
+    assert_eq!(written[1024..], image[512..]);
+
+    Ok(())
+}
+
+// Reads the clock's seconds and month, then its seconds, day and month
+// again, then a random word, into R0 to R5
+fn read_clock_and_random(start: &str, seed: u64) -> LC3Result<Vec<u16>> {
+    let program = vec![
+        // LDI R0, SECONDS then LDI R1, MONTH
+        0xA006, 0xA206,
+        // LDI R2, SECONDS then LDI R3, DAY and LDI R4, MONTH
+        0xA404, 0xA605, 0xA803,
+        // LDI R5, RANDOM
+        0xAA04,
+        // HALT
+        0xF025,
+        // SECONDS, MONTH, DAY and RANDOM
+        0xFE18, 0xFE1C, 0xFE1B, 0xFE1E,
+    ];
+
+    // A second passes every two instructions
+    let source = ClockSource::Simulated {
+        start: parse_start_time(start).map_err(LC3Error::Other)?,
+        instructions_per_second: 2,
+    };
+    let clock = RealTimeClock::new(0xFE18, source);
+    let mut vm = VM::new_with_io(ScriptedIOHandle::new());
+    vm.add_device(clock.addresses(), Box::new(clock))?;
+    vm.add_device(0xFE1E..=0xFE1E, Box::new(RandomNumbers::new(Some(seed))))?;
+    vm.load_program(&program)?;
+    vm.run()?;
+
+    (0..6).map(|index| vm.reg_index_read(index)).collect()
+}
+
+#[test]
+fn can_read_deterministic_clock_and_random_numbers() -> LC3Result<()> {
+    let first = read_clock_and_random("2000-02-29T23:59:59", 42)?;
+    assert_eq!(first[..5], [59, 2, 0, 1, 3]);
+
+    assert_eq!(read_clock_and_random("2000-02-29T23:59:59", 42)?, first);
+    assert_ne!(read_clock_and_random("2000-02-29T23:59:59", 43)?[5], first[5]);
+
+    Ok(())
+}
+
+#[test]
+fn can_parse_clock_start_times() {
+    assert_eq!(parse_start_time("12345"), Ok(12345));
+    assert_eq!(parse_start_time("1970-01-02T01:02:03"), Ok(86_400 + 3723));
+    // 2000 was a leap year
+    assert_eq!(parse_start_time("2000-03-01"), Ok(parse_start_time("2000-02-29").unwrap() + 86_400));
+    assert!(parse_start_time("2000-13-01").is_err());
+    assert!(parse_start_time("2024-02-31").is_err());
+    assert!(parse_start_time("2023-02-29").is_err());
+    assert!(parse_start_time("2024-04-31").is_err());
+    assert!(parse_start_time("2000-01-01T24:00:00").is_err());
+}
+
+// Collects what a serial port sends
+#[derive(Clone, Default)]
+struct Sent(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Sent {
+    fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().extend(bytes);
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn can_echo_serial_bytes_from_interrupt_handler() -> LC3Result<()> {
+    let program = vec![
+        // LEA R0, HANDLER then STI R0, VECTOR
+        0xE007, 0xB00A,
+        // LD R0, ENABLE then STI R0, RX_STATUS
+        0x200A, 0xB00A,
+        // LOOP: LDI R1, RX_STATUS then AND R1, R1, #1 and BRz LOOP until
+        // the other end hangs up
+        0xA209, 0x5261, 0x05FD,
+        // HALT
+        0xF025,
+        // HANDLER: LDI R0, RX_DATA then ADD R0, R0, #1, STI R0, TX_DATA
+        // and RTI
+        0xA006, 0x1021, 0xB005, 0x8000,
+        // VECTOR, ENABLE and the receive status, receive data and
+        // transmit data registers
+        0x0183, 0x4000, 0xFE20, 0xFE21, 0xFE23,
+    ];
+
+    let sent = Sent::default();
+    let receive = Box::new(Cursor::new(b"HAL".to_vec()));
+    let serial = Serial::new(0xFE20, receive, Box::new(sent.clone()), Interrupt { vector: 0x83, priority: 4 });
+    let mut vm = VM::new_with_io(ScriptedIOHandle::new());
+    vm.add_device(serial.addresses(), Box::new(serial))?;
+    vm.load_program(&program)?;
+    vm.run()?;
+
+    assert_eq!(&*sent.0.borrow(), b"IBM");
+
+    Ok(())
+}
+
+#[test]
+fn can_parse_serial_endpoints() {
+    assert_eq!("tcp:127.0.0.1:9000".parse(), Ok(SerialEndpoint::Tcp("127.0.0.1:9000".to_string())));
+    assert_eq!("tcp-listen:9000".parse(), Ok(SerialEndpoint::TcpListen(9000)));
+    assert_eq!("unix-listen:/tmp/lc3.sock".parse(), Ok(SerialEndpoint::UnixListen("/tmp/lc3.sock".into())));
+    assert_eq!(
+        "pipe:in,out".parse(),
+        Ok(SerialEndpoint::Pipe {
+            receive: "in".into(),
+            send: "out".into()
+        })
+    );
+    assert!("tcp-listen:port".parse::<SerialEndpoint>().is_err());
+    assert!("serial".parse::<SerialEndpoint>().is_err());
+}
@@ -0,0 +1,175 @@
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use super::{Device, DeviceContext, Interrupt};
+use crate::error::LC3Result;
+
+// Offsets of the timer's registers from its base address
+pub const TIMER_STATUS: u16 = 0;
+pub const TIMER_CONTROL: u16 = 1;
+pub const TIMER_RELOAD: u16 = 2;
+pub const TIMER_COUNT: u16 = 3;
+const NUM_REGISTERS: u16 = 4;
+
+// Status bit, set when the count reaches zero and cleared by writing to
+// the status register
+pub const TIMER_EXPIRED: u16 = 1 << 15;
+
+// Control bits
+pub const TIMER_ENABLE: u16 = 1 << 15;
+pub const TIMER_INTERRUPT_ENABLE: u16 = 1 << 14;
+// Start counting down from the reload value again on expiry, instead of
+// stopping
+pub const TIMER_REPEAT: u16 = 1 << 13;
+
+// Looking at the clock on every instruction would slow the VM down, so in
+// Milliseconds mode it's only read this often
+const CLOCK_CHECK_INTERVAL: u32 = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimerMode {
+    // Count instructions executed, so runs are repeatable
+    Instructions,
+    // Count wall-clock milliseconds
+    Milliseconds,
+}
+
+impl FromStr for TimerMode {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text {
+            "instructions" => Ok(Self::Instructions),
+            "ms" => Ok(Self::Milliseconds),
+            other => Err(format!("Unknown timer mode {:?}", other)),
+        }
+    }
+}
+
+// A programmable interval timer with four registers from its base
+// address: status, control, reload and count. Writing the reload value
+// starts a new period, and while enabled the count goes down once per
+// instruction or millisecond. When it reaches zero the timer expires,
+// raising its interrupt until the status register is written if
+// interrupts are enabled.
+pub struct Timer {
+    base: u16,
+    mode: TimerMode,
+    interrupt: Interrupt,
+    status: u16,
+    control: u16,
+    reload: u16,
+    count: u16,
+    // Milliseconds mode only
+    last_checked: Instant,
+    ticks_since_check: u32,
+}
+
+impl Timer {
+    pub fn new(base: u16, mode: TimerMode, interrupt: Interrupt) -> Self {
+        Self {
+            base,
+            mode,
+            interrupt,
+            status: 0,
+            control: 0,
+            reload: 0,
+            count: 0,
+            last_checked: Instant::now(),
+            ticks_since_check: 0,
+        }
+    }
+
+    // The addresses to map the timer over
+    pub fn addresses(&self) -> RangeInclusive<u16> {
+        self.base..=self.base.saturating_add(NUM_REGISTERS - 1)
+    }
+
+    fn count_down(&mut self, elapsed: u16) {
+        if self.control & TIMER_ENABLE == 0 || self.count == 0 {
+            return;
+        }
+
+        self.count = self.count.saturating_sub(elapsed);
+        if self.count == 0 {
+            self.status |= TIMER_EXPIRED;
+            if self.control & TIMER_REPEAT != 0 {
+                self.count = self.reload;
+            } else {
+                self.control &= !TIMER_ENABLE;
+            }
+        }
+    }
+}
+
+impl Device for Timer {
+    fn read(&mut self, address: u16, _: &mut dyn DeviceContext) -> LC3Result<u16> {
+        let value = match address.wrapping_sub(self.base) {
+            TIMER_STATUS => self.status,
+            TIMER_CONTROL => self.control,
+            TIMER_RELOAD => self.reload,
+            TIMER_COUNT => self.count,
+            _ => 0,
+        };
+
+        Ok(value)
+    }
+
+    fn write(&mut self, address: u16, value: u16, _: &mut dyn DeviceContext) -> LC3Result<()> {
+        match address.wrapping_sub(self.base) {
+            TIMER_STATUS => self.status &= !TIMER_EXPIRED,
+            TIMER_CONTROL => {
+                // Milliseconds are counted from when the timer's enabled
+                if value & TIMER_ENABLE != 0 && self.control & TIMER_ENABLE == 0 {
+                    self.last_checked = Instant::now();
+                }
+                self.control = value;
+            }
+            TIMER_RELOAD => {
+                self.reload = value;
+                self.count = value;
+            }
+            TIMER_COUNT => self.count = value,
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    fn ticks(&self) -> bool {
+        true
+    }
+
+    fn tick(&mut self) -> LC3Result<()> {
+        match self.mode {
+            TimerMode::Instructions => self.count_down(1),
+            TimerMode::Milliseconds => {
+                self.ticks_since_check += 1;
+                if self.ticks_since_check < CLOCK_CHECK_INTERVAL {
+                    return Ok(());
+                }
+                self.ticks_since_check = 0;
+
+                // Whole milliseconds only, carrying the rest over to the
+                // next check
+                let elapsed = self.last_checked.elapsed().as_millis().min(u16::MAX as u128) as u16;
+                if elapsed > 0 {
+                    self.last_checked += Duration::from_millis(elapsed as u64);
+                    self.count_down(elapsed);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn interrupts(&self) -> bool {
+        true
+    }
+
+    fn interrupt(&self) -> Option<Interrupt> {
+        let raised = self.status & TIMER_EXPIRED != 0 && self.control & TIMER_INTERRUPT_ENABLE != 0;
+        raised.then_some(self.interrupt)
+    }
+}
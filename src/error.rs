@@ -1,8 +1,11 @@
 use std::error::Error as StdError;
 use std::result::Result as StdResult;
+use std::time::Duration;
 
 use thiserror::Error;
 
+use crate::protection::Access;
+
 pub type LC3Result<T> = StdResult<T, LC3Error>;
 
 pub(crate) type BoxedError = Box<dyn StdError + 'static>;
@@ -25,12 +28,63 @@ pub enum LC3Error {
     BadOpCode { code: u8 },
     #[error("Bad trap code {code} encountered during command parsing")]
     BadTrapCode { code: u8 },
+    #[error("Scripted input was exhausted")]
+    InputExhausted,
+    #[error("Interrupted by Ctrl-C")]
+    Interrupted,
+    #[error("{reason}\n{transcript}")]
+    Expect { reason: String, transcript: String },
+    #[error("Invalid test spec: {0}")]
+    Spec(String),
+    #[error("Instruction limit of {limit} reached at PC x{pc:04X}")]
+    InstructionLimit { limit: u64, pc: u16 },
+    #[error("Time limit of {limit:?} reached at PC x{pc:04X}")]
+    TimeLimit { limit: Duration, pc: u16 },
+    #[error("Output limit of {limit} characters reached at PC x{pc:04X}")]
+    OutputLimit { limit: u64, pc: u16 },
     #[error("Program length {len} exceeds maximum allowed size {max_len}")]
     ProgramSize { len: usize, max_len: usize },
+    #[error("Invalid line map: {0}")]
+    LineMap(String),
+    #[error("Can't translate program: {0}")]
+    Translate(String),
+    #[error("Jumped to x{target:04X}, which wasn't translated")]
+    UntranslatedJump { target: u16 },
+    #[error("Write to x{address:04X} would modify the program's own code")]
+    SelfModifyingCode { address: u16 },
+    #[error("{access} of x{address:04X} at PC x{pc:04X} violates memory protection")]
+    AccessViolation { pc: u16, address: u16, access: Access },
+    #[error("RTI at PC x{pc:04X} outside an interrupt handler")]
+    PrivilegeViolation { pc: u16 },
+    #[error("Device overlaps another device at x{address:04X}")]
+    DeviceOverlap { address: u16 },
+    #[error("Node {index}: {source}")]
+    Node {
+        index: usize,
+        #[source]
+        source: Box<LC3Error>,
+    },
+    #[error("Invalid trace: {0}")]
+    Trace(String),
+    // Only raised with the jit feature, but always defined so that
+    // enabling the feature doesn't change the enum for other crates
+    #[error("JIT compilation failed: {0}")]
+    Jit(String),
     #[error("Encountered the following error: {0}")]
     Other(String),
 }
 
+impl LC3Error {
+    // True for the errors raised when a run exceeds one of its Limits
+    pub fn is_limit(&self) -> bool {
+        match self {
+            LC3Error::InstructionLimit { .. } | LC3Error::TimeLimit { .. } | LC3Error::OutputLimit { .. } => true,
+            LC3Error::Node { source, .. } => source.is_limit(),
+            _ => false,
+        }
+    }
+}
+
 fn to_boxed_error<ErrType>(err: ErrType) -> BoxedError
 where
     ErrType: std::error::Error + 'static,
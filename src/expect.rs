@@ -0,0 +1,325 @@
+use regex::Regex;
+
+use crate::error::{LC3Error, LC3Result};
+use crate::io::ScriptedIOHandle;
+use crate::vm::VM;
+
+// Number of instructions each expectation may run before giving up.
+const DEFAULT_TIMEOUT: u64 = 1_000_000;
+
+enum Pattern {
+    Literal(String),
+    Regex(Regex),
+}
+
+impl Pattern {
+    // Byte range of the first match in text, given that the first scanned
+    // bytes of it have already been searched without a match. A literal
+    // only needs to look again at the end of those that it could still
+    // finish, but a regex can match any length so it always looks at all
+    // of text.
+    fn find(&self, text: &str, scanned: usize) -> Option<(usize, usize)> {
+        match self {
+            Pattern::Literal(literal) => {
+                let mut from = scanned.saturating_sub(literal.len().saturating_sub(1));
+                while !text.is_char_boundary(from) {
+                    from -= 1;
+                }
+                text[from..]
+                    .find(literal.as_str())
+                    .map(|start| (from + start, from + start + literal.len()))
+            }
+            Pattern::Regex(regex) => regex.find(text).map(|m| (m.start(), m.end())),
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            Pattern::Literal(literal) => format!("{:?}", literal),
+            Pattern::Regex(regex) => format!("/{}/", regex.as_str()),
+        }
+    }
+}
+
+enum TranscriptEntry {
+    Output(String),
+    Input(String),
+}
+
+// Drives a program running on a ScriptedIOHandle the way a user at the
+// terminal would: wait for some output, type a reply, wait for more.
+//
+// Each expectation consumes the program's output up to the end of its
+// match, so later expectations only see what was printed after it. When an
+// expectation fails the error carries a transcript of everything sent and
+// received so far.
+pub struct Expect {
+    vm: VM<ScriptedIOHandle>,
+    timeout: u64,
+    // Byte offset into the captured output up to which text has been matched
+    consumed: usize,
+    transcript: Vec<TranscriptEntry>,
+}
+
+impl Expect {
+    pub fn new(program: &[u16]) -> LC3Result<Self> {
+        let mut vm = VM::new_with_io(ScriptedIOHandle::new());
+        vm.load_program(program)?;
+        Self::from_vm(vm)
+    }
+
+    // Wrap a VM that already has its program (and any plugins) loaded.
+    pub fn from_vm(mut vm: VM<ScriptedIOHandle>) -> LC3Result<Self> {
+        vm.start()?;
+        Ok(Self {
+            vm,
+            timeout: DEFAULT_TIMEOUT,
+            consumed: 0,
+            transcript: Vec::new(),
+        })
+    }
+
+    // Maximum number of instructions to execute while waiting on a single
+    // expectation.
+    pub fn set_timeout(&mut self, instructions: u64) {
+        self.timeout = instructions;
+    }
+
+    pub fn send(&mut self, input: &str) {
+        self.vm.io_handle_mut().add_input(input);
+        self.transcript.push(TranscriptEntry::Input(input.to_string()));
+    }
+
+    // Run until the program prints literal, returning the matched text.
+    pub fn expect(&mut self, literal: &str) -> LC3Result<String> {
+        self.wait_for(&Pattern::Literal(literal.to_string()))
+    }
+
+    // Run until the program's output matches the regex pattern, returning
+    // the matched text.
+    pub fn expect_regex(&mut self, pattern: &str) -> LC3Result<String> {
+        let regex = Regex::new(pattern)
+            .map_err(|err| LC3Error::Other(format!("Invalid expect pattern: {}", err)))?;
+        self.wait_for(&Pattern::Regex(regex))
+    }
+
+    // Run until the program halts.
+    pub fn expect_halt(&mut self) -> LC3Result<()> {
+        let mut budget = self.timeout;
+        while self.vm.get_running()? {
+            if budget == 0 {
+                return Err(self.failure(format!(
+                    "Timed out after {} instructions waiting for the program to halt",
+                    self.timeout
+                )));
+            }
+            self.step("the program to halt")?;
+            budget -= 1;
+        }
+
+        self.consume_output(self.vm.io_handle().output_len() - self.consumed);
+        Ok(())
+    }
+
+    pub fn transcript(&self) -> String {
+        let mut rendered = String::from("Transcript:\n");
+        for entry in &self.transcript {
+            match entry {
+                TranscriptEntry::Output(text) => rendered += &format!("  output: {:?}\n", text),
+                TranscriptEntry::Input(text) => rendered += &format!("  input:  {:?}\n", text),
+            }
+        }
+
+        let unmatched = self.vm.io_handle().with_output(|output| output[self.consumed..].to_string());
+        if !unmatched.is_empty() {
+            rendered += &format!("  output: {:?} (unmatched)\n", unmatched);
+        }
+
+        rendered
+    }
+
+    pub fn vm(&mut self) -> &mut VM<ScriptedIOHandle> {
+        &mut self.vm
+    }
+
+    pub fn into_vm(self) -> VM<ScriptedIOHandle> {
+        self.vm
+    }
+
+    fn wait_for(&mut self, pattern: &Pattern) -> LC3Result<String> {
+        let mut budget = self.timeout;
+        let mut checked_len = None;
+
+        loop {
+            // Only search again when something new has been printed, and
+            // then only as much as the pattern needs to
+            let output_len = self.vm.io_handle().output_len();
+            if checked_len != Some(output_len) {
+                let scanned = checked_len.map_or(0, |len| len - self.consumed);
+                let found = self.vm.io_handle().with_output(|output| {
+                    let pending = &output[self.consumed..];
+                    let (start, end) = pattern.find(pending, scanned)?;
+                    Some((end, pending[start..end].to_string()))
+                });
+                if let Some((end, matched)) = found {
+                    self.consume_output(end);
+                    return Ok(matched);
+                }
+                checked_len = Some(output_len);
+            }
+
+            if !self.vm.get_running()? {
+                return Err(self.failure(format!(
+                    "Program halted while waiting for {}",
+                    pattern.describe()
+                )));
+            }
+
+            if budget == 0 {
+                return Err(self.failure(format!(
+                    "Timed out after {} instructions waiting for {}",
+                    self.timeout,
+                    pattern.describe()
+                )));
+            }
+
+            self.step(&pattern.describe())?;
+            budget -= 1;
+        }
+    }
+
+    fn step(&mut self, waiting_for: &str) -> LC3Result<()> {
+        match self.vm.step() {
+            Ok(()) => Ok(()),
+            Err(LC3Error::InputExhausted) => Err(self.failure(format!(
+                "Program asked for input while waiting for {}",
+                waiting_for
+            ))),
+            Err(err) => Err(self.failure(format!(
+                "VM error while waiting for {}: {}",
+                waiting_for, err
+            ))),
+        }
+    }
+
+    fn consume_output(&mut self, len: usize) {
+        if len == 0 {
+            return;
+        }
+
+        let range = self.consumed..self.consumed + len;
+        let text = self.vm.io_handle().with_output(|output| output[range].to_string());
+        self.consumed += len;
+        self.transcript.push(TranscriptEntry::Output(text));
+    }
+
+    fn failure(&self, reason: String) -> LC3Error {
+        LC3Error::Expect {
+            reason,
+            transcript: self.transcript(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Expect;
+    use crate::error::{LC3Error, LC3Result};
+
+    // Prints "Name? ", reads a character, echoes it and halts.
+    fn prompt_program() -> Vec<u16> {
+        let mut program = vec![
+            // LEA R0, the prompt string
+            0b1110_0000_0000_0100,
+            // PUTS
+            0xF022,
+            // GETC
+            0xF020,
+            // OUT
+            0xF021,
+            // HALT
+            0xF025,
+        ];
+        program.extend("Name? ".chars().map(|ch| ch as u16));
+        program.push(0);
+        program
+    }
+
+    fn expect_failure(result: LC3Result<String>) -> (String, String) {
+        match result {
+            Err(LC3Error::Expect { reason, transcript }) => (reason, transcript),
+            other => panic!("Expected an expect failure, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn can_drive_interaction() -> LC3Result<()> {
+        let mut session = Expect::new(&prompt_program())?;
+        assert_eq!(session.expect("Name?")?, "Name?");
+        session.send("z");
+        assert_eq!(session.expect_regex(r"[a-z]")?, "z");
+        session.expect_halt()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn can_match_across_writes() -> LC3Result<()> {
+        let program = [
+            // LD R0, 'a' then OUT
+            0b0010_0000_0000_0100,
+            0xF021,
+            // LD R0, 'b' then OUT
+            0b0010_0000_0000_0011,
+            0xF021,
+            // HALT
+            0xF025,
+            'a' as u16,
+            'b' as u16,
+        ];
+        let mut session = Expect::new(&program)?;
+        assert_eq!(session.expect("ab")?, "ab");
+        session.expect_halt()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn can_report_halt_with_transcript() -> LC3Result<()> {
+        let mut session = Expect::new(&prompt_program())?;
+        session.expect("Name? ")?;
+        session.send("q");
+
+        let (reason, transcript) = expect_failure(session.expect("Goodbye"));
+        assert_eq!(reason, "Program halted while waiting for \"Goodbye\"");
+        assert_eq!(
+            transcript,
+            "Transcript:\n  output: \"Name? \"\n  input:  \"q\"\n  output: \"q\" (unmatched)\n"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn can_report_missing_input() -> LC3Result<()> {
+        let mut session = Expect::new(&prompt_program())?;
+        let (reason, _) = expect_failure(session.expect("never printed"));
+        assert_eq!(
+            reason,
+            "Program asked for input while waiting for \"never printed\""
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn can_time_out() -> LC3Result<()> {
+        // BRnzp to itself
+        let mut session = Expect::new(&[0b0000_1111_1111_1111])?;
+        session.set_timeout(50);
+        let (reason, _) = expect_failure(session.expect_regex("^x+$"));
+        assert_eq!(reason, "Timed out after 50 instructions waiting for /^x+$/");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,70 @@
+// Hand-assembled programs shared by the tests and benchmarks. Both load at
+// x3000. The benchmarks include this file by path, so it can't use
+// anything from the rest of the crate.
+
+// Prints "hi\n" from PRINT (x3009), then three stars from a loop. MSG is at
+// x300C and the last word of code is at x300A.
+pub fn print_program() -> Vec<u16> {
+    vec![
+        // LEA R0, MSG then JSR PRINT
+        0b1110_0000_0000_1011,
+        0b0100_1000_0000_0111,
+        // AND R1, R1, #0 then ADD R1, R1, #3
+        0b0101_0010_0110_0000,
+        0b0001_0010_0110_0011,
+        // LOOP: LD R0, STAR then OUT
+        0b0010_0000_0000_0110,
+        0xF021,
+        // ADD R1, R1, #-1 then BRp LOOP
+        0b0001_0010_0111_1111,
+        0b0000_0011_1111_1100,
+        // HALT
+        0xF025,
+        // PRINT: PUTS then RET
+        0xF022,
+        0b1100_0001_1100_0000,
+        // STAR
+        '*' as u16,
+        // MSG
+        'h' as u16,
+        'i' as u16,
+        '\n' as u16,
+        0,
+    ]
+}
+
+// Sums 1..=n into R0 with a recursive SUM (x3004) that keeps R7 and its
+// argument on a stack pointed to by R6, growing down from x6000
+pub fn sum_program(n: u16) -> Vec<u16> {
+    vec![
+        // LD R6, STACK then LD R0, N
+        0b0010_1100_0001_0001,
+        0b0010_0000_0001_0001,
+        // JSR SUM then HALT
+        0b0100_1000_0000_0001,
+        0xF025,
+        // SUM: push R7
+        0b0001_1101_1011_1111,
+        0b0111_1111_1000_0000,
+        // ADD R0, R0, #0 then BRz BASE
+        0b0001_0000_0010_0000,
+        0b0000_0100_0000_0111,
+        // Push R0
+        0b0001_1101_1011_1111,
+        0b0111_0001_1000_0000,
+        // ADD R0, R0, #-1 then JSR SUM
+        0b0001_0000_0011_1111,
+        0b0100_1111_1111_1000,
+        // Pop into R1 then ADD R0, R0, R1
+        0b0110_0011_1000_0000,
+        0b0001_1101_1010_0001,
+        0b0001_0000_0000_0001,
+        // BASE: pop R7 then RET
+        0b0110_1111_1000_0000,
+        0b0001_1101_1010_0001,
+        0b1100_0001_1100_0000,
+        // STACK and N
+        0x6000,
+        n,
+    ]
+}
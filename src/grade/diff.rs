@@ -0,0 +1,52 @@
+// Line based diff of expected against actual output, built from a longest
+// common subsequence table. Outputs from student programs are short, so the
+// quadratic table is not a concern.
+pub(crate) fn diff_lines(expected: &str, actual: &str) -> String {
+    let expected: Vec<&str> = expected.split('\n').collect();
+    let actual: Vec<&str> = actual.split('\n').collect();
+
+    // common[i][j] is the LCS length of expected[i..] and actual[j..]
+    let mut common = vec![vec![0usize; actual.len() + 1]; expected.len() + 1];
+    for i in (0..expected.len()).rev() {
+        for j in (0..actual.len()).rev() {
+            common[i][j] = if expected[i] == actual[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let mut diff = String::new();
+    let (mut i, mut j) = (0, 0);
+    while i < expected.len() || j < actual.len() {
+        if i < expected.len() && j < actual.len() && expected[i] == actual[j] {
+            diff += &format!("  {}\n", expected[i]);
+            i += 1;
+            j += 1;
+        } else if j < actual.len() && (i == expected.len() || common[i][j + 1] >= common[i + 1][j])
+        {
+            diff += &format!("+ {}\n", actual[j]);
+            j += 1;
+        } else {
+            diff += &format!("- {}\n", expected[i]);
+            i += 1;
+        }
+    }
+
+    diff
+}
+
+#[cfg(test)]
+mod test {
+    use super::diff_lines;
+
+    #[test]
+    fn can_diff_lines() {
+        let diff = diff_lines("a\nb\nc", "a\nx\nc");
+        assert_eq!(diff, "  a\n+ x\n- b\n  c\n");
+
+        let diff = diff_lines("same", "same");
+        assert_eq!(diff, "  same\n");
+    }
+}
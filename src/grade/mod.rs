@@ -0,0 +1,8 @@
+mod diff;
+mod report;
+mod runner;
+mod spec;
+
+pub use report::{CaseResult, CaseStatus, Report};
+pub use runner::{run_case, run_suite};
+pub use spec::{TestCase, TestSpec};
@@ -0,0 +1,143 @@
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CaseStatus {
+    Passed,
+    // The program ran to completion but got something wrong
+    Failed,
+    // The program didn't run to completion (VM error, instruction limit)
+    Error,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CaseResult {
+    pub name: String,
+    pub status: CaseStatus,
+    pub instructions: u64,
+    pub output: String,
+    // One entry per mismatch, e.g. an output diff or a register comparison
+    pub failures: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Report {
+    pub name: String,
+    pub cases: Vec<CaseResult>,
+}
+
+impl Report {
+    pub fn count(&self, status: CaseStatus) -> usize {
+        self.cases.iter().filter(|case| case.status == status).count()
+    }
+
+    pub fn all_passed(&self) -> bool {
+        self.count(CaseStatus::Passed) == self.cases.len()
+    }
+
+    pub fn to_json(&self) -> String {
+        // Serializing plain strings and numbers can't fail
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+
+    pub fn to_junit_xml(&self) -> String {
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        xml += &format!(
+            "<testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" errors=\"{}\">\n",
+            xml_escape(&self.name),
+            self.cases.len(),
+            self.count(CaseStatus::Failed),
+            self.count(CaseStatus::Error),
+        );
+
+        for case in &self.cases {
+            xml += &format!("  <testcase name=\"{}\">\n", xml_escape(&case.name));
+            let tag = match case.status {
+                CaseStatus::Passed => None,
+                CaseStatus::Failed => Some("failure"),
+                CaseStatus::Error => Some("error"),
+            };
+            if let Some(tag) = tag {
+                let message = case.failures.first().map(String::as_str).unwrap_or("");
+                let message = message.lines().next().unwrap_or("");
+                xml += &format!(
+                    "    <{tag} message=\"{}\">{}</{tag}>\n",
+                    xml_escape(message),
+                    xml_escape(&case.failures.join("\n")),
+                    tag = tag
+                );
+            }
+            xml += &format!(
+                "    <system-out>{}</system-out>\n",
+                xml_escape(&case.output)
+            );
+            xml += "  </testcase>\n";
+        }
+
+        xml += "</testsuite>\n";
+        xml
+    }
+}
+
+fn xml_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '&' => escaped += "&amp;",
+            '<' => escaped += "&lt;",
+            '>' => escaped += "&gt;",
+            '"' => escaped += "&quot;",
+            '\'' => escaped += "&apos;",
+            // XML 1.0 can't represent most control characters at all
+            ch if (ch as u32) < 0x20 && !matches!(ch, '\n' | '\r' | '\t') => {
+                escaped += &format!("\\u{{{:x}}}", ch as u32)
+            }
+            ch => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod test {
+    use super::{CaseResult, CaseStatus, Report};
+
+    #[test]
+    fn can_write_junit_xml() {
+        let report = Report {
+            name: "suite".to_string(),
+            cases: vec![
+                CaseResult {
+                    name: "good".to_string(),
+                    status: CaseStatus::Passed,
+                    instructions: 3,
+                    output: "ok".to_string(),
+                    failures: vec![],
+                },
+                CaseResult {
+                    name: "bad <1>".to_string(),
+                    status: CaseStatus::Failed,
+                    instructions: 4,
+                    output: "".to_string(),
+                    failures: vec!["R0: expected x0001, got x0000".to_string()],
+                },
+            ],
+        };
+
+        let expected = concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+            "<testsuite name=\"suite\" tests=\"2\" failures=\"1\" errors=\"0\">\n",
+            "  <testcase name=\"good\">\n",
+            "    <system-out>ok</system-out>\n",
+            "  </testcase>\n",
+            "  <testcase name=\"bad &lt;1&gt;\">\n",
+            "    <failure message=\"R0: expected x0001, got x0000\">",
+            "R0: expected x0001, got x0000</failure>\n",
+            "    <system-out></system-out>\n",
+            "  </testcase>\n",
+            "</testsuite>\n",
+        );
+        assert_eq!(report.to_junit_xml(), expected);
+        assert!(!report.all_passed());
+    }
+}
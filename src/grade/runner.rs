@@ -0,0 +1,208 @@
+use super::diff::diff_lines;
+use super::report::{CaseResult, CaseStatus, Report};
+use super::spec::{TestCase, TestSpec};
+use crate::error::{LC3Error, LC3Result};
+use crate::io::ScriptedIOHandle;
+use crate::limits::Limits;
+use crate::register::Register;
+use crate::vm::VM;
+
+// Used when neither the case nor the spec sets a limit
+const DEFAULT_INSTRUCTION_LIMIT: u64 = 1_000_000;
+
+// Run every case in spec against program, each on a fresh VM.
+pub fn run_suite(program: &[u16], spec: &TestSpec) -> LC3Result<Report> {
+    let cases = spec
+        .cases
+        .iter()
+        .map(|case| run_case(program, spec, case))
+        .collect::<LC3Result<Vec<_>>>()?;
+
+    Ok(Report {
+        name: spec.name.clone(),
+        cases,
+    })
+}
+
+pub fn run_case(program: &[u16], spec: &TestSpec, case: &TestCase) -> LC3Result<CaseResult> {
+    let mut vm = VM::new_with_io(ScriptedIOHandle::with_input(&case.input));
+    // A program that doesn't fit in memory is broken for every case, so
+    // this fails the whole run rather than the case.
+    vm.load_program(program)?;
+    for (address, value) in &case.memory {
+        vm.mem_write(*address, *value)?;
+    }
+
+    vm.start()?;
+    for (index, value) in &case.registers {
+        vm.reg_index_write(*index, *value)?;
+    }
+
+    let limit = case
+        .instruction_limit
+        .or(spec.instruction_limit)
+        .unwrap_or(DEFAULT_INSTRUCTION_LIMIT);
+    vm.set_limits(Limits::new().max_instructions(limit));
+
+    let mut run_error = None;
+    while vm.get_running()? {
+        if let Err(err) = vm.step() {
+            run_error = Some(match err {
+                LC3Error::InputExhausted => {
+                    "Program asked for more input than the case provides".to_string()
+                }
+                err => err.to_string(),
+            });
+            break;
+        }
+    }
+
+    let instructions = vm.instructions_executed();
+    let output = vm.io_handle().output();
+    let mut failures = Vec::new();
+    if let Some(error) = run_error {
+        failures.push(error);
+    }
+
+    if let Some(expected) = &case.expected_output {
+        if *expected != output {
+            failures.push(format!(
+                "Output differs (- expected, + actual):\n{}",
+                diff_lines(expected, &output)
+            ));
+        }
+    }
+
+    for (index, expected) in &case.expected_registers {
+        let actual = vm.reg_index_read(*index)?;
+        if actual != *expected {
+            let name = Register::from_index(*index).map_or("?", |reg| reg.name());
+            failures.push(format!(
+                "{}: expected x{:04X}, got x{:04X}",
+                name, expected, actual
+            ));
+        }
+    }
+
+    // Peeked rather than read, so checking a device's address doesn't read
+    // from the device or show up to plugins
+    for (address, expected) in &case.expected_memory {
+        let actual = vm.peek_memory(*address);
+        if actual != *expected {
+            failures.push(format!(
+                "Memory x{:04X}: expected x{:04X}, got x{:04X}",
+                address, expected, actual
+            ));
+        }
+    }
+
+    let status = if failures.is_empty() {
+        CaseStatus::Passed
+    } else if vm.get_running()? {
+        CaseStatus::Error
+    } else {
+        CaseStatus::Failed
+    };
+
+    Ok(CaseResult {
+        name: case.name.clone(),
+        status,
+        instructions,
+        output,
+        failures,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::run_suite;
+    use crate::error::LC3Result;
+    use crate::grade::{CaseStatus, TestSpec};
+
+    // Reads a digit d, prints d + 1 and stores it at x4000
+    fn increment_program() -> Vec<u16> {
+        vec![
+            // GETC
+            0xF020,
+            // ADD R0, R0, #1
+            0b0001_0000_0010_0001,
+            // OUT
+            0xF021,
+            // STI R0, the pointer below
+            0b1011_0000_0000_0001,
+            // HALT
+            0xF025,
+            0x4000,
+        ]
+    }
+
+    #[test]
+    fn can_grade_cases() -> LC3Result<()> {
+        let spec = TestSpec::from_toml_str(
+            r#"
+            [[case]]
+            name = "pass"
+            input = "4"
+            expected_output = "5"
+            expected_registers = { R0 = 0x35 }
+            expected_memory = { x4000 = 0x35 }
+
+            [[case]]
+            name = "wrong output"
+            input = "4"
+            expected_output = "6"
+            expected_registers = { R0 = 0x36 }
+
+            [[case]]
+            name = "no input"
+
+            [[case]]
+            name = "limit"
+            input = "1"
+            instruction_limit = 2
+        "#,
+        )?;
+
+        let report = run_suite(&increment_program(), &spec)?;
+        let statuses: Vec<CaseStatus> = report.cases.iter().map(|case| case.status).collect();
+        assert_eq!(
+            statuses,
+            vec![
+                CaseStatus::Passed,
+                CaseStatus::Failed,
+                CaseStatus::Error,
+                CaseStatus::Error
+            ]
+        );
+
+        let failures = &report.cases[1].failures;
+        assert_eq!(failures[0], "Output differs (- expected, + actual):\n+ 5\n- 6\n");
+        assert_eq!(failures[1], "R0: expected x0036, got x0035");
+        assert_eq!(
+            report.cases[3].failures[0],
+            "Instruction limit of 2 reached at PC x3002"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn checking_memory_does_not_read_devices() -> LC3Result<()> {
+        // A key is still waiting once the program halts, which KBSR would
+        // show if it were read
+        let spec = TestSpec::from_toml_str(
+            r#"
+            [[case]]
+            name = "kbsr"
+            input = "45"
+            expected_memory = { xFE00 = 0 }
+        "#,
+        )?;
+
+        let report = run_suite(&increment_program(), &spec)?;
+        assert_eq!(report.cases[0].failures, Vec::<String>::new());
+        assert_eq!(report.cases[0].status, CaseStatus::Passed);
+
+        Ok(())
+    }
+}
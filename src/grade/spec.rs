@@ -0,0 +1,244 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::error::{BoxErrors, LC3Error, LC3Result};
+use crate::register::Register;
+
+// Words in a spec can be written as plain integers (negative values are
+// stored as two's complement) or as strings in LC3 assembler notation,
+// e.g. "x4000", "#-3" or "0x4000".
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawWord {
+    Int(i64),
+    Str(String),
+}
+
+#[derive(Deserialize)]
+struct RawCase {
+    name: String,
+    #[serde(default)]
+    input: String,
+    expected_output: Option<String>,
+    #[serde(default)]
+    registers: BTreeMap<String, RawWord>,
+    #[serde(default)]
+    memory: BTreeMap<String, RawWord>,
+    #[serde(default)]
+    expected_registers: BTreeMap<String, RawWord>,
+    #[serde(default)]
+    expected_memory: BTreeMap<String, RawWord>,
+    instruction_limit: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct RawSpec {
+    name: Option<String>,
+    instruction_limit: Option<u64>,
+    #[serde(alias = "case")]
+    cases: Vec<RawCase>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestCase {
+    pub name: String,
+    pub input: String,
+    pub expected_output: Option<String>,
+    // (register index, value) pairs
+    pub registers: Vec<(u8, u16)>,
+    // (address, value) pairs
+    pub memory: Vec<(u16, u16)>,
+    pub expected_registers: Vec<(u8, u16)>,
+    pub expected_memory: Vec<(u16, u16)>,
+    pub instruction_limit: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestSpec {
+    pub name: String,
+    // Applies to every case that doesn't set its own limit
+    pub instruction_limit: Option<u64>,
+    pub cases: Vec<TestCase>,
+}
+
+impl TestSpec {
+    pub fn from_toml_str(text: &str) -> LC3Result<Self> {
+        let raw: RawSpec = toml::from_str(text).map_err(|err| LC3Error::Spec(err.to_string()))?;
+        Self::from_raw(raw)
+    }
+
+    pub fn from_json_str(text: &str) -> LC3Result<Self> {
+        let raw: RawSpec =
+            serde_json::from_str(text).map_err(|err| LC3Error::Spec(err.to_string()))?;
+        Self::from_raw(raw)
+    }
+
+    // Reads a spec file, treating anything with a .json extension as JSON
+    // and everything else as TOML.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> LC3Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path).map_io_error()?;
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => Self::from_json_str(&text),
+            _ => Self::from_toml_str(&text),
+        }
+    }
+
+    fn from_raw(raw: RawSpec) -> LC3Result<Self> {
+        let cases = raw
+            .cases
+            .into_iter()
+            .map(TestCase::from_raw)
+            .collect::<LC3Result<Vec<_>>>()?;
+
+        Ok(Self {
+            name: raw.name.unwrap_or_else(|| "lc3rs".to_string()),
+            instruction_limit: raw.instruction_limit,
+            cases,
+        })
+    }
+}
+
+impl TestCase {
+    fn from_raw(raw: RawCase) -> LC3Result<Self> {
+        let context = |err: LC3Error| match err {
+            LC3Error::Spec(msg) => LC3Error::Spec(format!("case {:?}: {}", raw.name, msg)),
+            other => other,
+        };
+
+        let registers = parse_registers(&raw.registers).map_err(context)?;
+        let memory = parse_memory(&raw.memory).map_err(context)?;
+        let expected_registers = parse_registers(&raw.expected_registers).map_err(context)?;
+        let expected_memory = parse_memory(&raw.expected_memory).map_err(context)?;
+
+        Ok(Self {
+            name: raw.name,
+            input: raw.input,
+            expected_output: raw.expected_output,
+            registers,
+            memory,
+            expected_registers,
+            expected_memory,
+            instruction_limit: raw.instruction_limit,
+        })
+    }
+}
+
+fn parse_registers(raw: &BTreeMap<String, RawWord>) -> LC3Result<Vec<(u8, u16)>> {
+    raw.iter()
+        .map(|(name, value)| {
+            let register = Register::from_name(name)
+                .ok_or_else(|| LC3Error::Spec(format!("unknown register {:?}", name)))?;
+            Ok((register.to_u8(), parse_raw_word(value)?))
+        })
+        .collect()
+}
+
+fn parse_memory(raw: &BTreeMap<String, RawWord>) -> LC3Result<Vec<(u16, u16)>> {
+    raw.iter()
+        .map(|(address, value)| Ok((parse_word(address)?, parse_raw_word(value)?)))
+        .collect()
+}
+
+fn parse_raw_word(raw: &RawWord) -> LC3Result<u16> {
+    match raw {
+        RawWord::Int(value) => int_to_word(*value),
+        RawWord::Str(text) => parse_word(text),
+    }
+}
+
+pub(crate) fn parse_word(text: &str) -> LC3Result<u16> {
+    let text = text.trim();
+    let bad_word = || LC3Error::Spec(format!("{:?} is not a valid word", text));
+
+    let value = if let Some(hex) = text
+        .strip_prefix("0x")
+        .or_else(|| text.strip_prefix('x'))
+        .or_else(|| text.strip_prefix('X'))
+    {
+        i64::from_str_radix(hex, 16).map_err(|_| bad_word())?
+    } else {
+        text.trim_start_matches('#')
+            .parse::<i64>()
+            .map_err(|_| bad_word())?
+    };
+
+    int_to_word(value)
+}
+
+fn int_to_word(value: i64) -> LC3Result<u16> {
+    if value < i16::MIN as i64 || value > u16::MAX as i64 {
+        return Err(LC3Error::Spec(format!("{} does not fit in a word", value)));
+    }
+
+    Ok(value as u16)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_word, TestSpec};
+    use crate::error::LC3Result;
+
+    #[test]
+    fn can_parse_words() -> LC3Result<()> {
+        let test_cases = vec![
+            ("x4000", 0x4000),
+            ("0xFFFF", 0xFFFF),
+            ("#12", 12),
+            ("-1", 0xFFFF),
+            ("25", 25),
+        ];
+
+        for (text, expected) in test_cases {
+            assert_eq!(parse_word(text)?, expected);
+        }
+
+        assert!(parse_word("x10000").is_err());
+        assert!(parse_word("R1").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn can_parse_toml_and_json_specs() -> LC3Result<()> {
+        let toml_spec = r#"
+            instruction_limit = 500
+
+            [[case]]
+            name = "square"
+            input = "5"
+            expected_output = "25"
+
+            [case.registers]
+            R1 = 3
+
+            [case.expected_memory]
+            x4000 = -2
+        "#;
+
+        let json_spec = r#"{
+            "instruction_limit": 500,
+            "cases": [{
+                "name": "square",
+                "input": "5",
+                "expected_output": "25",
+                "registers": {"r1": 3},
+                "expected_memory": {"x4000": "xFFFE"}
+            }]
+        }"#;
+
+        let from_toml = TestSpec::from_toml_str(toml_spec)?;
+        let from_json = TestSpec::from_json_str(json_spec)?;
+        assert_eq!(from_toml, from_json);
+
+        let case = &from_toml.cases[0];
+        assert_eq!(case.registers, vec![(1, 3)]);
+        assert_eq!(case.expected_memory, vec![(0x4000, 0xFFFE)]);
+
+        assert!(TestSpec::from_toml_str("[[case]]\nname = \"x\"\nregisters = { R9 = 1 }").is_err());
+
+        Ok(())
+    }
+}
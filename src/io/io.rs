@@ -1,29 +1,23 @@
+use std::cell::Cell;
 use std::io::{stdout, Write};
+#[cfg(not(feature = "device-query"))]
+use std::time::Duration;
 
+#[cfg(not(feature = "device-query"))]
+use crossterm::event::poll;
 use crossterm::event::{read, Event, KeyCode, KeyEvent, KeyModifiers};
 use crossterm::terminal::{disable_raw_mode, enable_raw_mode};
 
-use crate::error::{BoxErrors, LC3Result};
+use crate::error::{BoxErrors, LC3Error, LC3Result};
 
 pub fn getchar() -> LC3Result<char> {
     // Largely owe this code to this Stackoverflow answer:
     // https://stackoverflow.com/questions/60130532/detect-keydown-in-rust
     try_enable_raw_mode()?;
-
-    let read_char = loop {
-        match read().map_io_error()? {
-                Event::Key(KeyEvent {
-                    code: KeyCode::Char(key),
-                    modifiers: KeyModifiers::NONE,
-                }) => break key,
-
-                _ => {}
-            }
-    };
-
+    let read_char = read_key();
     try_disable_raw_mode()?;
 
-    Ok(read_char)
+    read_char
 }
 
 pub(crate) fn putchar(ch: char) -> LC3Result<()> {
@@ -32,6 +26,92 @@ pub(crate) fn putchar(ch: char) -> LC3Result<()> {
     stdout.flush().map_io_error()
 }
 
+// Block until a key that types a character is pressed
+fn read_key() -> LC3Result<char> {
+    loop {
+        if let Some(key) = key_char(&read().map_io_error()?)? {
+            return Ok(key);
+        }
+    }
+}
+
+// The character a key press types, if it is one. Anything else, such as
+// mouse events, resizes and keys with modifiers held, is ignored. Raw mode
+// stops the terminal turning Ctrl-C into SIGINT, so it's caught here and
+// stops the run instead.
+fn key_char(event: &Event) -> LC3Result<Option<char>> {
+    match event {
+        Event::Key(KeyEvent {
+            code: KeyCode::Char('c'),
+            modifiers: KeyModifiers::CONTROL,
+        }) => Err(LC3Error::Interrupted),
+        Event::Key(KeyEvent {
+            code: KeyCode::Char(key),
+            modifiers: KeyModifiers::NONE,
+        }) => Ok(Some(*key)),
+        _ => Ok(None),
+    }
+}
+
+// The terminal, as a running program sees it. Raw mode is needed to read
+// keys as they're typed rather than once the user hits enter. It's turned
+// on the first time the program reads the keyboard and stays on until the
+// terminal is dropped, rather than being toggled for every poll of the
+// keyboard status register.
+#[derive(Default)]
+pub(crate) struct Terminal {
+    raw_mode: Cell<bool>,
+    // A key read while checking for one, for the next getchar
+    waiting: Cell<Option<char>>,
+}
+
+impl Terminal {
+    pub(crate) fn getchar(&self) -> LC3Result<char> {
+        if let Some(key) = self.waiting.take() {
+            return Ok(key);
+        }
+        self.enable_raw_mode()?;
+        read_key()
+    }
+
+    // Raw mode doesn't turn a newline into a carriage return and line feed
+    pub(crate) fn putchar(&self, ch: char) -> LC3Result<()> {
+        if ch == '\n' && self.raw_mode.get() {
+            putchar('\r')?;
+        }
+        putchar(ch)
+    }
+
+    // Check whether a key has been pressed without blocking. Other input
+    // events are skipped.
+    #[cfg(not(feature = "device-query"))]
+    pub(crate) fn key_waiting(&self) -> LC3Result<bool> {
+        self.enable_raw_mode()?;
+        while self.waiting.get().is_none() && poll(Duration::from_millis(0)).map_io_error()? {
+            self.waiting.set(key_char(&read().map_io_error()?)?);
+        }
+
+        Ok(self.waiting.get().is_some())
+    }
+
+    fn enable_raw_mode(&self) -> LC3Result<()> {
+        if !self.raw_mode.get() {
+            try_enable_raw_mode()?;
+            self.raw_mode.set(true);
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        if self.raw_mode.get() {
+            let _ = try_disable_raw_mode();
+        }
+    }
+}
+
 fn try_enable_raw_mode() -> LC3Result<()> {
     enable_raw_mode().map_io_error()
 }
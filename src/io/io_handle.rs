@@ -1,13 +1,14 @@
-#[cfg(test)]
+#[cfg(any(test, feature = "device-query"))]
 use std::cell::RefCell;
 
-use device_query::{DeviceQuery, DeviceState};
+#[cfg(feature = "device-query")]
+use device_query::{DeviceQuery, DeviceState, Keycode};
 
-use super::io::{getchar, putchar};
+use super::io::Terminal;
 use crate::error::LC3Result;
 
-#[cfg(test)]
-use crate::error::{LC3Error};
+#[cfg(any(test, feature = "device-query"))]
+use crate::error::LC3Error;
 
 // TODO: Maybe the dependency should be flipped here, so this trait should
 // be part of the VM module. It's the VM's needs that actually determine
@@ -16,32 +17,96 @@ pub trait IOHandle {
     fn getchar(&self) -> LC3Result<char>;
     fn putchar(&self, ch: char) -> LC3Result<()>;
     fn is_key_down(&self) -> LC3Result<bool>;
+
+    // Called by the VM after every instruction it executes, so handles can
+    // keep time in instructions.
+    fn tick(&self) -> LC3Result<()> {
+        Ok(())
+    }
+
+    // Checked by the VM after every instruction. Returning true stops the
+    // run loop as though the program had executed HALT.
+    fn halt_requested(&self) -> LC3Result<bool> {
+        Ok(false)
+    }
 }
 
+// By default key presses are detected by polling the terminal for waiting
+// input, which needs no system libraries. Building with the device-query
+// feature polls the physical keyboard state instead.
 pub struct RealIOHandle {
+    terminal: Terminal,
+    #[cfg(feature = "device-query")]
     device_state: DeviceState,
+    // Keys down at the last poll, so holding one only counts once
+    #[cfg(feature = "device-query")]
+    held: RefCell<Vec<Keycode>>,
 }
 
 impl RealIOHandle {
     pub fn new() -> Self {
         Self {
+            terminal: Terminal::default(),
+            #[cfg(feature = "device-query")]
             device_state: DeviceState::new(),
+            #[cfg(feature = "device-query")]
+            held: RefCell::new(Vec::new()),
         }
     }
 }
 
+impl Default for RealIOHandle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl IOHandle for RealIOHandle {
     fn getchar(&self) -> LC3Result<char> {
-        getchar()
+        self.terminal.getchar()
     }
 
     fn putchar(&self, ch: char) -> LC3Result<()> {
-        putchar(ch)
+        self.terminal.putchar(ch)
     }
 
+    #[cfg(not(feature = "device-query"))]
     fn is_key_down(&self) -> LC3Result<bool> {
-        Ok(self.device_state.get_keys().is_empty())
+        self.terminal.key_waiting()
     }
+
+    // A key counts when it's newly pressed. Modifiers on their own don't
+    // type anything, so they're left out. Ctrl-C stops the run, as it does
+    // while reading from the terminal.
+    #[cfg(feature = "device-query")]
+    fn is_key_down(&self) -> LC3Result<bool> {
+        let keys = self.device_state.get_keys();
+        let control = keys.contains(&Keycode::LControl) || keys.contains(&Keycode::RControl);
+        if control && keys.contains(&Keycode::C) {
+            return Err(LC3Error::Interrupted);
+        }
+
+        let keys: Vec<Keycode> = keys.into_iter().filter(|key| !is_modifier(key)).collect();
+        let pressed = keys.iter().any(|key| !self.held.borrow().contains(key));
+        *self.held.borrow_mut() = keys;
+
+        Ok(pressed)
+    }
+}
+
+#[cfg(feature = "device-query")]
+fn is_modifier(key: &Keycode) -> bool {
+    matches!(
+        key,
+        Keycode::LShift
+            | Keycode::RShift
+            | Keycode::LControl
+            | Keycode::RControl
+            | Keycode::LAlt
+            | Keycode::RAlt
+            | Keycode::Meta
+            | Keycode::CapsLock
+    )
 }
 
 #[cfg(test)]
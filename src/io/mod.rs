@@ -1,8 +1,11 @@
+#[allow(clippy::module_inception)]
 mod io;
 mod io_handle;
+mod scripted;
 
 pub use io_handle::IOHandle;
 pub(crate) use io_handle::{RealIOHandle};
 #[cfg(test)]
 pub(crate) use io_handle::{TestIOHandle};
 pub use io::getchar;
+pub use scripted::{ExhaustedInput, ScriptedIOHandle};
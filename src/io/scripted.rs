@@ -0,0 +1,285 @@
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::path::Path;
+
+use super::IOHandle;
+use crate::error::{BoxErrors, LC3Error, LC3Result};
+
+// What a ScriptedIOHandle should do when the program asks for input
+// and the script has none left.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExhaustedInput {
+    // Fail the read with LC3Error::InputExhausted.
+    Error,
+    // Hand back the given character (e.g. '\u{4}') on every read.
+    Eof(char),
+    // Hand back '\0' and ask the VM to stop after the current instruction.
+    Halt,
+}
+
+// An IOHandle that feeds a program scripted input and captures whatever
+// it prints, for running LC3 programs without a terminal.
+//
+// Input is consumed first in, first out. Timed keystrokes only become
+// visible to the keyboard status register once the VM has executed the
+// requested number of instructions, although a blocking read with nothing
+// else queued will skip ahead to the next one.
+pub struct ScriptedIOHandle {
+    input: RefCell<VecDeque<char>>,
+    // Kept sorted by the instruction count they become due at.
+    timed_input: RefCell<VecDeque<(u64, char)>>,
+    output: RefCell<String>,
+    instructions: Cell<u64>,
+    exhausted: ExhaustedInput,
+    halt_requested: Cell<bool>,
+}
+
+impl ScriptedIOHandle {
+    pub fn new() -> Self {
+        Self {
+            input: RefCell::new(VecDeque::new()),
+            timed_input: RefCell::new(VecDeque::new()),
+            output: RefCell::new(String::new()),
+            instructions: Cell::new(0),
+            exhausted: ExhaustedInput::Error,
+            halt_requested: Cell::new(false),
+        }
+    }
+
+    pub fn with_input(input: &str) -> Self {
+        let mut handle = Self::new();
+        handle.add_input(input);
+        handle
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> LC3Result<Self> {
+        let input = std::fs::read_to_string(path).map_io_error()?;
+        Ok(Self::with_input(&input))
+    }
+
+    pub fn add_input(&mut self, input: &str) {
+        self.input.borrow_mut().extend(input.chars());
+    }
+
+    // Queue a key press that arrives once `after_instructions`
+    // instructions have been executed.
+    pub fn add_timed_key(&mut self, after_instructions: u64, ch: char) {
+        let mut timed = self.timed_input.borrow_mut();
+        let pos = timed
+            .iter()
+            .position(|(due, _)| *due > after_instructions)
+            .unwrap_or_else(|| timed.len());
+        timed.insert(pos, (after_instructions, ch));
+    }
+
+    pub fn set_exhausted_behavior(&mut self, behavior: ExhaustedInput) {
+        self.exhausted = behavior;
+    }
+
+    pub fn output(&self) -> String {
+        self.output.borrow().clone()
+    }
+
+    // Look at everything printed so far without copying it
+    pub fn with_output<T>(&self, f: impl FnOnce(&str) -> T) -> T {
+        f(&self.output.borrow())
+    }
+
+    pub fn output_len(&self) -> usize {
+        self.output.borrow().len()
+    }
+
+    pub fn into_output(self) -> String {
+        self.output.into_inner()
+    }
+
+    pub fn instruction_count(&self) -> u64 {
+        self.instructions.get()
+    }
+
+    // Move any timed keys that have come due onto the end of the input queue.
+    fn release_due_keys(&self) {
+        let now = self.instructions.get();
+        let mut timed = self.timed_input.borrow_mut();
+        while let Some((due, ch)) = timed.front().copied() {
+            if due > now {
+                break;
+            }
+            timed.pop_front();
+            self.input.borrow_mut().push_back(ch);
+        }
+    }
+
+    fn on_exhausted(&self) -> LC3Result<char> {
+        match self.exhausted {
+            ExhaustedInput::Error => Err(LC3Error::InputExhausted),
+            ExhaustedInput::Eof(ch) => Ok(ch),
+            ExhaustedInput::Halt => {
+                self.halt_requested.set(true);
+                Ok('\0')
+            }
+        }
+    }
+}
+
+impl Default for ScriptedIOHandle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl IOHandle for ScriptedIOHandle {
+    fn getchar(&self) -> LC3Result<char> {
+        self.release_due_keys();
+        if let Some(ch) = self.input.borrow_mut().pop_front() {
+            return Ok(ch);
+        }
+
+        // Nothing is queued yet, so a blocking read just waits for the
+        // next timed key to arrive.
+        if let Some((due, ch)) = self.timed_input.borrow_mut().pop_front() {
+            self.instructions.set(self.instructions.get().max(due));
+            return Ok(ch);
+        }
+
+        self.on_exhausted()
+    }
+
+    fn putchar(&self, ch: char) -> LC3Result<()> {
+        self.output.borrow_mut().push(ch);
+        Ok(())
+    }
+
+    fn is_key_down(&self) -> LC3Result<bool> {
+        self.release_due_keys();
+        if !self.input.borrow().is_empty() {
+            return Ok(true);
+        }
+
+        if !self.timed_input.borrow().is_empty() {
+            return Ok(false);
+        }
+
+        match self.exhausted {
+            ExhaustedInput::Error => Ok(false),
+            ExhaustedInput::Eof(_) => Ok(true),
+            ExhaustedInput::Halt => {
+                // Polling for input that can never arrive would spin forever.
+                self.halt_requested.set(true);
+                Ok(false)
+            }
+        }
+    }
+
+    fn tick(&self) -> LC3Result<()> {
+        self.instructions.set(self.instructions.get() + 1);
+        Ok(())
+    }
+
+    fn halt_requested(&self) -> LC3Result<bool> {
+        Ok(self.halt_requested.get())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ExhaustedInput, ScriptedIOHandle};
+    use crate::error::{LC3Error, LC3Result};
+    use crate::io::IOHandle;
+    use crate::vm::VM;
+
+    // Reads characters with GETC and echoes them with OUT until it reads
+    // a '.', then halts.
+    fn echo_program() -> Vec<u16> {
+        vec![
+            // GETC
+            0xF020,
+            // OUT
+            0xF021,
+            // LD R1, the negated '.' stored below
+            0b0010_0010_0000_0011,
+            // ADD R1, R1, R0
+            0b0001_0010_0100_0000,
+            // BRnp back to the GETC
+            0b0000_1011_1111_1011,
+            // HALT
+            0xF025,
+            (-('.' as i16)) as u16,
+        ]
+    }
+
+    #[test]
+    fn can_read_input_in_order() -> LC3Result<()> {
+        let handle = ScriptedIOHandle::with_input("abc");
+        assert!(handle.is_key_down()?);
+        assert_eq!(handle.getchar()?, 'a');
+        assert_eq!(handle.getchar()?, 'b');
+        assert_eq!(handle.getchar()?, 'c');
+        assert!(!handle.is_key_down()?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn can_handle_exhausted_input() -> LC3Result<()> {
+        let handle = ScriptedIOHandle::new();
+        match handle.getchar() {
+            Err(LC3Error::InputExhausted) => {}
+            other => panic!("Expected exhausted input error, got {:?}", other),
+        }
+
+        let mut handle = ScriptedIOHandle::new();
+        handle.set_exhausted_behavior(ExhaustedInput::Eof('\u{4}'));
+        assert!(handle.is_key_down()?);
+        assert_eq!(handle.getchar()?, '\u{4}');
+
+        let mut handle = ScriptedIOHandle::new();
+        handle.set_exhausted_behavior(ExhaustedInput::Halt);
+        assert!(!handle.halt_requested()?);
+        assert_eq!(handle.getchar()?, '\0');
+        assert!(handle.halt_requested()?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn can_deliver_timed_keys() -> LC3Result<()> {
+        let mut handle = ScriptedIOHandle::new();
+        handle.add_timed_key(3, 'y');
+        handle.add_timed_key(1, 'x');
+
+        assert!(!handle.is_key_down()?);
+        handle.tick()?;
+        assert!(handle.is_key_down()?);
+        assert_eq!(handle.getchar()?, 'x');
+        assert!(!handle.is_key_down()?);
+
+        // A blocking read skips ahead to the next timed key
+        assert_eq!(handle.getchar()?, 'y');
+        assert_eq!(handle.instruction_count(), 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn can_run_scripted_program() -> LC3Result<()> {
+        let mut vm = VM::new_with_io(ScriptedIOHandle::with_input("hi."));
+        vm.load_program(&echo_program())?;
+        vm.run()?;
+        assert_eq!(vm.into_io_handle().into_output(), "hi.");
+
+        Ok(())
+    }
+
+    #[test]
+    fn can_halt_on_exhausted_input() -> LC3Result<()> {
+        let mut handle = ScriptedIOHandle::with_input("hi");
+        handle.set_exhausted_behavior(ExhaustedInput::Halt);
+        let mut vm = VM::new_with_io(handle);
+        vm.load_program(&echo_program())?;
+        vm.run()?;
+        assert_eq!(vm.io_handle().output(), "hi");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,323 @@
+use cranelift_codegen::ir::condcodes::IntCC;
+use cranelift_codegen::ir::{types, AbiParam, InstBuilder, MemFlags, Type, Value};
+use cranelift_codegen::settings::{self, Configurable};
+use cranelift_codegen::Context;
+use cranelift_codegen::entity::EntityRef;
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext, Variable};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::{default_libcall_names, Module};
+
+use super::MMIO_START;
+use crate::condition_flags::{FL_NEG, FL_POS, FL_ZRO};
+use crate::error::{LC3Error, LC3Result};
+use crate::op::{Instruction, Operand};
+use crate::register::Register::{RCond, RPC};
+
+// A compiled block takes pointers to the VM's registers, its memory and the
+// JIT's code map. It runs until the end of the block or until it reaches an
+// instruction it has to leave to the interpreter, writes the registers (PC
+// included) back and returns the number of instructions it completed.
+pub(crate) type BlockFn = unsafe extern "C" fn(*mut u16, *mut u16, *const u8) -> u32;
+
+fn jit_error<E: std::fmt::Display>(err: E) -> LC3Error {
+    LC3Error::Jit(err.to_string())
+}
+
+pub(crate) struct Compiler {
+    module: JITModule,
+    context: Context,
+    builder_context: FunctionBuilderContext,
+}
+
+impl Compiler {
+    pub(crate) fn new() -> LC3Result<Self> {
+        let mut flags = settings::builder();
+        flags.set("opt_level", "speed").map_err(jit_error)?;
+        flags.set("use_colocated_libcalls", "false").map_err(jit_error)?;
+        flags.set("is_pic", "false").map_err(jit_error)?;
+        let isa = cranelift_native::builder()
+            .map_err(jit_error)?
+            .finish(settings::Flags::new(flags))
+            .map_err(jit_error)?;
+
+        let module = JITModule::new(JITBuilder::with_isa(isa, default_libcall_names()));
+        let context = module.make_context();
+
+        Ok(Self {
+            module,
+            context,
+            builder_context: FunctionBuilderContext::new(),
+        })
+    }
+
+    // Compile the instructions found from address start onwards. Only the
+    // last instruction may transfer control, and none of them may be a
+    // trap or one of the unused op codes.
+    pub(crate) fn compile(&mut self, start: u16, instructions: &[Instruction]) -> LC3Result<BlockFn> {
+        let pointer = self.module.target_config().pointer_type();
+        self.module.clear_context(&mut self.context);
+        let signature = &mut self.context.func.signature;
+        signature.params.extend([AbiParam::new(pointer); 3]);
+        signature.returns.push(AbiParam::new(types::I32));
+
+        let builder = FunctionBuilder::new(&mut self.context.func, &mut self.builder_context);
+        Translator::new(builder, pointer).translate(start, instructions);
+
+        let id = self
+            .module
+            .declare_anonymous_function(&self.context.func.signature)
+            .map_err(jit_error)?;
+        self.module.define_function(id, &mut self.context).map_err(jit_error)?;
+        self.module.finalize_definitions().map_err(jit_error)?;
+        let code = self.module.get_finalized_function(id);
+
+        // Safety: the function was built with the signature BlockFn describes
+        Ok(unsafe { std::mem::transmute::<*const u8, BlockFn>(code) })
+    }
+}
+
+struct Translator<'a> {
+    builder: FunctionBuilder<'a>,
+    pointer: Type,
+    registers: Value,
+    memory: Value,
+    code_map: Value,
+    general: [Variable; 8],
+    cond: Variable,
+}
+
+impl<'a> Translator<'a> {
+    fn new(mut builder: FunctionBuilder<'a>, pointer: Type) -> Self {
+        let entry = builder.create_block();
+        builder.append_block_params_for_function_params(entry);
+        builder.switch_to_block(entry);
+        let params = builder.block_params(entry).to_vec();
+
+        // The registers live in variables for the whole block so Cranelift
+        // can keep them in machine registers, and are only written back on
+        // the way out.
+        let general: [Variable; 8] = std::array::from_fn(Variable::new);
+        let cond = Variable::new(general.len());
+        for var in general.iter().chain([&cond]) {
+            builder.declare_var(*var, types::I16);
+        }
+        let flags = MemFlags::trusted();
+        for (index, var) in general.iter().enumerate() {
+            let value = builder.ins().load(types::I16, flags, params[0], 2 * index as i32);
+            builder.def_var(*var, value);
+        }
+        let value = builder.ins().load(types::I16, flags, params[0], 2 * RCond.to_u8() as i32);
+        builder.def_var(cond, value);
+
+        Self {
+            builder,
+            pointer,
+            registers: params[0],
+            memory: params[1],
+            code_map: params[2],
+            general,
+            cond,
+        }
+    }
+
+    fn translate(mut self, start: u16, instructions: &[Instruction]) {
+        let mut exited = false;
+        for (completed, instruction) in instructions.iter().enumerate() {
+            let address = start.wrapping_add(completed as u16);
+            exited = self.instruction(*instruction, address, completed as u32);
+        }
+
+        if !exited {
+            let end = self.constant(start.wrapping_add(instructions.len() as u16));
+            self.exit(end, instructions.len() as u32);
+        }
+
+        self.builder.seal_all_blocks();
+        self.builder.finalize();
+    }
+
+    // Emit one instruction. Returns true if it ended the block.
+    fn instruction(&mut self, instruction: Instruction, address: u16, completed: u32) -> bool {
+        // PC relative offsets are from the incremented program counter
+        let pc = address.wrapping_add(1);
+
+        match instruction {
+            Instruction::Add { target, source, operand } => {
+                let (left, right) = (self.reg(source), self.operand(operand));
+                let sum = self.builder.ins().iadd(left, right);
+                self.set_reg_with_flags(target, sum);
+            }
+            Instruction::And { target, source, operand } => {
+                let (left, right) = (self.reg(source), self.operand(operand));
+                let result = self.builder.ins().band(left, right);
+                self.set_reg_with_flags(target, result);
+            }
+            Instruction::Not { target, source } => {
+                let source = self.reg(source);
+                let negated = self.builder.ins().bnot(source);
+                self.set_reg_with_flags(target, negated);
+            }
+            Instruction::Ld { target, offset } => {
+                let location = self.constant(pc.wrapping_add(offset));
+                let value = self.load(location, address, completed);
+                self.set_reg_with_flags(target, value);
+            }
+            Instruction::Ldr { target, base, offset } => {
+                let location = self.base_offset(base, offset);
+                let value = self.load(location, address, completed);
+                self.set_reg_with_flags(target, value);
+            }
+            Instruction::Ldi { target, offset } => {
+                let pointer = self.constant(pc.wrapping_add(offset));
+                let location = self.load(pointer, address, completed);
+                let value = self.load(location, address, completed);
+                self.set_reg_with_flags(target, value);
+            }
+            Instruction::Lea { target, offset } => {
+                let location = self.constant(pc.wrapping_add(offset));
+                self.set_reg_with_flags(target, location);
+            }
+            Instruction::St { source, offset } => {
+                let location = self.constant(pc.wrapping_add(offset));
+                let value = self.reg(source);
+                self.store(location, value, address, completed);
+            }
+            Instruction::Str { source, base, offset } => {
+                let location = self.base_offset(base, offset);
+                let value = self.reg(source);
+                self.store(location, value, address, completed);
+            }
+            Instruction::Sti { source, offset } => {
+                let pointer = self.constant(pc.wrapping_add(offset));
+                let location = self.load(pointer, address, completed);
+                let value = self.reg(source);
+                self.store(location, value, address, completed);
+            }
+            Instruction::Br { flags, offset } => {
+                let cond = self.builder.use_var(self.cond);
+                let matched = self.builder.ins().band_imm(cond, flags as i64);
+                let taken = self.constant(pc.wrapping_add(offset));
+                let not_taken = self.constant(pc);
+                let next = self.builder.ins().select(matched, taken, not_taken);
+                self.exit(next, completed + 1);
+                return true;
+            }
+            Instruction::Jmp { base } => {
+                let next = self.reg(base);
+                self.exit(next, completed + 1);
+                return true;
+            }
+            Instruction::Jsr { offset } => {
+                let link = self.constant(pc);
+                self.builder.def_var(self.general[7], link);
+                let next = self.constant(pc.wrapping_add(offset));
+                self.exit(next, completed + 1);
+                return true;
+            }
+            Instruction::Jsrr { base } => {
+                // The interpreter saves the return address before reading
+                // the base register, so JSRR R7 jumps to the next instruction
+                let link = self.constant(pc);
+                self.builder.def_var(self.general[7], link);
+                let next = self.reg(base);
+                self.exit(next, completed + 1);
+                return true;
+            }
+            Instruction::Rti | Instruction::Res | Instruction::Trap { .. } => {
+                unreachable!("Blocks never contain {:?}", instruction)
+            }
+        }
+
+        false
+    }
+
+    fn constant(&mut self, value: u16) -> Value {
+        self.builder.ins().iconst(types::I16, value as i16 as i64)
+    }
+
+    fn reg(&mut self, index: u8) -> Value {
+        self.builder.use_var(self.general[index as usize])
+    }
+
+    fn operand(&mut self, operand: Operand) -> Value {
+        match operand {
+            Operand::Register(index) => self.reg(index),
+            Operand::Immediate(value) => self.constant(value),
+        }
+    }
+
+    fn base_offset(&mut self, base: u8, offset: u16) -> Value {
+        let base = self.reg(base);
+        self.builder.ins().iadd_imm(base, offset as i16 as i64)
+    }
+
+    fn set_reg_with_flags(&mut self, index: u8, value: Value) {
+        self.builder.def_var(self.general[index as usize], value);
+
+        let zero = self.builder.ins().icmp_imm(IntCC::Equal, value, 0);
+        let negative = self.builder.ins().icmp_imm(IntCC::SignedLessThan, value, 0);
+        let (fl_zro, fl_neg, fl_pos) = (self.constant(FL_ZRO), self.constant(FL_NEG), self.constant(FL_POS));
+        let sign_flag = self.builder.ins().select(negative, fl_neg, fl_pos);
+        let cond = self.builder.ins().select(zero, fl_zro, sign_flag);
+        self.builder.def_var(self.cond, cond);
+    }
+
+    // Pointer to the memory word at address
+    fn word(&mut self, address: Value) -> Value {
+        let index = self.builder.ins().uextend(self.pointer, address);
+        let offset = self.builder.ins().ishl_imm(index, 1);
+        self.builder.ins().iadd(self.memory, offset)
+    }
+
+    // Reads of the memory mapped registers have side effects, so those are
+    // left to the interpreter
+    fn load(&mut self, address: Value, pc: u16, completed: u32) -> Value {
+        let mapped = self.builder.ins().icmp_imm(IntCC::UnsignedGreaterThanOrEqual, address, MMIO_START as i64);
+        self.exit_if(mapped, pc, completed);
+
+        let word = self.word(address);
+        self.builder.ins().load(types::I16, MemFlags::trusted(), word, 0)
+    }
+
+    // Writes to code go through the interpreter so cached and compiled
+    // copies of it are thrown away
+    fn store(&mut self, address: Value, value: Value, pc: u16, completed: u32) {
+        let mapped = self.builder.ins().icmp_imm(IntCC::UnsignedGreaterThanOrEqual, address, MMIO_START as i64);
+        self.exit_if(mapped, pc, completed);
+
+        let index = self.builder.ins().uextend(self.pointer, address);
+        let entry = self.builder.ins().iadd(self.code_map, index);
+        let is_code = self.builder.ins().load(types::I8, MemFlags::trusted(), entry, 0);
+        self.exit_if(is_code, pc, completed);
+
+        let word = self.word(address);
+        self.builder.ins().store(MemFlags::trusted(), value, word, 0);
+    }
+
+    // Leave the block before the instruction at pc when condition is set
+    fn exit_if(&mut self, condition: Value, pc: u16, completed: u32) {
+        let exit = self.builder.create_block();
+        let next = self.builder.create_block();
+        self.builder.ins().brif(condition, exit, &[], next, &[]);
+
+        self.builder.switch_to_block(exit);
+        let pc = self.constant(pc);
+        self.exit(pc, completed);
+
+        self.builder.switch_to_block(next);
+    }
+
+    fn exit(&mut self, pc: Value, completed: u32) {
+        for index in 0..self.general.len() {
+            let value = self.reg(index as u8);
+            self.builder.ins().store(MemFlags::trusted(), value, self.registers, 2 * index as i32);
+        }
+        let cond = self.builder.use_var(self.cond);
+        let flags = MemFlags::trusted();
+        self.builder.ins().store(flags, cond, self.registers, 2 * RCond.to_u8() as i32);
+        self.builder.ins().store(flags, pc, self.registers, 2 * RPC.to_u8() as i32);
+
+        let completed = self.builder.ins().iconst(types::I32, completed as i64);
+        self.builder.ins().return_(&[completed]);
+    }
+}
@@ -0,0 +1,154 @@
+mod compile;
+#[cfg(test)]
+mod test;
+
+use compile::{BlockFn, Compiler};
+use crate::error::LC3Result;
+use crate::op::Instruction;
+use crate::register::NUM_REGISTERS;
+use crate::vm::MEMORY_SIZE;
+
+// Start of the memory mapped device registers. Compiled code never touches
+// these or runs code from them.
+pub(crate) const MMIO_START: u16 = 0xFE00;
+
+// How many times the interpreter has to arrive at an address before the
+// block starting there is compiled
+const COMPILE_THRESHOLD: u16 = 32;
+
+const MAX_BLOCK_LEN: u16 = 64;
+
+// Hit count for addresses whose block can't or shouldn't be compiled
+const NEVER_COMPILE: u16 = u16::MAX;
+
+#[derive(Clone, Copy)]
+pub(crate) struct Block {
+    func: BlockFn,
+    len: u16,
+}
+
+impl Block {
+    pub(crate) fn len(&self) -> u16 {
+        self.len
+    }
+}
+
+// Compiles hot straight-line runs of LC-3 code to native code. Blocks end
+// at the first branch, jump or subroutine call and stop short of traps,
+// the unused op codes and the memory mapped registers, which are all left
+// to the interpreter.
+pub(crate) struct Jit {
+    compiler: Compiler,
+    blocks: Vec<Option<Block>>,
+    hits: Vec<u16>,
+    // Non-zero for every address the VM has run as code. Compiled code
+    // leaves stores to these addresses to the interpreter, which throws
+    // away the decoded and compiled copies.
+    code_map: Vec<u8>,
+}
+
+impl Jit {
+    pub(crate) fn new() -> LC3Result<Self> {
+        Ok(Self {
+            compiler: Compiler::new()?,
+            blocks: vec![None; MEMORY_SIZE],
+            hits: vec![0; MEMORY_SIZE],
+            code_map: vec![0; MEMORY_SIZE],
+        })
+    }
+
+    pub(crate) fn mark_code(&mut self, address: u16) {
+        self.code_map[address as usize] = 1;
+    }
+
+    pub(crate) fn is_code(&self, address: u16) -> bool {
+        self.code_map[address as usize] != 0
+    }
+
+    // The compiled block starting at address, compiling it first if the
+    // address has become hot
+    pub(crate) fn block_at(&mut self, address: u16, memory: &[u16]) -> LC3Result<Option<Block>> {
+        if let Some(block) = self.blocks[address as usize] {
+            return Ok(Some(block));
+        }
+
+        let hits = &mut self.hits[address as usize];
+        if *hits == NEVER_COMPILE {
+            return Ok(None);
+        }
+        *hits += 1;
+        if *hits < COMPILE_THRESHOLD {
+            return Ok(None);
+        }
+
+        let instructions = scan_block(memory, address);
+        if instructions.is_empty() {
+            self.hits[address as usize] = NEVER_COMPILE;
+            return Ok(None);
+        }
+
+        for offset in 0..instructions.len() as u16 {
+            self.mark_code(address + offset);
+        }
+
+        let block = Block {
+            func: self.compiler.compile(address, &instructions)?,
+            len: instructions.len() as u16,
+        };
+        self.blocks[address as usize] = Some(block);
+
+        Ok(Some(block))
+    }
+
+    // Run a compiled block against the VM's registers and memory, returning
+    // how many of its instructions were completed
+    pub(crate) fn run(&self, block: Block, registers: &mut [u16; NUM_REGISTERS], memory: &mut [u16; MEMORY_SIZE]) -> u32 {
+        // Safety: the block only reads and writes the first ten registers,
+        // the memory word at any u16 address and the code map entry at any
+        // u16 address, and all three are big enough for that.
+        unsafe { (block.func)(registers.as_mut_ptr(), memory.as_mut_ptr(), self.code_map.as_ptr()) }
+    }
+
+    // Throw away every compiled block covering a code address that is
+    // being written to. Code that modifies itself is left to the
+    // interpreter from then on.
+    pub(crate) fn invalidate(&mut self, address: u16) {
+        for distance in 0..MAX_BLOCK_LEN {
+            let start = address.wrapping_sub(distance);
+            if let Some(block) = self.blocks[start as usize] {
+                if block.len > distance {
+                    self.blocks[start as usize] = None;
+                    self.hits[start as usize] = NEVER_COMPILE;
+                }
+            }
+        }
+    }
+
+    #[cfg(test)]
+    pub(crate) fn compiled_blocks(&self) -> usize {
+        self.blocks.iter().filter(|block| block.is_some()).count()
+    }
+}
+
+fn scan_block(memory: &[u16], start: u16) -> Vec<Instruction> {
+    let mut instructions = Vec::new();
+    let mut address = start;
+
+    while address < MMIO_START && instructions.len() < MAX_BLOCK_LEN as usize {
+        let instruction = Instruction::decode(memory[address as usize]);
+        match instruction {
+            Instruction::Trap { .. } | Instruction::Rti | Instruction::Res => break,
+            Instruction::Br { .. }
+            | Instruction::Jmp { .. }
+            | Instruction::Jsr { .. }
+            | Instruction::Jsrr { .. } => {
+                instructions.push(instruction);
+                break;
+            }
+            _ => instructions.push(instruction),
+        }
+        address += 1;
+    }
+
+    instructions
+}
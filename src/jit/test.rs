@@ -0,0 +1,269 @@
+// Differential tests: every program is run once interpreted and once with
+// the JIT, and the two runs have to end in exactly the same state.
+
+use super::MMIO_START;
+use crate::error::LC3Result;
+use crate::fixtures::sum_program;
+use crate::io::ScriptedIOHandle;
+use crate::limits::Limits;
+use crate::plugin::NoPlugins;
+use crate::register::Register::RCount;
+use crate::vm::VM;
+
+#[derive(Debug, PartialEq)]
+struct Outcome {
+    result: String,
+    registers: Vec<u16>,
+    memory: Vec<u16>,
+    output: String,
+    instructions: u64,
+}
+
+// Returns the outcome and the number of blocks the JIT compiled
+fn run(program: &[u16], io_handle: ScriptedIOHandle, limits: Limits, jit: bool) -> LC3Result<(Outcome, usize)> {
+    let mut vm = VM::new_with_plugins(io_handle, NoPlugins);
+    vm.set_limits(limits);
+    vm.load_program(program)?;
+    if jit {
+        vm.enable_jit()?;
+    }
+    let result = format!("{:?}", vm.run());
+
+    let registers = (0..RCount.to_u8())
+        .map(|index| vm.reg_index_read(index))
+        .collect::<LC3Result<_>>()?;
+    let memory = (0..MMIO_START)
+        .map(|address| vm.mem_read(address))
+        .collect::<LC3Result<_>>()?;
+    let compiled = vm.jit().map_or(0, |jit| jit.compiled_blocks());
+
+    let outcome = Outcome {
+        result,
+        registers,
+        memory,
+        output: vm.io_handle().output(),
+        instructions: vm.instructions_executed(),
+    };
+
+    Ok((outcome, compiled))
+}
+
+// Run a program both ways, check they agree and return the JIT's outcome
+fn run_both(program: &[u16], io_handle: fn() -> ScriptedIOHandle, limits: Limits) -> LC3Result<(Outcome, usize)> {
+    let (interpreted, _) = run(program, io_handle(), limits, false)?;
+    let (compiled, blocks) = run(program, io_handle(), limits, true)?;
+    assert_eq!(interpreted, compiled);
+
+    Ok((compiled, blocks))
+}
+
+fn check_program(program: &[u16]) -> LC3Result<(Outcome, usize)> {
+    run_both(program, ScriptedIOHandle::new, Limits::new())
+}
+
+#[test]
+fn can_compile_arithmetic_loop() -> LC3Result<()> {
+    let program = vec![
+        // AND R0, R0, #0
+        0b0101_0000_0010_0000,
+        // LD R1, the loop count below
+        0b0010_0010_0000_0100,
+        // ADD R0, R0, #3
+        0b0001_0000_0010_0011,
+        // ADD R1, R1, #-1
+        0b0001_0010_0111_1111,
+        // BRp back to the first ADD
+        0b0000_0011_1111_1101,
+        // HALT
+        0xF025,
+        1000,
+    ];
+
+    let (outcome, blocks) = check_program(&program)?;
+    assert_eq!(outcome.registers[0], 3000);
+    assert!(blocks > 0);
+
+    Ok(())
+}
+
+#[test]
+fn can_compile_memory_copy() -> LC3Result<()> {
+    let program = vec![
+        // LD R1, SRC
+        0b0010_0010_0000_1111,
+        // LD R2, DST
+        0b0010_0100_0000_1111,
+        // LD R3, COUNT
+        0b0010_0110_0000_1111,
+        // FILL: STR R3, R1, #0
+        0b0111_0110_0100_0000,
+        // ADD R1, R1, #1
+        0b0001_0010_0110_0001,
+        // ADD R3, R3, #-1
+        0b0001_0110_1111_1111,
+        // BRp FILL
+        0b0000_0011_1111_1100,
+        // LD R1, SRC
+        0b0010_0010_0000_1000,
+        // LD R3, COUNT
+        0b0010_0110_0000_1001,
+        // COPY: LDR R4, R1, #0
+        0b0110_1000_0100_0000,
+        // STR R4, R2, #0
+        0b0111_1000_1000_0000,
+        // ADD R1, R1, #1
+        0b0001_0010_0110_0001,
+        // ADD R2, R2, #1
+        0b0001_0100_1010_0001,
+        // ADD R3, R3, #-1
+        0b0001_0110_1111_1111,
+        // BRp COPY
+        0b0000_0011_1111_1010,
+        // HALT
+        0xF025,
+        // SRC, DST and COUNT
+        0x4000,
+        0x5000,
+        100,
+    ];
+
+    let (outcome, blocks) = check_program(&program)?;
+    assert_eq!(outcome.memory[0x5000], 100);
+    assert_eq!(outcome.memory[0x5063], 1);
+    assert!(blocks > 0);
+
+    Ok(())
+}
+
+#[test]
+fn can_compile_recursion() -> LC3Result<()> {
+    let (outcome, blocks) = check_program(&sum_program(50))?;
+    assert_eq!(outcome.registers[0], 1275);
+    assert!(blocks > 0);
+
+    Ok(())
+}
+
+#[test]
+fn can_handle_self_modifying_code() -> LC3Result<()> {
+    // Runs a loop long enough for it to be compiled, then overwrites the
+    // ADD in its body and runs it again
+    let program = vec![
+        // LD R1, COUNT
+        0b0010_0010_0000_1011,
+        // LOOP: ADD R0, R0, #1
+        0b0001_0000_0010_0001,
+        // ADD R1, R1, #-1
+        0b0001_0010_0111_1111,
+        // BRp LOOP
+        0b0000_0011_1111_1101,
+        // ADD R2, R2, #0 then BRp DONE
+        0b0001_0100_1010_0000,
+        0b0000_0010_0000_0101,
+        // LD R3, NEWOP then ST R3, LOOP
+        0b0010_0110_0000_0110,
+        0b0011_0111_1111_1001,
+        // ADD R2, R2, #1
+        0b0001_0100_1010_0001,
+        // LD R1, COUNT then BRnzp LOOP
+        0b0010_0010_0000_0010,
+        0b0000_1111_1111_0110,
+        // DONE: HALT
+        0xF025,
+        // COUNT
+        40,
+        // NEWOP: ADD R0, R0, #2
+        0b0001_0000_0010_0010,
+    ];
+
+    let (outcome, blocks) = check_program(&program)?;
+    assert_eq!(outcome.registers[0], 120);
+    assert!(blocks > 0);
+
+    Ok(())
+}
+
+#[test]
+fn can_leave_mmio_to_interpreter() -> LC3Result<()> {
+    // Polls the keyboard status register and echoes two keys
+    let program = vec![
+        // LD R1, COUNT
+        0b0010_0010_0000_0111,
+        // POLL: LDI R0, KBSR then BRzp POLL
+        0b1010_0000_0000_0111,
+        0b0000_0111_1111_1110,
+        // LDI R0, KBDR then OUT
+        0b1010_0000_0000_0110,
+        0xF021,
+        // ADD R1, R1, #-1 then BRp POLL
+        0b0001_0010_0111_1111,
+        0b0000_0011_1111_1010,
+        // HALT
+        0xF025,
+        // COUNT, KBSR and KBDR
+        2,
+        0xFE00,
+        0xFE02,
+    ];
+
+    let io_handle = || {
+        let mut io_handle = ScriptedIOHandle::new();
+        io_handle.add_timed_key(500, 'h');
+        io_handle.add_timed_key(900, 'i');
+        io_handle
+    };
+    let (outcome, _) = run_both(&program, io_handle, Limits::new())?;
+    assert_eq!(outcome.output, "hi");
+
+    Ok(())
+}
+
+#[test]
+fn can_stop_at_instruction_limit() -> LC3Result<()> {
+    // AND R0, R0, #0 to set the flags, then BRnzp to itself
+    let program = vec![0b0101_0000_0010_0000, 0b0000_1111_1111_1111];
+
+    let limits = Limits::new().max_instructions(1001);
+    let (outcome, blocks) = run_both(&program, ScriptedIOHandle::new, limits)?;
+    assert_eq!(outcome.instructions, 1001);
+    assert!(blocks > 0);
+
+    Ok(())
+}
+
+// Random programs made of everything the JIT compiles except the register
+// jumps, which could send the program counter off the end of memory.
+// Between them they read and write all over memory, including their own
+// code, and most run until the instruction limit.
+#[test]
+fn can_match_interpreter_on_random_programs() -> LC3Result<()> {
+    const OP_CODES: [u16; 12] = [0x0, 0x1, 0x2, 0x3, 0x4, 0x5, 0x6, 0x7, 0x9, 0xA, 0xB, 0xE];
+
+    let mut seed: u32 = 0x2545_F491;
+    let mut next = || {
+        seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+        (seed >> 16) as u16
+    };
+
+    for _ in 0..200 {
+        let program: Vec<u16> = (0..48)
+            .map(|_| {
+                let op_code = OP_CODES[next() as usize % OP_CODES.len()];
+                let word = (op_code << 12) | (next() & 0x0FFF);
+                match op_code {
+                    // Short backward branches, so the programs loop enough
+                    // for blocks to get hot
+                    0x0 => (word & 0x0E00) | (0u16.wrapping_sub(1 + next() % 12) & 0x01FF),
+                    // Only JSR, never JSRR
+                    0x4 => word | 0x0800,
+                    _ => word,
+                }
+            })
+            .collect();
+
+        let limits = Limits::new().max_instructions(3000);
+        run_both(&program, ScriptedIOHandle::new, limits)?;
+    }
+
+    Ok(())
+}
@@ -3,17 +3,35 @@ extern crate crossterm;
 pub mod cli;
 mod command;
 mod condition_flags;
+pub mod device;
 pub mod error;
+pub mod expect;
+#[cfg(test)]
+mod fixtures;
+pub mod grade;
 pub mod io;
+#[cfg(feature = "jit")]
+mod jit;
+pub mod limits;
+pub mod network;
 mod op;
 pub mod plugin;
+pub mod protection;
 mod register;
+pub mod source;
+pub mod stats;
+pub mod symbols;
+pub mod trace;
+pub mod translate;
 mod trap;
 #[macro_use]
 mod utils;
 pub mod vm;
 
 pub use error::{LC3Error, LC3Result};
-pub use io::IOHandle;
-pub use plugin::{Event, Plugin};
+pub use expect::Expect;
+pub use io::{IOHandle, ScriptedIOHandle};
+pub use limits::Limits;
+pub use plugin::{Event, NoPlugins, Plugin, PluginSet};
+pub use stats::RunStats;
 pub use vm::VM;
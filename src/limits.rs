@@ -0,0 +1,32 @@
+use std::time::Duration;
+
+// Bounds on how much work a single run of the VM may do, so a runaway
+// program aborts with an error instead of hanging. Unset fields are
+// unlimited.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Limits {
+    pub max_instructions: Option<u64>,
+    pub max_duration: Option<Duration>,
+    pub max_output_chars: Option<u64>,
+}
+
+impl Limits {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn max_instructions(mut self, max: u64) -> Self {
+        self.max_instructions = Some(max);
+        self
+    }
+
+    pub fn max_duration(mut self, max: Duration) -> Self {
+        self.max_duration = Some(max);
+        self
+    }
+
+    pub fn max_output_chars(mut self, max: u64) -> Self {
+        self.max_output_chars = Some(max);
+        self
+    }
+}
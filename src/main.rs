@@ -1,23 +1,418 @@
-use lc3rs::cli:: {Options, read_program};
-use lc3rs::error::{BoxErrors, PublicResult};
+use lc3rs::cli:: {Command, GradeOptions, NetworkOptions, Options, ProfileFormat, ReportFormat, TraceFormat, TraceOptions, TranslateOptions, read_program};
+use lc3rs::device::{
+    ClockSource, Disk, Frame, FrameCapture, Interrupt, RandomNumbers, RealTimeClock, Serial, Timer, FRAMEBUFFER_CONTROL,
+};
+use lc3rs::error::{BoxErrors, LC3Error, PublicResult};
+use lc3rs::grade::{run_suite, TestSpec};
+use lc3rs::io::ScriptedIOHandle;
+use lc3rs::limits::Limits;
+use lc3rs::network::{Link, Network, Scheduler};
+use lc3rs::trace::{to_json, to_text, TraceFilter, TraceReader};
+use lc3rs::translate::translate;
+use lc3rs::source::LineMap;
+use lc3rs::stats::RunStats;
+use lc3rs::symbols::SymbolTable;
 use lc3rs::vm::VM;
 use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::io::{BufReader, BufWriter, Write};
+use std::time::Instant;
+use lc3rs::plugin::coverage::{Coverage, CoverageData};
 use lc3rs::plugin::debuglogger::DebugLogger;
+use lc3rs::plugin::jsonlogger::{EventFilter, JsonLogger};
+use lc3rs::plugin::profiler::Profiler;
+use lc3rs::plugin::sanitizer::Sanitizer;
+use lc3rs::plugin::stackcheck::StackChecker;
+use lc3rs::plugin::timeline::Timeline;
+use lc3rs::plugin::tracer::Tracer;
 use structopt::StructOpt;
 
+// Exit code for a grading run where at least one case didn't pass. Kept
+// apart from the 1 of a crash or usage error, so CI can tell a failing
+// program from a broken grader.
+const EXIT_CASES_FAILED: i32 = 2;
+// Exit code when a run is aborted by one of the --max-* limits. Matches
+// the exit code of coreutils timeout.
+const EXIT_LIMIT_EXCEEDED: i32 = 124;
+// Instruction limit for each node of a network run when --max-instructions
+// isn't given. A node waiting on a peer that has already halted would
+// otherwise keep the network running forever.
+const DEFAULT_NODE_INSTRUCTIONS: u64 = 10_000_000;
+// Exit code when the user stops a run with Ctrl-C, as a shell reports a
+// program killed by SIGINT
+const EXIT_INTERRUPTED: i32 = 130;
+
 fn main() -> PublicResult<()> {
     let options = Options::from_args();
-    let program = read_program(&options.path, options.little_endian)?;
+
+    match options.command {
+        Some(Command::Grade(grade_options)) => grade(grade_options),
+        Some(Command::Translate(translate_options)) => translate_program(translate_options),
+        Some(Command::Trace(trace_options)) => view_trace(trace_options),
+        Some(Command::Network(network_options)) => run_network(network_options),
+        None => run(options),
+    }
+}
+
+fn run(options: Options) -> PublicResult<()> {
+    let path = match &options.path {
+        Some(path) => path,
+        None => {
+            Options::clap().print_help()?;
+            println!();
+            return Ok(());
+        }
+    };
+    let program = read_program(path, options.little_endian)?;
 
     let mut vm = VM::new();
+    vm.set_limits(options.limits());
+    #[cfg(feature = "jit")]
+    if options.jit {
+        vm.enable_jit()?;
+    }
 
-    if let Some(path) = options.debug_log_path {
+    if let Some(path) = &options.debug_log_path {
         let debug_file = File::create(path)?;
         let logger = DebugLogger::new(debug_file);
         vm.add_plugin(Box::new(logger));
     }
+    let mut event_log = None;
+    if let Some(path) = &options.event_log {
+        let filter = EventFilter {
+            kinds: (!options.event_kinds.is_empty()).then(|| options.event_kinds.clone()),
+            pc: options.event_pc,
+            memory: options.event_memory,
+        };
+        let logger = JsonLogger::with_filter(File::create(path)?, filter);
+        event_log = Some(logger.log());
+        vm.add_plugin(Box::new(logger));
+    }
+
+    let symbols = match &options.symbols {
+        Some(path) => SymbolTable::from_file(path)?,
+        None => SymbolTable::new(),
+    };
+    let profile = options.profile.as_ref().map(|_| {
+        let profiler = Profiler::new();
+        let profile = profiler.profile();
+        vm.add_plugin(Box::new(profiler));
+        profile
+    });
+    let coverage = (options.coverage.is_some() || options.listing.is_some()).then(|| {
+        let coverage = Coverage::new();
+        let data = coverage.data();
+        vm.add_plugin(Box::new(coverage));
+        data
+    });
+    let sanitizer = options.sanitize.then(|| {
+        let sanitizer = Sanitizer::new();
+        let report = sanitizer.report();
+        vm.add_plugin(Box::new(sanitizer));
+        report
+    });
+    let stack_report = options.stack_check.then(|| {
+        let checker = match options.stack_guard {
+            Some(guard) => StackChecker::with_guard(guard),
+            None => StackChecker::new(),
+        };
+        let report = checker.report();
+        vm.add_plugin(Box::new(checker));
+        report
+    });
+    let timeline = options.timeline.as_ref().map(|_| {
+        let timeline = Timeline::new();
+        let data = timeline.data();
+        vm.add_plugin(Box::new(timeline));
+        data
+    });
+
+    let trace = match &options.trace {
+        Some(path) => {
+            let tracer = Tracer::new(BufWriter::new(File::create(path)?), options.trace_compress)?;
+            let trace = tracer.trace();
+            vm.add_plugin(Box::new(tracer));
+            Some(trace)
+        }
+        None => None,
+    };
+
+    if let Some(address) = options.timer {
+        let interrupt = Interrupt {
+            vector: options.timer_vector,
+            priority: options.timer_priority,
+        };
+        let timer = Timer::new(address, options.timer_mode, interrupt);
+        vm.add_device(timer.addresses(), Box::new(timer))?;
+    }
+
+    if let Some(path) = &options.disk {
+        let interrupt = Interrupt {
+            vector: options.disk_vector,
+            priority: options.disk_priority,
+        };
+        let disk = Disk::open(options.disk_address, path, options.disk_latency, interrupt)?;
+        vm.add_device(disk.addresses(), Box::new(disk))?;
+    }
+
+    if let Some(address) = options.rtc {
+        let source = match options.rtc_start {
+            Some(start) => ClockSource::Simulated {
+                start,
+                instructions_per_second: options.rtc_rate,
+            },
+            None => ClockSource::System,
+        };
+        let clock = RealTimeClock::new(address, source);
+        vm.add_device(clock.addresses(), Box::new(clock))?;
+    }
+
+    if let Some(address) = options.rng {
+        vm.add_device(address..=address, Box::new(RandomNumbers::new(options.rng_seed)))?;
+    }
+
+    if let Some(endpoint) = &options.serial {
+        let interrupt = Interrupt {
+            vector: options.serial_vector,
+            priority: options.serial_priority,
+        };
+        let serial = Serial::open(options.serial_address, endpoint, interrupt)?;
+        vm.add_device(serial.addresses(), Box::new(serial))?;
+    }
+
+    if options.framebuffer.is_some() || options.framebuffer_terminal {
+        let path = options.framebuffer.clone();
+        let terminal = options.framebuffer_terminal;
+        let mut frames = 0;
+        let sink = move |frame: &Frame| {
+            frames += 1;
+            if let Some(path) = &path {
+                frame.save(numbered_path(path, frames))?;
+            }
+            if terminal {
+                frame.write_terminal(&mut std::io::stdout()).map_io_error()?;
+            }
+            Ok(())
+        };
+        vm.add_device(FRAMEBUFFER_CONTROL..=FRAMEBUFFER_CONTROL, Box::new(FrameCapture::new(Box::new(sink))))?;
+    }
 
     vm.load_program(&program)?;
+    for region in &options.protect {
+        vm.protect(region.addresses.start..=region.addresses.end, region.protection);
+    }
+
+    let started_at = Instant::now();
+    let result = vm.run();
+    let stats = RunStats::new(vm.instructions_executed(), started_at.elapsed());
+    let showing_frame = options.framebuffer.is_some() || options.framebuffer_terminal;
+    let frame = showing_frame.then(|| Frame::from_memory(|address| vm.peek_memory(address)));
+    // Dropping the VM takes the terminal out of raw mode before anything
+    // else is printed
+    drop(vm);
+    if let Some(trace) = trace {
+        trace.borrow_mut().finish()?;
+    }
+    if let Some(log) = event_log {
+        log.borrow_mut().finish()?;
+    }
+    if options.stats {
+        eprintln!("{}", stats);
+    }
+    if let (Some(path), Some(frame)) = (&options.framebuffer, &frame) {
+        frame.save(path)?;
+    }
+    if let (true, Some(frame)) = (options.framebuffer_terminal, &frame) {
+        frame.write_terminal(&mut std::io::stdout())?;
+    }
+    if let (Some(path), Some(profile)) = (&options.profile, profile) {
+        let profile = profile.borrow();
+        let report = match options.profile_format {
+            ProfileFormat::Flat => profile.flat_report(&symbols),
+            ProfileFormat::CallGraph => profile.call_graph_report(&symbols),
+            ProfileFormat::Folded => profile.folded_stacks(&symbols),
+        };
+        std::fs::write(path, report)?;
+    }
+    if let (Some(path), Some(timeline)) = (&options.timeline, timeline) {
+        std::fs::write(path, timeline.borrow().chrome_trace(&symbols))?;
+    }
+    if let Some(report) = sanitizer {
+        eprint!("{}", report.borrow().text_report(&symbols));
+    }
+    if let Some(report) = stack_report {
+        eprint!("{}", report.borrow().text_report(&symbols));
+    }
+    if let Some(data) = coverage {
+        write_coverage(&options, path, &program, &symbols, &data.borrow())?;
+    }
+
+    match result {
+        Err(err) if err.is_limit() => {
+            eprintln!("{}", err);
+            std::process::exit(EXIT_LIMIT_EXCEEDED);
+        }
+        Err(LC3Error::Interrupted) => {
+            eprintln!("{}", LC3Error::Interrupted);
+            std::process::exit(EXIT_INTERRUPTED);
+        }
+        result => result.box_error(),
+    }
+}
+
+// screen.png becomes screen-0001.png for the first frame
+fn numbered_path(path: &str, number: usize) -> PathBuf {
+    let path = Path::new(path);
+    let stem = path.file_stem().and_then(|stem| stem.to_str()).unwrap_or("frame");
+    let name = match path.extension().and_then(|extension| extension.to_str()) {
+        Some(extension) => format!("{}-{:04}.{}", stem, number, extension),
+        None => format!("{}-{:04}", stem, number),
+    };
+    path.with_file_name(name)
+}
+
+fn write_coverage(
+    options: &Options,
+    program_path: &str,
+    program: &[u16],
+    symbols: &SymbolTable,
+    data: &CoverageData,
+) -> PublicResult<()> {
+    let source = match &options.source {
+        Some(path) => Some(std::fs::read_to_string(path)?),
+        None => None,
+    };
+    let lines = match (&options.line_map, &source) {
+        (Some(path), _) => Some(LineMap::from_file(path)?),
+        (None, Some(source)) => Some(LineMap::from_assembly(source, symbols)),
+        (None, None) => None,
+    };
+
+    if let Some(path) = &options.coverage {
+        let source_path = options.source.as_deref().unwrap_or(program_path);
+        std::fs::write(path, data.lcov(source_path, program, lines.as_ref()))?;
+    }
+
+    if let Some(path) = &options.listing {
+        let listing = match (&source, &lines) {
+            (Some(source), Some(lines)) => data.annotated_source(source, program, lines),
+            _ => data.annotated_listing(program, symbols),
+        };
+        std::fs::write(path, listing)?;
+    }
+
+    Ok(())
+}
+
+fn grade(options: GradeOptions) -> PublicResult<()> {
+    let program = read_program(&options.path, options.little_endian)?;
+    let spec = TestSpec::from_file(&options.spec_path)?;
+    let report = run_suite(&program, &spec)?;
+
+    let rendered = match options.format {
+        ReportFormat::Json => report.to_json(),
+        ReportFormat::JUnit => report.to_junit_xml(),
+    };
+
+    match options.output {
+        Some(path) => std::fs::write(path, rendered)?,
+        None => print!("{}", rendered),
+    }
+
+    if !report.all_passed() {
+        std::process::exit(EXIT_CASES_FAILED);
+    }
+
+    Ok(())
+}
 
-    vm.run().box_error()
+fn translate_program(options: TranslateOptions) -> PublicResult<()> {
+    let program = read_program(&options.path, options.little_endian)?;
+    let source = translate(&program, options.lang).box_error()?;
+
+    match options.output {
+        Some(path) => std::fs::write(path, source)?,
+        None => print!("{}", source),
+    }
+
+    Ok(())
+}
+
+fn view_trace(options: TraceOptions) -> PublicResult<()> {
+    let reader = TraceReader::new(BufReader::new(File::open(&options.path)?))?;
+    let filter = TraceFilter {
+        pc: options.pc,
+        writes: options.writes,
+        after: options.after,
+        until: options.until,
+        search: options.search,
+    };
+    let mut out: Box<dyn Write> = match &options.output {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(BufWriter::new(std::io::stdout().lock())),
+    };
+
+    let mut window = filter.start();
+    for (step, record) in reader.enumerate() {
+        let record = record?;
+        if !filter.matches(&record, &mut window) {
+            continue;
+        }
+        let line = match options.format {
+            TraceFormat::Text => to_text(step as u64, &record),
+            TraceFormat::Json => to_json(step as u64, &record),
+        };
+        writeln!(out, "{}", line)?;
+    }
+    out.flush()?;
+
+    Ok(())
+}
+
+fn run_network(options: NetworkOptions) -> PublicResult<()> {
+    let link = Link {
+        latency: options.latency,
+        drop_rate: options.drop_rate,
+    };
+    let network = Network::new(options.seed);
+    network.connect_topology(options.topology, options.paths.len() as u16, link);
+
+    let mut scheduler = Scheduler::new(network.clone());
+    for (node, path) in options.paths.iter().enumerate() {
+        let program = read_program(path, options.little_endian)?;
+        let interrupt = Interrupt {
+            vector: options.vector,
+            priority: options.priority,
+        };
+        let interface = network.interface(options.address, node as u16, interrupt);
+
+        // Nodes share the terminal, so each prints to a buffer shown when
+        // the network stops
+        let mut vm = VM::new_with_io(ScriptedIOHandle::new());
+        let max_instructions = options.max_instructions.unwrap_or(DEFAULT_NODE_INSTRUCTIONS);
+        vm.set_limits(Limits::new().max_instructions(max_instructions));
+        vm.add_device(interface.addresses(), Box::new(interface))?;
+        vm.load_program(&program)?;
+        scheduler.add(vm);
+    }
+
+    let result = scheduler.run();
+    let rounds = scheduler.rounds();
+    for (node, vm) in scheduler.into_vms().into_iter().enumerate() {
+        println!("--- node {} ---", node);
+        println!("{}", vm.into_io_handle().into_output());
+    }
+    let stats = network.stats();
+    eprintln!(
+        "{} rounds, {} words sent, {} delivered, {} dropped",
+        rounds, stats.sent, stats.delivered, stats.dropped
+    );
+
+    match result {
+        Err(err) if err.is_limit() => {
+            eprintln!("{}", err);
+            std::process::exit(EXIT_LIMIT_EXCEEDED);
+        }
+        result => result.box_error(),
+    }
 }
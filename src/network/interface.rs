@@ -0,0 +1,94 @@
+use std::ops::RangeInclusive;
+
+use super::Network;
+use crate::device::{Device, DeviceContext, Interrupt};
+use crate::error::LC3Result;
+
+// Offsets of the interface's registers from its base address
+pub const NET_RX_STATUS: u16 = 0;
+pub const NET_RX_DATA: u16 = 1;
+pub const NET_RX_SOURCE: u16 = 2;
+pub const NET_TX_DATA: u16 = 3;
+pub const NET_TX_DESTINATION: u16 = 4;
+pub const NET_NODE: u16 = 5;
+const NUM_REGISTERS: u16 = 6;
+
+// Receive status bits. Ready is set while a word is waiting. Writing to the
+// receive status sets whether the interface interrupts while one is.
+pub const NET_READY: u16 = 1 << 15;
+pub const NET_INTERRUPT_ENABLE: u16 = 1 << 14;
+
+// A node's connection to a Network. Words arrive in the order they were
+// delivered, and the data register reads the next one, after which the
+// source register says who sent the one after that. Writing the data
+// register sends a word to the node in the destination register. The node
+// register reads the node's own number, so every node can run the same
+// program.
+pub struct NetworkInterface {
+    base: u16,
+    node: u16,
+    network: Network,
+    interrupt: Interrupt,
+    interrupt_enabled: bool,
+    destination: u16,
+}
+
+impl NetworkInterface {
+    pub fn new(base: u16, node: u16, network: Network, interrupt: Interrupt) -> Self {
+        Self {
+            base,
+            node,
+            network,
+            interrupt,
+            interrupt_enabled: false,
+            destination: 0,
+        }
+    }
+
+    // The addresses to map the interface over
+    pub fn addresses(&self) -> RangeInclusive<u16> {
+        self.base..=self.base.saturating_add(NUM_REGISTERS - 1)
+    }
+
+    fn ready(&self) -> bool {
+        self.network.peek(self.node).is_some()
+    }
+}
+
+impl Device for NetworkInterface {
+    fn read(&mut self, address: u16, _: &mut dyn DeviceContext) -> LC3Result<u16> {
+        let value = match address.wrapping_sub(self.base) {
+            NET_RX_STATUS => {
+                let ready = if self.ready() { NET_READY } else { 0 };
+                let enabled = if self.interrupt_enabled { NET_INTERRUPT_ENABLE } else { 0 };
+                ready | enabled
+            }
+            NET_RX_DATA => self.network.receive(self.node).map_or(0, |(_, word)| word),
+            NET_RX_SOURCE => self.network.peek(self.node).map_or(0, |(source, _)| source),
+            NET_TX_DESTINATION => self.destination,
+            NET_NODE => self.node,
+            _ => 0,
+        };
+
+        Ok(value)
+    }
+
+    fn write(&mut self, address: u16, value: u16, _: &mut dyn DeviceContext) -> LC3Result<()> {
+        match address.wrapping_sub(self.base) {
+            NET_RX_STATUS => self.interrupt_enabled = value & NET_INTERRUPT_ENABLE != 0,
+            NET_TX_DATA => self.network.send(self.node, self.destination, value),
+            NET_TX_DESTINATION => self.destination = value,
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    fn interrupts(&self) -> bool {
+        true
+    }
+
+    fn interrupt(&self) -> Option<Interrupt> {
+        (self.interrupt_enabled && self.ready()).then_some(self.interrupt)
+    }
+}
@@ -0,0 +1,215 @@
+mod interface;
+mod scheduler;
+#[cfg(test)]
+mod test;
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, VecDeque};
+use std::rc::Rc;
+use std::str::FromStr;
+
+use crate::device::{Interrupt, SplitMix64};
+
+pub use interface::{
+    NetworkInterface, NET_INTERRUPT_ENABLE, NET_NODE, NET_READY, NET_RX_DATA, NET_RX_SOURCE, NET_RX_STATUS,
+    NET_TX_DATA, NET_TX_DESTINATION,
+};
+pub use scheduler::Scheduler;
+
+// Sending to this node sends to every neighbour
+pub const BROADCAST: u16 = 0xFFFF;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Link {
+    // Rounds of the scheduler a word spends on the link after the one that
+    // sent it. At 0 it arrives before the next instruction.
+    pub latency: u64,
+    // Chance of each word being lost, from 0 to 1
+    pub drop_rate: f64,
+}
+
+impl Default for Link {
+    fn default() -> Self {
+        Self {
+            latency: 0,
+            drop_rate: 0.0,
+        }
+    }
+}
+
+// Ways of linking nodes 0 up to some number
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Topology {
+    // Every node to every other
+    Full,
+    // Each node to the next, and the last back to the first
+    Ring,
+    // Each node to the next
+    Line,
+    // Node 0 to every other
+    Star,
+}
+
+impl Topology {
+    // The pairs of nodes to link
+    pub fn links(&self, nodes: u16) -> Vec<(u16, u16)> {
+        let chain = (1..nodes).map(|node| (node - 1, node));
+        match self {
+            Topology::Full => (0..nodes)
+                .flat_map(|a| (a + 1..nodes).map(move |b| (a, b)))
+                .collect(),
+            // Two nodes only need the one link
+            Topology::Ring if nodes > 2 => chain.chain(std::iter::once((nodes - 1, 0))).collect(),
+            Topology::Ring | Topology::Line => chain.collect(),
+            Topology::Star => (1..nodes).map(|node| (0, node)).collect(),
+        }
+    }
+}
+
+impl FromStr for Topology {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text {
+            "full" => Ok(Self::Full),
+            "ring" => Ok(Self::Ring),
+            "line" => Ok(Self::Line),
+            "star" => Ok(Self::Star),
+            other => Err(format!("Unknown topology {:?}, expected full, ring, line or star", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct NetworkStats {
+    pub sent: u64,
+    pub delivered: u64,
+    // Lost on a link, or sent to a node with no link to the sender
+    pub dropped: u64,
+}
+
+struct Packet {
+    source: u16,
+    destination: u16,
+    word: u16,
+    arrives: u64,
+}
+
+struct NetworkState {
+    // Keyed by the lower node first
+    links: BTreeMap<(u16, u16), Link>,
+    // In the order they were sent, so delivery is too
+    in_flight: Vec<Packet>,
+    // Words waiting at each node with who sent them
+    inboxes: BTreeMap<u16, VecDeque<(u16, u16)>>,
+    round: u64,
+    random: SplitMix64,
+    stats: NetworkStats,
+}
+
+// A virtual network passing words between nodes, each a VM with a
+// NetworkInterface. Time on the network is the scheduler's rounds, so with
+// the same seed for deciding which words are lost a run always plays out
+// the same way. Clones share the same network.
+#[derive(Clone)]
+pub struct Network {
+    state: Rc<RefCell<NetworkState>>,
+}
+
+impl Network {
+    pub fn new(seed: u64) -> Self {
+        let state = NetworkState {
+            links: BTreeMap::new(),
+            in_flight: Vec::new(),
+            inboxes: BTreeMap::new(),
+            round: 0,
+            random: SplitMix64::new(seed),
+            stats: NetworkStats::default(),
+        };
+
+        Self {
+            state: Rc::new(RefCell::new(state)),
+        }
+    }
+
+    // Link two nodes both ways, replacing any link already between them
+    pub fn connect(&self, a: u16, b: u16, link: Link) {
+        self.state.borrow_mut().links.insert((a.min(b), a.max(b)), link);
+    }
+
+    // Link nodes 0 up to but not including nodes in the topology's shape
+    pub fn connect_topology(&self, topology: Topology, nodes: u16, link: Link) {
+        for (a, b) in topology.links(nodes) {
+            self.connect(a, b, link);
+        }
+    }
+
+    // A device to map into node's VM, with its registers from base
+    pub fn interface(&self, base: u16, node: u16, interrupt: Interrupt) -> NetworkInterface {
+        NetworkInterface::new(base, node, self.clone(), interrupt)
+    }
+
+    pub fn stats(&self) -> NetworkStats {
+        self.state.borrow().stats
+    }
+
+    fn neighbours(&self, node: u16) -> Vec<u16> {
+        let state = self.state.borrow();
+        let ends = state.links.keys().filter(|(a, b)| *a == node || *b == node);
+        ends.map(|&(a, b)| if a == node { b } else { a }).collect()
+    }
+
+    pub(crate) fn send(&self, source: u16, destination: u16, word: u16) {
+        let destinations = match destination {
+            BROADCAST => self.neighbours(source),
+            destination => vec![destination],
+        };
+
+        let mut state = self.state.borrow_mut();
+        for destination in destinations {
+            state.stats.sent += 1;
+            let link = state.links.get(&(source.min(destination), source.max(destination))).copied();
+            let link = match link {
+                Some(link) if state.random.next_fraction() >= link.drop_rate => link,
+                _ => {
+                    state.stats.dropped += 1;
+                    continue;
+                }
+            };
+            let arrives = state.round + 1 + link.latency;
+            state.in_flight.push(Packet {
+                source,
+                destination,
+                word,
+                arrives,
+            });
+        }
+    }
+
+    // Start a round of the scheduler, delivering the words due by then
+    pub(crate) fn advance(&self, round: u64) {
+        let mut state = self.state.borrow_mut();
+        state.round = round;
+
+        let (due, in_flight) = std::mem::take(&mut state.in_flight)
+            .into_iter()
+            .partition(|packet| packet.arrives <= round);
+        state.in_flight = in_flight;
+        for packet in due {
+            state.stats.delivered += 1;
+            let inbox = state.inboxes.entry(packet.destination).or_default();
+            inbox.push_back((packet.source, packet.word));
+        }
+    }
+
+    // The next word waiting at node and who sent it
+    pub(crate) fn peek(&self, node: u16) -> Option<(u16, u16)> {
+        let state = self.state.borrow();
+        state.inboxes.get(&node).and_then(|inbox| inbox.front().copied())
+    }
+
+    pub(crate) fn receive(&self, node: u16) -> Option<(u16, u16)> {
+        let mut state = self.state.borrow_mut();
+        state.inboxes.get_mut(&node).and_then(|inbox| inbox.pop_front())
+    }
+}
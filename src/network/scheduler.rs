@@ -0,0 +1,90 @@
+use super::Network;
+use crate::error::{LC3Error, LC3Result};
+use crate::io::IOHandle;
+use crate::plugin::{DynPlugins, PluginSet};
+use crate::vm::VM;
+
+// Runs VMs on a network in lockstep. Each round delivers the words due on
+// the network, then steps every VM still running by one instruction in
+// the order they were added, so a run doesn't depend on the host's timing.
+// Each VM keeps its own IO handle, plugins and limits.
+pub struct Scheduler<IOType: IOHandle, Plugins = DynPlugins<IOType>> {
+    network: Network,
+    vms: Vec<VM<IOType, Plugins>>,
+    round: u64,
+}
+
+impl<IOType: IOHandle, Plugins: PluginSet<IOType>> Scheduler<IOType, Plugins> {
+    pub fn new(network: Network) -> Self {
+        Self {
+            network,
+            vms: Vec::new(),
+            round: 0,
+        }
+    }
+
+    // Add a VM with its program loaded and its interface mapped, returning
+    // its index
+    pub fn add(&mut self, vm: VM<IOType, Plugins>) -> usize {
+        self.vms.push(vm);
+        self.vms.len() - 1
+    }
+
+    pub fn vms(&self) -> &[VM<IOType, Plugins>] {
+        &self.vms
+    }
+
+    pub fn into_vms(self) -> Vec<VM<IOType, Plugins>> {
+        self.vms
+    }
+
+    pub fn rounds(&self) -> u64 {
+        self.round
+    }
+
+    pub fn start(&mut self) -> LC3Result<()> {
+        self.round = 0;
+        for (index, vm) in self.vms.iter_mut().enumerate() {
+            vm.start().map_err(|err| node_error(index, err))?;
+        }
+
+        Ok(())
+    }
+
+    // Run a round, returning false once every VM has halted. An error
+    // from any VM stops the whole network, and says which VM it came from.
+    pub fn step(&mut self) -> LC3Result<bool> {
+        self.round += 1;
+        self.network.advance(self.round);
+
+        let mut running = false;
+        for (index, vm) in self.vms.iter_mut().enumerate() {
+            running |= step_vm(vm).map_err(|err| node_error(index, err))?;
+        }
+
+        Ok(running)
+    }
+
+    pub fn run(&mut self) -> LC3Result<()> {
+        self.start()?;
+        while self.step()? {}
+
+        Ok(())
+    }
+}
+
+// Step the VM if it's still running, returning whether it still is
+fn step_vm<IOType: IOHandle, Plugins: PluginSet<IOType>>(vm: &mut VM<IOType, Plugins>) -> LC3Result<bool> {
+    if !vm.get_running()? {
+        return Ok(false);
+    }
+    vm.step()?;
+    vm.get_running()
+}
+
+fn node_error(index: usize, err: LC3Error) -> LC3Error {
+    LC3Error::Node {
+        index,
+        source: Box::new(err),
+    }
+}
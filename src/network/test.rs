@@ -0,0 +1,105 @@
+use super::{Link, Network, Scheduler, Topology};
+use crate::device::Interrupt;
+use crate::error::{LC3Error, LC3Result};
+use crate::io::ScriptedIOHandle;
+use crate::limits::Limits;
+use crate::vm::VM;
+
+const INTERFACE: u16 = 0xFE24;
+
+// Sends 41 to node 1 and waits for the reply in RESULT
+const CLIENT: [u16; 16] = [
+    // LD R0, ONE then STI R0, TX_DESTINATION
+    0x2008, 0xB00A,
+    // LD R0, WORD then STI R0, TX_DATA
+    0x2007, 0xB009,
+    // POLL: LDI R1, RX_STATUS then BRzp POLL
+    0xA206, 0x07FE,
+    // LDI R0, RX_DATA then ST R0, RESULT
+    0xA007, 0x3007,
+    // HALT
+    0xF025,
+    // ONE and WORD
+    1, 41,
+    // RX_STATUS, TX_DESTINATION, TX_DATA and RX_DATA
+    0xFE24, 0xFE28, 0xFE27, 0xFE25,
+    // RESULT
+    0,
+];
+
+// Waits for a word and sends it back to whoever sent it plus one
+const ECHO: [u16; 13] = [
+    // LOOP: LDI R1, RX_STATUS then BRzp LOOP
+    0xA207, 0x07FE,
+    // LDI R2, RX_SOURCE then STI R2, TX_DESTINATION
+    0xA406, 0xB406,
+    // LDI R0, RX_DATA then ADD R0, R0, #1 and STI R0, TX_DATA
+    0xA006, 0x1021, 0xB005,
+    // HALT
+    0xF025,
+    // RX_STATUS, RX_SOURCE, TX_DESTINATION, RX_DATA and TX_DATA
+    0xFE24, 0xFE26, 0xFE28, 0xFE25, 0xFE27,
+];
+
+fn client_and_echo(link: Link) -> LC3Result<Scheduler<ScriptedIOHandle>> {
+    let network = Network::new(7);
+    network.connect(0, 1, link);
+
+    let mut scheduler = Scheduler::new(network.clone());
+    for (node, program) in [&CLIENT[..], &ECHO[..]].iter().enumerate() {
+        let interface = network.interface(INTERFACE, node as u16, Interrupt { vector: 0x84, priority: 4 });
+        let mut vm = VM::new_with_io(ScriptedIOHandle::new());
+        vm.set_limits(Limits {
+            max_instructions: Some(200),
+            ..Limits::default()
+        });
+        vm.add_device(interface.addresses(), Box::new(interface))?;
+        vm.load_program(program)?;
+        scheduler.add(vm);
+    }
+
+    Ok(scheduler)
+}
+
+#[test]
+fn can_pass_words_between_vms_in_lockstep() -> LC3Result<()> {
+    let mut immediate = client_and_echo(Link::default())?;
+    immediate.run()?;
+    assert_eq!(immediate.vms()[0].peek_memory(0x300F), 42);
+
+    // Four rounds more on the way there and four on the way back
+    let link = Link {
+        latency: 4,
+        drop_rate: 0.0,
+    };
+    let mut delayed = client_and_echo(link)?;
+    delayed.run()?;
+    assert_eq!(delayed.vms()[0].peek_memory(0x300F), 42);
+    assert_eq!(delayed.rounds(), immediate.rounds() + 8);
+
+    Ok(())
+}
+
+#[test]
+fn can_drop_words() -> LC3Result<()> {
+    let link = Link {
+        latency: 0,
+        drop_rate: 1.0,
+    };
+    let mut scheduler = client_and_echo(link)?;
+    let result = scheduler.run();
+
+    // Both wait forever, and the client hits its limit first
+    assert!(matches!(result, Err(LC3Error::Node { index: 0, source }) if source.is_limit()));
+
+    Ok(())
+}
+
+#[test]
+fn can_link_topologies() {
+    assert_eq!(Topology::Full.links(3), [(0, 1), (0, 2), (1, 2)]);
+    assert_eq!(Topology::Ring.links(4), [(0, 1), (1, 2), (2, 3), (3, 0)]);
+    assert_eq!(Topology::Ring.links(2), [(0, 1)]);
+    assert_eq!(Topology::Line.links(3), [(0, 1), (1, 2)]);
+    assert_eq!(Topology::Star.links(3), [(0, 1), (0, 2)]);
+}
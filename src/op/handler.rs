@@ -1,20 +1,28 @@
+use super::Operand;
 use super::trap_handler as handle_trap;
-use crate::command::Command;
 use crate::error::{LC3Error, LC3Result};
 use crate::io::IOHandle;
+use crate::plugin::PluginSet;
 use crate::register::Register::{RCond, RPC, RR7};
 use crate::trap::TrapCode;
-use crate::utils::sign_extend;
 use crate::vm::VM;
 use crate::wrapping_add;
 
-pub(crate) fn branch<IO: IOHandle>(vm: &mut VM<IO>, command: &Command) -> LC3Result<()> {
-    let test_flag = command.bit_slice(4, 6)?;
+// The handlers take the fields the decoder already pulled out of the
+// instruction word, so none of them needs to look at the raw bits.
+
+fn read_operand<IO: IOHandle, P: PluginSet<IO>>(vm: &mut VM<IO, P>, operand: Operand) -> LC3Result<u16> {
+    match operand {
+        Operand::Register(index) => vm.reg_index_read(index),
+        Operand::Immediate(value) => Ok(value),
+    }
+}
+
+pub(crate) fn branch<IO: IOHandle, P: PluginSet<IO>>(vm: &mut VM<IO, P>, test_flag: u16, offset: u16) -> LC3Result<()> {
     let flags = vm.reg_read(RCond)?;
     let will_branch = (flags & test_flag) != 0;
 
     if will_branch {
-        let offset = sign_extend(command.bit_slice(7, 15)?, 9);
         let new_pc = wrapping_add!(vm.reg_read(RPC)?, offset);
         vm.reg_write(RPC, new_pc)?;
     };
@@ -22,16 +30,9 @@ pub(crate) fn branch<IO: IOHandle>(vm: &mut VM<IO>, command: &Command) -> LC3Res
     Ok(())
 }
 
-pub(crate) fn add<IO: IOHandle>(vm: &mut VM<IO>, command: &Command) -> LC3Result<()> {
-    let target_reg = command.bit_slice(4, 6)? as u8;
-    let left = vm.reg_index_read(command.bit_slice(7, 9)? as u8)?;
-    let immediate = command.bit_slice(10, 10)? == 1;
-
-    let right = if immediate {
-        sign_extend(command.bit_slice(11, 15)?, 5)
-    } else {
-        vm.reg_index_read(command.bit_slice(13, 15)? as u8)?
-    };
+pub(crate) fn add<IO: IOHandle, P: PluginSet<IO>>(vm: &mut VM<IO, P>, target_reg: u8, source: u8, operand: Operand) -> LC3Result<()> {
+    let left = vm.reg_index_read(source)?;
+    let right = read_operand(vm, operand)?;
 
     vm.reg_index_write(target_reg, wrapping_add!(left, right))?;
     vm.update_flags(target_reg as usize)?;
@@ -39,9 +40,7 @@ pub(crate) fn add<IO: IOHandle>(vm: &mut VM<IO>, command: &Command) -> LC3Result
     Ok(())
 }
 
-pub(crate) fn load<IO: IOHandle>(vm: &mut VM<IO>, command: &Command) -> LC3Result<()> {
-    let target_reg = command.bit_slice(4, 6)? as u8;
-    let offset = sign_extend(command.bit_slice(7, 15)?, 9);
+pub(crate) fn load<IO: IOHandle, P: PluginSet<IO>>(vm: &mut VM<IO, P>, target_reg: u8, offset: u16) -> LC3Result<()> {
     let pc = vm.reg_read(RPC)?;
     let address = wrapping_add!(pc, offset);
     let val = vm.mem_read(address)?;
@@ -51,9 +50,7 @@ pub(crate) fn load<IO: IOHandle>(vm: &mut VM<IO>, command: &Command) -> LC3Resul
     Ok(())
 }
 
-pub(crate) fn store<IO: IOHandle>(vm: &mut VM<IO>, command: &Command) -> LC3Result<()> {
-    let source = command.bit_slice(4, 6)? as u8;
-    let offset = sign_extend(command.bit_slice(7, 15)?, 9);
+pub(crate) fn store<IO: IOHandle, P: PluginSet<IO>>(vm: &mut VM<IO, P>, source: u8, offset: u16) -> LC3Result<()> {
     let target = wrapping_add!(vm.reg_read(RPC)?, offset);
     let val = vm.reg_index_read(source)?;
     vm.mem_write(target, val)?;
@@ -61,36 +58,31 @@ pub(crate) fn store<IO: IOHandle>(vm: &mut VM<IO>, command: &Command) -> LC3Resu
     Ok(())
 }
 
-pub(crate) fn jump_register<IO: IOHandle>(vm: &mut VM<IO>, command: &Command) -> LC3Result<()> {
+pub(crate) fn jump_subroutine<IO: IOHandle, P: PluginSet<IO>>(vm: &mut VM<IO, P>, offset: u16) -> LC3Result<()> {
     // Save program counter
     let pc = vm.reg_read(RPC)?;
     vm.reg_write(RR7, pc)?;
 
-    let offset_mode = command.bit_slice(4, 4)? == 1;
+    let destination = wrapping_add!(vm.reg_read(RPC)?, offset);
+    vm.reg_write(RPC, destination)?;
 
-    let destination = if offset_mode {
-        let offset = sign_extend(command.bit_slice(5, 15)?, 11);
-        wrapping_add!(vm.reg_read(RPC)?, offset)
-    } else {
-        let source_reg = command.bit_slice(7, 9)? as u8;
-        vm.reg_index_read(source_reg)?
-    };
+    Ok(())
+}
 
+pub(crate) fn jump_register<IO: IOHandle, P: PluginSet<IO>>(vm: &mut VM<IO, P>, base: u8) -> LC3Result<()> {
+    // Save program counter
+    let pc = vm.reg_read(RPC)?;
+    vm.reg_write(RR7, pc)?;
+
+    let destination = vm.reg_index_read(base)?;
     vm.reg_write(RPC, destination)?;
 
     Ok(())
 }
 
-pub(crate) fn and<IO: IOHandle>(vm: &mut VM<IO>, command: &Command) -> LC3Result<()> {
-    let target_reg = command.bit_slice(4, 6)? as u8;
-    let left = vm.reg_index_read(command.bit_slice(7, 9)? as u8)?;
-    let immediate = command.bit_slice(10, 10)? == 1;
-
-    let right = if immediate {
-        sign_extend(command.bit_slice(11, 15)?, 5)
-    } else {
-        vm.reg_index_read(command.bit_slice(13, 15)? as u8)?
-    };
+pub(crate) fn and<IO: IOHandle, P: PluginSet<IO>>(vm: &mut VM<IO, P>, target_reg: u8, source: u8, operand: Operand) -> LC3Result<()> {
+    let left = vm.reg_index_read(source)?;
+    let right = read_operand(vm, operand)?;
 
     vm.reg_index_write(target_reg, left & right)?;
     vm.update_flags(target_reg.into())?;
@@ -98,10 +90,7 @@ pub(crate) fn and<IO: IOHandle>(vm: &mut VM<IO>, command: &Command) -> LC3Result
     Ok(())
 }
 
-pub(crate) fn load_register<IO: IOHandle>(vm: &mut VM<IO>, command: &Command) -> LC3Result<()> {
-    let target = command.bit_slice(4, 6)? as u8;
-    let base = command.bit_slice(7, 9)? as u8;
-    let offset = sign_extend(command.bit_slice(10, 15)?, 6);
+pub(crate) fn load_register<IO: IOHandle, P: PluginSet<IO>>(vm: &mut VM<IO, P>, target: u8, base: u8, offset: u16) -> LC3Result<()> {
     let address = wrapping_add!(vm.reg_index_read(base)?, offset);
     let val = vm.mem_read(address)?;
     vm.reg_index_write(target, val)?;
@@ -110,10 +99,7 @@ pub(crate) fn load_register<IO: IOHandle>(vm: &mut VM<IO>, command: &Command) ->
     Ok(())
 }
 
-pub(crate) fn store_register<IO: IOHandle>(vm: &mut VM<IO>, command: &Command) -> LC3Result<()> {
-    let source = command.bit_slice(4, 6)? as u8;
-    let base_register = command.bit_slice(7, 9)? as u8;
-    let offset = sign_extend(command.bit_slice(10, 15)?, 6);
+pub(crate) fn store_register<IO: IOHandle, P: PluginSet<IO>>(vm: &mut VM<IO, P>, source: u8, base_register: u8, offset: u16) -> LC3Result<()> {
     let address = wrapping_add!(vm.reg_index_read(base_register)?, offset);
     let val = vm.reg_index_read(source)?;
     vm.mem_write(address, val)?;
@@ -121,15 +107,11 @@ pub(crate) fn store_register<IO: IOHandle>(vm: &mut VM<IO>, command: &Command) -
     Ok(())
 }
 
-pub(crate) fn rti<IO: IOHandle>(_vm: &mut VM<IO>, _command: &Command) -> LC3Result<()> {
-    Err(LC3Error::Internal(
-        "Attempt to execute unimplemented op code".to_string(),
-    ))
+pub(crate) fn rti<IO: IOHandle, P: PluginSet<IO>>(vm: &mut VM<IO, P>) -> LC3Result<()> {
+    vm.return_from_interrupt()
 }
 
-pub(crate) fn not<IO: IOHandle>(vm: &mut VM<IO>, command: &Command) -> LC3Result<()> {
-    let target = command.bit_slice(4, 6)? as u8;
-    let source = command.bit_slice(7, 9)? as u8;
+pub(crate) fn not<IO: IOHandle, P: PluginSet<IO>>(vm: &mut VM<IO, P>, target: u8, source: u8) -> LC3Result<()> {
     let negated = !vm.reg_index_read(source)?;
     vm.reg_index_write(target, negated)?;
     vm.update_flags(target.into())?;
@@ -137,11 +119,9 @@ pub(crate) fn not<IO: IOHandle>(vm: &mut VM<IO>, command: &Command) -> LC3Result
     Ok(())
 }
 
-pub(crate) fn load_indirect<IO: IOHandle>(vm: &mut VM<IO>, command: &Command) -> LC3Result<()> {
-    let pc_offset = sign_extend(command.bit_slice(7, 15)?, 9);
+pub(crate) fn load_indirect<IO: IOHandle, P: PluginSet<IO>>(vm: &mut VM<IO, P>, target: u8, pc_offset: u16) -> LC3Result<()> {
     let pc = vm.reg_read(RPC)?;
 
-    let target = command.bit_slice(4, 6)? as u8;
     let final_address = vm.mem_read(wrapping_add!(pc, pc_offset))?;
     let val = vm.mem_read(final_address)?;
 
@@ -151,9 +131,7 @@ pub(crate) fn load_indirect<IO: IOHandle>(vm: &mut VM<IO>, command: &Command) ->
     Ok(())
 }
 
-pub(crate) fn store_indirect<IO: IOHandle>(vm: &mut VM<IO>, command: &Command) -> LC3Result<()> {
-    let source = command.bit_slice(4, 6)? as u8;
-    let offset = sign_extend(command.bit_slice(7, 15)?, 9);
+pub(crate) fn store_indirect<IO: IOHandle, P: PluginSet<IO>>(vm: &mut VM<IO, P>, source: u8, offset: u16) -> LC3Result<()> {
     let pc = vm.reg_read(RPC)?;
     let address = wrapping_add!(pc, offset);
     let final_address = vm.mem_read(address)?;
@@ -163,23 +141,20 @@ pub(crate) fn store_indirect<IO: IOHandle>(vm: &mut VM<IO>, command: &Command) -
     Ok(())
 }
 
-pub(crate) fn jump<IO: IOHandle>(vm: &mut VM<IO>, command: &Command) -> LC3Result<()> {
-    let address_reg = command.bit_slice(7, 9)? as u8;
+pub(crate) fn jump<IO: IOHandle, P: PluginSet<IO>>(vm: &mut VM<IO, P>, address_reg: u8) -> LC3Result<()> {
     let address = vm.reg_index_read(address_reg)?;
     vm.reg_write(RPC, address)?;
 
     Ok(())
 }
 
-pub(crate) fn reserved<IO: IOHandle>(_vm: &mut VM<IO>, _command: &Command) -> LC3Result<()> {
+pub(crate) fn reserved<IO: IOHandle, P: PluginSet<IO>>(_vm: &mut VM<IO, P>) -> LC3Result<()> {
     Err(LC3Error::Internal(
         "Attempt to execute unimplemented op code".to_string(),
     ))
 }
 
-pub(crate) fn load_effective_address<IO: IOHandle>(vm: &mut VM<IO>, command: &Command) -> LC3Result<()> {
-    let target = command.bit_slice(4, 6)? as u8;
-    let offset = sign_extend(command.bit_slice(7, 15)?, 9);
+pub(crate) fn load_effective_address<IO: IOHandle, P: PluginSet<IO>>(vm: &mut VM<IO, P>, target: u8, offset: u16) -> LC3Result<()> {
     let effective_address = wrapping_add!(vm.reg_read(RPC)?, offset);
     vm.reg_index_write(target, effective_address)?;
     vm.update_flags(target.into())?;
@@ -187,8 +162,7 @@ pub(crate) fn load_effective_address<IO: IOHandle>(vm: &mut VM<IO>, command: &Co
     Ok(())
 }
 
-pub(crate) fn trap<IO: IOHandle>(vm: &mut VM<IO>, command: &Command) -> LC3Result<()> {
-    let code = command.bit_slice(8, 15)? as u8;
+pub(crate) fn trap<IO: IOHandle, P: PluginSet<IO>>(vm: &mut VM<IO, P>, code: u8) -> LC3Result<()> {
     let code = TrapCode::from_int(code);
     match code? {
         TrapCode::GetC => handle_trap::getchar(vm)?,
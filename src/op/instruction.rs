@@ -0,0 +1,243 @@
+use crate::utils::sign_extend;
+
+// Second operand of ADD and AND, selected by bit 5 of the instruction.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Operand {
+    Register(u8),
+    Immediate(u16),
+}
+
+// An instruction word with its fields already extracted and its offsets
+// sign extended, so executing it doesn't need to touch the raw bits again.
+// Decoding can't fail: bad trap vectors and the unused op codes only error
+// when they're executed, which is when the VM has always reported them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Instruction {
+    Br { flags: u16, offset: u16 },
+    Add { target: u8, source: u8, operand: Operand },
+    Ld { target: u8, offset: u16 },
+    St { source: u8, offset: u16 },
+    Jsr { offset: u16 },
+    Jsrr { base: u8 },
+    And { target: u8, source: u8, operand: Operand },
+    Ldr { target: u8, base: u8, offset: u16 },
+    Str { source: u8, base: u8, offset: u16 },
+    Rti,
+    Not { target: u8, source: u8 },
+    Ldi { target: u8, offset: u16 },
+    Sti { source: u8, offset: u16 },
+    Jmp { base: u8 },
+    Res,
+    Lea { target: u8, offset: u16 },
+    Trap { code: u8 },
+}
+
+// Where an instruction sends control, as far as following subroutine
+// calls goes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Transfer {
+    Call,
+    Return,
+}
+
+// Register fields sit at one of three fixed positions in the word
+fn reg_at(bytes: u16, shift: u8) -> u8 {
+    ((bytes >> shift) & 0x7) as u8
+}
+
+fn pc_offset9(bytes: u16) -> u16 {
+    sign_extend(bytes & 0x1FF, 9)
+}
+
+fn operand(bytes: u16) -> Operand {
+    if (bytes >> 5) & 1 == 1 {
+        Operand::Immediate(sign_extend(bytes & 0x1F, 5))
+    } else {
+        Operand::Register(reg_at(bytes, 0))
+    }
+}
+
+impl Instruction {
+    pub(crate) fn decode(bytes: u16) -> Self {
+        let high = reg_at(bytes, 9);
+        let mid = reg_at(bytes, 6);
+
+        match bytes >> 12 {
+            0x0 => Self::Br {
+                flags: high as u16,
+                offset: pc_offset9(bytes),
+            },
+            0x1 => Self::Add {
+                target: high,
+                source: mid,
+                operand: operand(bytes),
+            },
+            0x2 => Self::Ld {
+                target: high,
+                offset: pc_offset9(bytes),
+            },
+            0x3 => Self::St {
+                source: high,
+                offset: pc_offset9(bytes),
+            },
+            0x4 if (bytes >> 11) & 1 == 1 => Self::Jsr {
+                offset: sign_extend(bytes & 0x7FF, 11),
+            },
+            0x4 => Self::Jsrr { base: mid },
+            0x5 => Self::And {
+                target: high,
+                source: mid,
+                operand: operand(bytes),
+            },
+            0x6 => Self::Ldr {
+                target: high,
+                base: mid,
+                offset: sign_extend(bytes & 0x3F, 6),
+            },
+            0x7 => Self::Str {
+                source: high,
+                base: mid,
+                offset: sign_extend(bytes & 0x3F, 6),
+            },
+            0x8 => Self::Rti,
+            0x9 => Self::Not {
+                target: high,
+                source: mid,
+            },
+            0xA => Self::Ldi {
+                target: high,
+                offset: pc_offset9(bytes),
+            },
+            0xB => Self::Sti {
+                source: high,
+                offset: pc_offset9(bytes),
+            },
+            0xC => Self::Jmp { base: mid },
+            0xD => Self::Res,
+            0xE => Self::Lea {
+                target: high,
+                offset: pc_offset9(bytes),
+            },
+            _ => Self::Trap {
+                code: (bytes & 0xFF) as u8,
+            },
+        }
+    }
+
+    // Assembly for an instruction, with PC relative targets resolved against
+    // next, the address of the instruction after it
+    pub(crate) fn disassemble(self, next: u16) -> String {
+        let target = |offset: u16| format!("x{:04X}", next.wrapping_add(offset));
+        let operand = |operand: Operand| match operand {
+            Operand::Register(index) => format!("R{}", index),
+            Operand::Immediate(value) => format!("#{}", value as i16),
+        };
+
+        match self {
+            Self::Br { flags, offset } => {
+                let conditions: String = [(4, 'n'), (2, 'z'), (1, 'p')]
+                    .iter()
+                    .filter(|(flag, _)| flags & flag != 0)
+                    .map(|(_, name)| name)
+                    .collect();
+                format!("BR{} {}", conditions, target(offset))
+            }
+            Self::Add { target, source, operand: right } => {
+                format!("ADD R{}, R{}, {}", target, source, operand(right))
+            }
+            Self::And { target, source, operand: right } => {
+                format!("AND R{}, R{}, {}", target, source, operand(right))
+            }
+            Self::Not { target, source } => format!("NOT R{}, R{}", target, source),
+            Self::Ld { target: register, offset } => format!("LD R{}, {}", register, target(offset)),
+            Self::Ldi { target: register, offset } => format!("LDI R{}, {}", register, target(offset)),
+            Self::Ldr { target, base, offset } => format!("LDR R{}, R{}, #{}", target, base, offset as i16),
+            Self::Lea { target: register, offset } => format!("LEA R{}, {}", register, target(offset)),
+            Self::St { source, offset } => format!("ST R{}, {}", source, target(offset)),
+            Self::Sti { source, offset } => format!("STI R{}, {}", source, target(offset)),
+            Self::Str { source, base, offset } => format!("STR R{}, R{}, #{}", source, base, offset as i16),
+            Self::Jsr { offset } => format!("JSR {}", target(offset)),
+            Self::Jsrr { base } => format!("JSRR R{}", base),
+            Self::Jmp { base: 7 } => "RET".to_string(),
+            Self::Jmp { base } => format!("JMP R{}", base),
+            Self::Trap { code } => format!("TRAP x{:02X}", code),
+            Self::Rti => "RTI".to_string(),
+            Self::Res => "RES".to_string(),
+        }
+    }
+
+    // JSR and JSRR call a subroutine and RET, which is JMP R7, returns from
+    // one
+    pub(crate) fn transfer(&self) -> Option<Transfer> {
+        match self {
+            Self::Jsr { .. } | Self::Jsrr { .. } => Some(Transfer::Call),
+            Self::Jmp { base: 7 } => Some(Transfer::Return),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Instruction, Operand};
+    use crate::command::Command;
+    use crate::error::LC3Result;
+    use crate::op::Op;
+    use crate::utils::sign_extend;
+
+    #[test]
+    fn can_decode_instructions() {
+        let test_cases = vec![
+            (0b0000_1010_0000_0100, Instruction::Br { flags: 0b101, offset: 4 }),
+            (
+                0b0001_0000_0100_0010,
+                Instruction::Add { target: 0, source: 1, operand: Operand::Register(2) },
+            ),
+            (
+                0b0001_0000_0111_1110,
+                Instruction::Add { target: 0, source: 1, operand: Operand::Immediate(0xFFFE) },
+            ),
+            (0b0100_1111_1111_1111, Instruction::Jsr { offset: 0xFFFF }),
+            (0b0100_0000_1100_0000, Instruction::Jsrr { base: 3 }),
+            (
+                0b0110_0001_0011_1111,
+                Instruction::Ldr { target: 0, base: 4, offset: 0xFFFF },
+            ),
+            (0b1100_0001_1100_0000, Instruction::Jmp { base: 7 }),
+            (0xF025, Instruction::Trap { code: 0x25 }),
+        ];
+
+        for (bytes, expected) in test_cases {
+            assert_eq!(Instruction::decode(bytes), expected);
+        }
+    }
+
+    // Cross check the decoder against the general purpose bit slicing in
+    // Command for every possible word.
+    #[test]
+    fn can_decode_all_words_like_command() -> LC3Result<()> {
+        for bytes in 0..=u16::MAX {
+            let command = Command::new(bytes);
+            let decoded = Instruction::decode(bytes);
+            let offset9 = sign_extend(command.bit_slice(7, 15)?, 9);
+            let high = command.bit_slice(4, 6)? as u8;
+            let mid = command.bit_slice(7, 9)? as u8;
+
+            let expected = match Op::from_int(command.op_code()?)? {
+                Op::Br => Instruction::Br { flags: high as u16, offset: offset9 },
+                Op::Ld => Instruction::Ld { target: high, offset: offset9 },
+                Op::St => Instruction::St { source: high, offset: offset9 },
+                Op::Ldi => Instruction::Ldi { target: high, offset: offset9 },
+                Op::Sti => Instruction::Sti { source: high, offset: offset9 },
+                Op::Lea => Instruction::Lea { target: high, offset: offset9 },
+                Op::Not => Instruction::Not { target: high, source: mid },
+                Op::Jmp => Instruction::Jmp { base: mid },
+                Op::Trap => Instruction::Trap { code: command.bit_slice(8, 15)? as u8 },
+                _ => continue,
+            };
+            assert_eq!(decoded, expected);
+        }
+
+        Ok(())
+    }
+}
@@ -1,7 +1,10 @@
 pub(crate) mod handler;
+mod instruction;
+#[allow(clippy::module_inception)]
 mod op;
 pub(crate) mod trap_handler;
 
+pub(crate) use instruction::{Instruction, Operand, Transfer};
 pub(crate) use op::Op;
 
 #[cfg(test)]
@@ -42,7 +42,7 @@ pub(crate) enum Op {
 impl Op {
     pub(crate) fn from_int(op_code: u8) -> LC3Result<Self> {
         if (op_code as usize) < OP_CODES.len() {
-            return Ok( OP_CODES[op_code as usize].clone());
+            Ok(OP_CODES[op_code as usize].clone())
         } else {
             let err = LC3Error::BadOpCode{code: op_code};
             Err(err)
@@ -436,7 +436,7 @@ fn can_trap_put_byte_string() -> LC3Result<()> {
         let mut mask = *test_char as u16;
         let is_right = (pos % 2) == 1;
         if is_right {
-            mask = mask << 8;
+            mask <<= 8;
         };
 
         let mem_offset = pos / 2;
@@ -460,7 +460,7 @@ fn can_trap_halt() -> LC3Result<()> {
     vm.set_running(true)?;
     let command = Command::new(0xF025);
     vm.run_command(&command)?;
-    assert_eq!(vm.get_running()?, false);
+    assert!(!vm.get_running()?);
 
     Ok(())
 }
@@ -1,22 +1,23 @@
 use crate::error::LC3Result;
 use crate::io::{IOHandle};
+use crate::plugin::PluginSet;
 use crate::register::Register::{RR0};
 use crate::vm::VM;
 use crate::wrapping_add;
 
-pub(crate) fn getchar<IO: IOHandle>(vm: &mut VM<IO>) -> LC3Result<()> {
+pub(crate) fn getchar<IO: IOHandle, P: PluginSet<IO>>(vm: &mut VM<IO, P>) -> LC3Result<()> {
     let ch = vm.getchar()? as u16;
     vm.reg_write(RR0, ch)?;
     Ok(())
 }
 
-pub(crate) fn trap_out<IO: IOHandle>(vm: &mut VM<IO>) -> LC3Result<()> {
+pub(crate) fn trap_out<IO: IOHandle, P: PluginSet<IO>>(vm: &mut VM<IO, P>) -> LC3Result<()> {
     let ch = vm.reg_read(RR0)? as u8 as char;
     vm.putchar(ch)?;
     Ok(())
 }
 
-pub(crate) fn put_string<IO: IOHandle>(vm: &mut VM<IO>) -> LC3Result<()> {
+pub(crate) fn put_string<IO: IOHandle, P: PluginSet<IO>>(vm: &mut VM<IO, P>) -> LC3Result<()> {
     let mut next_address = vm.reg_read(RR0)?;
     loop {
         let value = vm.mem_read(next_address)?;
@@ -30,7 +31,7 @@ pub(crate) fn put_string<IO: IOHandle>(vm: &mut VM<IO>) -> LC3Result<()> {
     Ok(())
 }
 
-pub(crate) fn trap_in<IO: IOHandle>(vm: &mut VM<IO>) -> LC3Result<()> {
+pub(crate) fn trap_in<IO: IOHandle, P: PluginSet<IO>>(vm: &mut VM<IO, P>) -> LC3Result<()> {
     // TODO: Swap println out for something using the io handle, otherwise
     // this first part isn't testable.
     println!("Enter a character: ");
@@ -40,7 +41,7 @@ pub(crate) fn trap_in<IO: IOHandle>(vm: &mut VM<IO>) -> LC3Result<()> {
     Ok(())
 }
 
-pub(crate) fn put_byte_string<IO: IOHandle>(vm: &mut VM<IO>) -> LC3Result<()> {
+pub(crate) fn put_byte_string<IO: IOHandle, P: PluginSet<IO>>(vm: &mut VM<IO, P>) -> LC3Result<()> {
     let mut next_address = vm.reg_read(RR0)?;
     // TODO: Might be a nicer way to express this loop. Feels a bit close
     // to "while true"
@@ -62,7 +63,7 @@ pub(crate) fn put_byte_string<IO: IOHandle>(vm: &mut VM<IO>) -> LC3Result<()> {
     Ok(())
 }
 
-pub(crate) fn trap_halt<IO: IOHandle>(vm: &mut VM<IO>) -> LC3Result<()> {
+pub(crate) fn trap_halt<IO: IOHandle, P: PluginSet<IO>>(vm: &mut VM<IO, P>) -> LC3Result<()> {
     vm.set_running(false)?;
     Ok(())
 }
@@ -0,0 +1,329 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::rc::Rc;
+
+use super::{Event, Plugin};
+use crate::error::LC3Result;
+use crate::io::IOHandle;
+use crate::op::Instruction;
+use crate::source::LineMap;
+use crate::symbols::SymbolTable;
+use crate::translate::analysis::{find_code, word_at};
+use crate::vm::{MEMORY_SIZE, PC_START, VM};
+
+pub type CoverageRef = Rc<RefCell<CoverageData>>;
+
+// Records which instructions ran and which way each conditional branch
+// went. Whether a branch is taken is worked out from the condition flags
+// before it executes, the same test handler::branch makes.
+pub struct Coverage {
+    data: CoverageRef,
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Self {
+            data: Rc::new(RefCell::new(CoverageData::new())),
+        }
+    }
+
+    // A handle to the results, which stays readable once the plugin has
+    // been handed to a VM
+    pub fn data(&self) -> CoverageRef {
+        self.data.clone()
+    }
+}
+
+impl Default for Coverage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<IOType: IOHandle, Plugins> Plugin<IOType, Plugins> for Coverage {
+    fn handle_event(&mut self, vm: &mut VM<IOType, Plugins>, event: &Event) -> LC3Result<()> {
+        if let Event::Command { bytes } = event {
+            let address = vm.instruction_address();
+            self.data.borrow_mut().record(address, *bytes, vm.condition_flags());
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct BranchCounts {
+    pub taken: u64,
+    pub not_taken: u64,
+}
+
+pub struct CoverageData {
+    executions: Vec<u64>,
+    // Only conditional branches are counted
+    branches: BTreeMap<u16, BranchCounts>,
+}
+
+impl CoverageData {
+    fn new() -> Self {
+        Self {
+            executions: vec![0; MEMORY_SIZE],
+            branches: BTreeMap::new(),
+        }
+    }
+
+    fn record(&mut self, address: u16, bytes: u16, flags: u16) {
+        self.executions[address as usize] += 1;
+
+        if let Some(tested) = conditional_branch(bytes) {
+            let counts = self.branches.entry(address).or_default();
+            if flags & tested != 0 {
+                counts.taken += 1;
+            } else {
+                counts.not_taken += 1;
+            }
+        }
+    }
+
+    pub fn executions(&self, address: u16) -> u64 {
+        self.executions[address as usize]
+    }
+
+    pub fn branch(&self, address: u16) -> Option<BranchCounts> {
+        self.branches.get(&address).copied()
+    }
+
+    // The program's instructions: the code found by following it from its
+    // start, plus anything else in it that ran
+    fn code(&self, program: &[u16]) -> BTreeSet<u16> {
+        let mut code = find_code(program).code;
+        code.extend((0..program.len() as u16).map(|index| PC_START + index).filter(|&address| self.executions(address) > 0));
+        code
+    }
+
+    // Coverage in lcov's .info format for source_path. With a line map the
+    // instructions are the addresses in it. Without one they're found from
+    // the program and each word counts as a line, numbered from 1 at the
+    // start address.
+    pub fn lcov(&self, source_path: &str, program: &[u16], lines: Option<&LineMap>) -> String {
+        let instructions: Vec<(u16, usize)> = match lines {
+            Some(lines) => lines.iter().collect(),
+            None => self
+                .code(program)
+                .into_iter()
+                .map(|address| (address, (address - PC_START) as usize + 1))
+                .collect(),
+        };
+
+        let mut line_counts: BTreeMap<usize, u64> = BTreeMap::new();
+        for &(address, line) in &instructions {
+            *line_counts.entry(line).or_insert(0) += self.executions(address);
+        }
+
+        let mut out = format!("TN:\nSF:{}\n", source_path);
+        for (line, count) in &line_counts {
+            out += &format!("DA:{},{}\n", line, count);
+        }
+        let hit_lines = line_counts.values().filter(|&&count| count > 0).count();
+        out += &format!("LF:{}\nLH:{}\n", line_counts.len(), hit_lines);
+
+        let mut found = 0;
+        let mut hit = 0;
+        for &(address, line) in &instructions {
+            if word_at(program, address).and_then(conditional_branch).is_none() {
+                continue;
+            }
+            // lcov wants - for branches on lines that never ran
+            let outcomes = match self.branch(address) {
+                Some(counts) => {
+                    hit += (counts.taken > 0) as u32 + (counts.not_taken > 0) as u32;
+                    [counts.taken.to_string(), counts.not_taken.to_string()]
+                }
+                None => ["-".to_string(), "-".to_string()],
+            };
+            for (branch, outcome) in outcomes.iter().enumerate() {
+                out += &format!("BRDA:{},0,{},{}\n", line, branch, outcome);
+            }
+            found += 2;
+        }
+        out += &format!("BRF:{}\nBRH:{}\nend_of_record\n", found, hit);
+
+        out
+    }
+
+    // The assembly source with each line prefixed by how many times it ran,
+    // or ##### for code that never ran, and conditional branches followed
+    // by how often they went each way
+    pub fn annotated_source(&self, source: &str, program: &[u16], lines: &LineMap) -> String {
+        let mut by_line: BTreeMap<usize, Vec<u16>> = BTreeMap::new();
+        for (address, line) in lines.iter() {
+            by_line.entry(line).or_default().push(address);
+        }
+
+        let mut out = String::new();
+        for (index, text) in source.lines().enumerate() {
+            let addresses = by_line.get(&(index + 1)).map(Vec::as_slice).unwrap_or(&[]);
+            let count: u64 = addresses.iter().map(|&address| self.executions(address)).sum();
+            let column = if addresses.is_empty() {
+                String::new()
+            } else if count == 0 {
+                "#####".to_string()
+            } else {
+                count.to_string()
+            };
+
+            out += &format!("{:>9}: {}", column, text);
+            for &address in addresses {
+                if word_at(program, address).and_then(conditional_branch).is_some() {
+                    out += &self.branch_note(address);
+                }
+            }
+            out += "\n";
+        }
+
+        out
+    }
+
+    // A disassembly of the program with the same annotations, for when
+    // there's no source. Words that aren't code are shown as data.
+    pub fn annotated_listing(&self, program: &[u16], symbols: &SymbolTable) -> String {
+        let code = self.code(program);
+        let mut out = String::new();
+
+        for (index, &word) in program.iter().enumerate() {
+            let address = PC_START + index as u16;
+            let label = symbols.label(address).unwrap_or("");
+            let line = if code.contains(&address) {
+                let count = match self.executions(address) {
+                    0 => "#####".to_string(),
+                    count => count.to_string(),
+                };
+                let instruction = Instruction::decode(word).disassemble(address.wrapping_add(1));
+                let mut line = format!("{:>9}: x{:04X}  {:<12} {}", count, address, label, instruction);
+                if conditional_branch(word).is_some() {
+                    line += &self.branch_note(address);
+                }
+                line
+            } else {
+                format!("{:>9}: x{:04X}  {:<12} .FILL x{:04X}", "", address, label, word)
+            };
+
+            out += line.trim_end();
+            out += "\n";
+        }
+
+        out
+    }
+
+    fn branch_note(&self, address: u16) -> String {
+        let counts = self.branch(address).unwrap_or_default();
+        format!("  [taken {}, not taken {}]", counts.taken, counts.not_taken)
+    }
+}
+
+// The flags a conditional branch tests. BR with no flags never branches
+// and BRnzp always does, so neither counts.
+fn conditional_branch(word: u16) -> Option<u16> {
+    match Instruction::decode(word) {
+        Instruction::Br { flags, .. } if flags != 0 && flags != 0b111 => Some(flags),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{BranchCounts, Coverage, CoverageRef};
+    use crate::error::LC3Result;
+    use crate::io::ScriptedIOHandle;
+    use crate::source::LineMap;
+    use crate::symbols::SymbolTable;
+    use crate::vm::VM;
+
+    // Counts R1 down from 3, then takes one branch and never reaches the
+    // next
+    const PROGRAM: [u16; 7] = [
+        // AND R1, R1, #0 then ADD R1, R1, #3
+        0b0101_0010_0110_0000,
+        0b0001_0010_0110_0011,
+        // LOOP: ADD R1, R1, #-1 then BRp LOOP
+        0b0001_0010_0111_1111,
+        0b0000_0011_1111_1110,
+        // BRz SKIP then BRp SKIP
+        0b0000_0100_0000_0001,
+        0b0000_0010_0000_0000,
+        // SKIP: HALT
+        0xF025,
+    ];
+
+    const SOURCE: &str = ".ORIG x3000\n\
+                          \x20       AND R1, R1, #0\n\
+                          \x20       ADD R1, R1, #3\n\
+                          LOOP    ADD R1, R1, #-1\n\
+                          \x20       BRp LOOP\n\
+                          \x20       BRz SKIP\n\
+                          \x20       BRp SKIP\n\
+                          SKIP    HALT\n\
+                          .END\n";
+
+    fn run() -> LC3Result<CoverageRef> {
+        let coverage = Coverage::new();
+        let data = coverage.data();
+        let mut vm = VM::new_with_plugins(ScriptedIOHandle::new(), (coverage,));
+        vm.load_program(&PROGRAM)?;
+        vm.run()?;
+
+        Ok(data)
+    }
+
+    #[test]
+    fn can_count_branches() -> LC3Result<()> {
+        let data = run()?;
+        let data = data.borrow();
+
+        assert_eq!(data.executions(0x3002), 3);
+        assert_eq!(data.executions(0x3005), 0);
+        assert_eq!(data.branch(0x3003), Some(BranchCounts { taken: 2, not_taken: 1 }));
+        assert_eq!(data.branch(0x3004), Some(BranchCounts { taken: 1, not_taken: 0 }));
+        assert_eq!(data.branch(0x3005), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn can_write_lcov() -> LC3Result<()> {
+        let data = run()?;
+        let data = data.borrow();
+
+        let lcov = data.lcov("program.obj", &PROGRAM, None);
+        assert!(lcov.starts_with("TN:\nSF:program.obj\nDA:1,1\n"));
+        assert!(lcov.contains("DA:3,3\nDA:4,3\nDA:5,1\nDA:6,0\nDA:7,1\nLF:7\nLH:6\n"));
+        assert!(lcov.contains("BRDA:4,0,0,2\nBRDA:4,0,1,1\nBRDA:5,0,0,1\nBRDA:5,0,1,0\nBRDA:6,0,0,-\n"));
+        assert!(lcov.ends_with("BRF:6\nBRH:3\nend_of_record\n"));
+
+        let lines = LineMap::from_assembly(SOURCE, &SymbolTable::new());
+        let lcov = data.lcov("program.asm", &PROGRAM, Some(&lines));
+        assert!(lcov.contains("DA:4,3\nDA:5,3\nDA:6,1\nDA:7,0\nDA:8,1\n"));
+        assert!(lcov.contains("BRDA:5,0,0,2\n"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn can_annotate_listings() -> LC3Result<()> {
+        let data = run()?;
+        let data = data.borrow();
+
+        let lines = LineMap::from_assembly(SOURCE, &SymbolTable::new());
+        let annotated = data.annotated_source(SOURCE, &PROGRAM, &lines);
+        assert!(annotated.contains("        3:         BRp LOOP  [taken 2, not taken 1]\n"));
+        assert!(annotated.contains("    #####:         BRp SKIP  [taken 0, not taken 0]\n"));
+        assert!(annotated.starts_with("         : .ORIG x3000\n"));
+
+        let mut symbols = SymbolTable::new();
+        symbols.insert(0x3002, "LOOP");
+        let listing = data.annotated_listing(&PROGRAM, &symbols);
+        assert!(listing.contains("        3: x3002  LOOP         ADD R1, R1, #-1\n"));
+        assert!(listing.contains("    #####: x3005               BRp x3006  [taken 0, not taken 0]\n"));
+
+        Ok(())
+    }
+}
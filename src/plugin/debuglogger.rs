@@ -22,8 +22,8 @@ impl<Sink: Write> DebugLogger<Sink> {
     }
 }
 
-impl<Sink: Write, IOType: IOHandle> Plugin<IOType> for DebugLogger<Sink> {
-    fn handle_event(&mut self, _: &mut VM<IOType>, event: &Event) -> LC3Result<()> {
+impl<Sink: Write, IOType: IOHandle, Plugins> Plugin<IOType, Plugins> for DebugLogger<Sink> {
+    fn handle_event(&mut self, _: &mut VM<IOType, Plugins>, event: &Event) -> LC3Result<()> {
         self.sink
             .write(debug_format(event)?.as_bytes())
             .map_plugin_error()?;
@@ -0,0 +1,305 @@
+use std::cell::RefCell;
+use std::io::{BufWriter, Write};
+use std::rc::Rc;
+use std::str::FromStr;
+
+use super::{Event, Plugin};
+use crate::error::{BoxErrors, LC3Result};
+use crate::io::IOHandle;
+use crate::op::Instruction;
+use crate::trace::AddressRange;
+use crate::vm::VM;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EventKind {
+    Command,
+    CharGet,
+    CharPut,
+    KeyDownGet,
+    MemGet,
+    MemSet,
+    RegGet,
+    RegSet,
+    RunningGet,
+    RunningSet,
+    Interrupt,
+}
+
+const EVENT_KINDS: [EventKind; 11] = [
+    EventKind::Command,
+    EventKind::CharGet,
+    EventKind::CharPut,
+    EventKind::KeyDownGet,
+    EventKind::MemGet,
+    EventKind::MemSet,
+    EventKind::RegGet,
+    EventKind::RegSet,
+    EventKind::RunningGet,
+    EventKind::RunningSet,
+    EventKind::Interrupt,
+];
+
+impl EventKind {
+    pub fn of(event: &Event) -> Self {
+        match event {
+            Event::Command { .. } => Self::Command,
+            Event::CharGet { .. } => Self::CharGet,
+            Event::CharPut { .. } => Self::CharPut,
+            Event::KeyDownGet { .. } => Self::KeyDownGet,
+            Event::MemGet { .. } => Self::MemGet,
+            Event::MemSet { .. } => Self::MemSet,
+            Event::RegGet { .. } => Self::RegGet,
+            Event::RegSet { .. } => Self::RegSet,
+            Event::RunningGet { .. } => Self::RunningGet,
+            Event::RunningSet { .. } => Self::RunningSet,
+            Event::Interrupt { .. } => Self::Interrupt,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Command => "command",
+            Self::CharGet => "char-get",
+            Self::CharPut => "char-put",
+            Self::KeyDownGet => "key-down-get",
+            Self::MemGet => "mem-get",
+            Self::MemSet => "mem-set",
+            Self::RegGet => "reg-get",
+            Self::RegSet => "reg-set",
+            Self::RunningGet => "running-get",
+            Self::RunningSet => "running-set",
+            Self::Interrupt => "interrupt",
+        }
+    }
+}
+
+impl FromStr for EventKind {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        EVENT_KINDS
+            .iter()
+            .find(|kind| kind.name() == text)
+            .copied()
+            .ok_or_else(|| format!("Unknown event kind {:?}", text))
+    }
+}
+
+// Which events get logged. Every condition that is set has to hold.
+#[derive(Debug, Clone, Default)]
+pub struct EventFilter {
+    pub kinds: Option<Vec<EventKind>>,
+    // Only events raised by instructions at these addresses
+    pub pc: Option<AddressRange>,
+    // Only memory reads and writes at these addresses. Other events pass.
+    pub memory: Option<AddressRange>,
+}
+
+impl EventFilter {
+    pub fn matches(&self, event: &Event, pc: u16) -> bool {
+        let location = match event {
+            Event::MemGet { location, .. } | Event::MemSet { location, .. } => Some(*location),
+            _ => None,
+        };
+
+        self.kinds.as_ref().is_none_or(|kinds| kinds.contains(&EventKind::of(event)))
+            && self.pc.is_none_or(|range| range.contains(pc))
+            && self
+                .memory
+                .is_none_or(|range| location.is_none_or(|location| range.contains(location)))
+    }
+}
+
+// Logs events as JSON, one object per line, so they can be picked through
+// with jq and friends. Each one carries the number of instructions
+// executed so far, counting the one that raised it, and the address and
+// disassembly of that instruction. Output is buffered, so it's only
+// complete once the log is finished.
+pub struct JsonLogger<Sink: Write> {
+    log: JsonLogRef<Sink>,
+    filter: EventFilter,
+    // Disassembly of the instruction being executed, worked out at the
+    // first event it raises that gets logged
+    instruction: String,
+    instruction_step: Option<u64>,
+}
+
+impl<Sink: Write> JsonLogger<Sink> {
+    pub fn new(sink: Sink) -> Self {
+        Self::with_filter(sink, EventFilter::default())
+    }
+
+    pub fn with_filter(sink: Sink, filter: EventFilter) -> Self {
+        Self {
+            log: Rc::new(RefCell::new(JsonLog {
+                sink: Some(BufWriter::new(sink)),
+            })),
+            filter,
+            instruction: String::new(),
+            instruction_step: None,
+        }
+    }
+
+    // A handle to the log, for finishing it once the plugin has been
+    // handed to a VM
+    pub fn log(&self) -> JsonLogRef<Sink> {
+        self.log.clone()
+    }
+
+    pub fn finish(self) -> LC3Result<Option<Sink>> {
+        self.log.borrow_mut().finish()
+    }
+
+    fn write_event(&self, step: u64, pc: u16, event: &Event) -> std::io::Result<()> {
+        let mut log = self.log.borrow_mut();
+        let sink = match log.sink.as_mut() {
+            Some(sink) => sink,
+            None => return Ok(()),
+        };
+        write!(sink, "{{\"step\":{},\"pc\":{},\"instruction\":", step, pc)?;
+        serde_json::to_writer(&mut *sink, &self.instruction)?;
+        write!(sink, ",\"event\":\"{}\"", EventKind::of(event).name())?;
+
+        match *event {
+            Event::Command { bytes } => write!(sink, ",\"bytes\":{}", bytes)?,
+            Event::CharGet { ch } | Event::CharPut { ch } => {
+                write!(sink, ",\"ch\":")?;
+                serde_json::to_writer(&mut *sink, &ch)?;
+            }
+            Event::KeyDownGet { value } | Event::RunningGet { value } | Event::RunningSet { value } => {
+                write!(sink, ",\"value\":{}", value)?
+            }
+            Event::MemGet { location, value } | Event::MemSet { location, value } => {
+                write!(sink, ",\"location\":{},\"value\":{}", location, value)?
+            }
+            Event::RegGet { index, value } | Event::RegSet { index, value } => {
+                write!(sink, ",\"index\":{},\"value\":{}", index, value)?
+            }
+            Event::Interrupt { vector, priority } => {
+                write!(sink, ",\"vector\":{},\"priority\":{}", vector, priority)?
+            }
+        }
+
+        sink.write_all(b"}\n")
+    }
+}
+
+pub type JsonLogRef<Sink> = Rc<RefCell<JsonLog<Sink>>>;
+
+// Dropping a log that was never finished still flushes it, but any error
+// doing so is lost
+pub struct JsonLog<Sink: Write> {
+    sink: Option<BufWriter<Sink>>,
+}
+
+impl<Sink: Write> JsonLog<Sink> {
+    // Write out anything still buffered and hand back the sink. Events
+    // after this aren't logged, and finishing again gives back nothing.
+    pub fn finish(&mut self) -> LC3Result<Option<Sink>> {
+        self.sink
+            .take()
+            .map(|sink| sink.into_inner().map_err(|err| err.into_error()))
+            .transpose()
+            .map_plugin_error()
+    }
+}
+
+impl<Sink: Write, IOType: IOHandle, Plugins> Plugin<IOType, Plugins> for JsonLogger<Sink> {
+    fn handle_event(&mut self, vm: &mut VM<IOType, Plugins>, event: &Event) -> LC3Result<()> {
+        let pc = vm.instruction_address();
+        if !self.filter.matches(event, pc) {
+            return Ok(());
+        }
+
+        let step = vm.instructions_executed();
+        if self.instruction_step != Some(step) {
+            let instruction = Instruction::decode(vm.peek_memory(pc));
+            self.instruction = instruction.disassemble(pc.wrapping_add(1));
+            self.instruction_step = Some(step);
+        }
+
+        self.write_event(step, pc, event).map_plugin_error()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::Value;
+
+    use super::{EventFilter, EventKind, JsonLogger};
+    use crate::error::LC3Result;
+    use crate::io::ScriptedIOHandle;
+    use crate::vm::VM;
+
+    // Stores 3 to x4000, then halts
+    const PROGRAM: [u16; 5] = [
+        // AND R1, R1, #0 then ADD R1, R1, #3
+        0b0101_0010_0110_0000,
+        0b0001_0010_0110_0011,
+        // STI R1, ADDR then HALT
+        0b1011_0010_0000_0001,
+        0xF025,
+        // ADDR
+        0x4000,
+    ];
+
+    fn log(filter: EventFilter) -> LC3Result<Vec<Value>> {
+        let logger = JsonLogger::with_filter(Vec::new(), filter);
+        let log = logger.log();
+        let mut vm = VM::new_with_plugins(ScriptedIOHandle::new(), (logger,));
+        vm.load_program(&PROGRAM)?;
+        vm.run()?;
+
+        let output = log.borrow_mut().finish()?.unwrap();
+        let output = String::from_utf8(output).unwrap();
+        Ok(output.lines().map(|line| serde_json::from_str(line).unwrap()).collect())
+    }
+
+    #[test]
+    fn can_log_events_as_json() -> LC3Result<()> {
+        let events = log(EventFilter::default())?;
+
+        let command = events.iter().find(|event| event["event"] == "command").unwrap();
+        assert_eq!(command["step"], 1);
+        assert_eq!(command["pc"], 0x3000);
+        assert_eq!(command["instruction"], "AND R1, R1, #0");
+        assert_eq!(command["bytes"], 0b0101_0010_0110_0000);
+
+        let write = events.iter().find(|event| event["event"] == "mem-set" && event["step"] != 0).unwrap();
+        assert_eq!(write["step"], 3);
+        assert_eq!(write["instruction"], "STI R1, x3004");
+        assert_eq!(write["location"], 0x4000);
+        assert_eq!(write["value"], 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn can_filter_events() -> LC3Result<()> {
+        let filter = EventFilter {
+            kinds: Some(vec![EventKind::MemGet, EventKind::RegSet]),
+            pc: Some("x3001-x3002".parse().unwrap()),
+            memory: Some("x3000-x3FFF".parse().unwrap()),
+        };
+        let events = log(filter)?;
+
+        let summary: Vec<(String, u64)> = events
+            .iter()
+            .map(|event| (event["event"].as_str().unwrap().to_string(), event["pc"].as_u64().unwrap()))
+            .collect();
+        // Each instruction sets the PC and fetches itself. The STI then
+        // reads its pointer, and its write to x4000 isn't a kind asked for.
+        let expected = [
+            ("reg-set", 0x3001),
+            ("mem-get", 0x3001),
+            ("reg-set", 0x3001),
+            ("reg-set", 0x3001),
+            ("reg-set", 0x3002),
+            ("mem-get", 0x3002),
+            ("mem-get", 0x3002),
+        ];
+        assert_eq!(summary, expected.map(|(kind, pc)| (kind.to_string(), pc)));
+
+        Ok(())
+    }
+}
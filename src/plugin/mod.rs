@@ -1,6 +1,16 @@
+#[allow(clippy::module_inception)]
 mod plugin;
+mod set;
+pub mod coverage;
 pub mod debuglogger;
+pub mod jsonlogger;
+pub mod profiler;
+pub mod sanitizer;
+pub mod stackcheck;
+pub mod timeline;
+pub mod tracer;
 #[cfg(test)]
 mod test;
 
 pub use plugin::{Plugin, Event};
+pub use set::{DynPlugins, NoPlugins, PluginSet};
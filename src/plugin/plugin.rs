@@ -1,5 +1,6 @@
 use crate::error::LC3Result;
 use crate::io::IOHandle;
+use crate::plugin::DynPlugins;
 use crate::vm::VM;
 
 #[derive(Debug, Clone, PartialEq)]
@@ -14,8 +15,13 @@ pub enum Event {
     RegSet { index: u8, value: u16 },
     RunningGet { value: bool },
     RunningSet { value: bool },
+    // Raised as the VM takes an interrupt, before anything is pushed
+    Interrupt { vector: u8, priority: u8 },
 }
 
-pub trait Plugin<IOType: IOHandle> {
-    fn handle_event(&mut self, vm: &mut VM<IOType>, event: &Event) -> LC3Result<()>;
+// Plugins is the plugin set of the VM the plugin is attached to. Plugins
+// that don't care which set they live in can implement this for any
+// Plugins, which lets them be added at runtime or statically.
+pub trait Plugin<IOType: IOHandle, Plugins = DynPlugins<IOType>> {
+    fn handle_event(&mut self, vm: &mut VM<IOType, Plugins>, event: &Event) -> LC3Result<()>;
 }
@@ -0,0 +1,360 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use super::{Event, Plugin};
+use crate::error::LC3Result;
+use crate::io::IOHandle;
+use crate::op::{Instruction, Op, Transfer};
+use crate::symbols::SymbolTable;
+use crate::vm::{MEMORY_SIZE, VM};
+
+const NUM_OPS: usize = 16;
+
+pub type ProfileRef = Rc<RefCell<Profile>>;
+
+// Counts executions per address, per op and per call stack. Subroutines
+// are entered with JSR or JSRR and left with RET, and each one is named by
+// its entry address. Every instruction counts as one cycle.
+pub struct Profiler {
+    profile: ProfileRef,
+    // Entry addresses of the active subroutines, outermost first
+    stack: Vec<u16>,
+    // Where the last instruction sent control, which is only known for
+    // sure once the next instruction arrives
+    transfer: Option<Transfer>,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self {
+            profile: Rc::new(RefCell::new(Profile::new())),
+            stack: Vec::new(),
+            transfer: None,
+        }
+    }
+
+    // A handle to the counts, which stays readable once the profiler has
+    // been handed to a VM
+    pub fn profile(&self) -> ProfileRef {
+        self.profile.clone()
+    }
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<IOType: IOHandle, Plugins> Plugin<IOType, Plugins> for Profiler {
+    fn handle_event(&mut self, vm: &mut VM<IOType, Plugins>, event: &Event) -> LC3Result<()> {
+        let bytes = match event {
+            Event::Command { bytes } => *bytes,
+            _ => return Ok(()),
+        };
+        let address = vm.instruction_address();
+        let mut profile = self.profile.borrow_mut();
+
+        match (self.transfer.take(), self.stack.last()) {
+            (Some(Transfer::Call), Some(&caller)) => {
+                profile.record_call(Some(caller), address);
+                self.stack.push(address);
+            }
+            // The outermost frame is never popped, so a stray RET doesn't
+            // leave the stack empty
+            (Some(Transfer::Return), _) if self.stack.len() > 1 => {
+                self.stack.pop();
+            }
+            (_, None) => {
+                profile.record_call(None, address);
+                self.stack.push(address);
+            }
+            _ => {}
+        }
+        profile.record_instruction(address, bytes, &self.stack);
+
+        self.transfer = Instruction::decode(bytes).transfer();
+
+        Ok(())
+    }
+}
+
+// Cycles and calls for one subroutine
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubroutineStats {
+    pub entry: u16,
+    pub calls: u64,
+    // Cycles spent in the subroutine itself
+    pub self_cycles: u64,
+    // Cycles spent in the subroutine and everything it called
+    pub inclusive_cycles: u64,
+}
+
+pub struct Profile {
+    executions: Vec<u64>,
+    // Op code of the instruction last executed at each address
+    op_codes: Vec<u8>,
+    ops: [u64; NUM_OPS],
+    // Cycles spent with each call stack, outermost subroutine first
+    stacks: HashMap<Vec<u16>, u64>,
+    calls: HashMap<u16, u64>,
+    // Number of calls by caller and callee
+    edges: HashMap<(u16, u16), u64>,
+}
+
+impl Profile {
+    fn new() -> Self {
+        Self {
+            executions: vec![0; MEMORY_SIZE],
+            op_codes: vec![0; MEMORY_SIZE],
+            ops: [0; NUM_OPS],
+            stacks: HashMap::new(),
+            calls: HashMap::new(),
+            edges: HashMap::new(),
+        }
+    }
+
+    fn record_call(&mut self, caller: Option<u16>, callee: u16) {
+        *self.calls.entry(callee).or_insert(0) += 1;
+        if let Some(caller) = caller {
+            *self.edges.entry((caller, callee)).or_insert(0) += 1;
+        }
+    }
+
+    fn record_instruction(&mut self, address: u16, bytes: u16, stack: &[u16]) {
+        self.executions[address as usize] += 1;
+        self.op_codes[address as usize] = (bytes >> 12) as u8;
+        self.ops[(bytes >> 12) as usize] += 1;
+        match self.stacks.get_mut(stack) {
+            Some(cycles) => *cycles += 1,
+            None => {
+                self.stacks.insert(stack.to_vec(), 1);
+            }
+        }
+    }
+
+    pub fn instructions(&self) -> u64 {
+        self.ops.iter().sum()
+    }
+
+    pub fn executions(&self, address: u16) -> u64 {
+        self.executions[address as usize]
+    }
+
+    // Every subroutine that ran, most inclusive cycles first
+    pub fn subroutines(&self) -> Vec<SubroutineStats> {
+        let mut stats: HashMap<u16, SubroutineStats> = self
+            .calls
+            .iter()
+            .map(|(&entry, &calls)| {
+                let stats = SubroutineStats {
+                    entry,
+                    calls,
+                    self_cycles: 0,
+                    inclusive_cycles: 0,
+                };
+                (entry, stats)
+            })
+            .collect();
+
+        for (stack, &cycles) in &self.stacks {
+            for (depth, entry) in stack.iter().enumerate() {
+                // Recursive calls only count once towards the inclusive
+                // cycles
+                if stack[..depth].contains(entry) {
+                    continue;
+                }
+                if let Some(stats) = stats.get_mut(entry) {
+                    stats.inclusive_cycles += cycles;
+                }
+            }
+            if let Some(stats) = stack.last().and_then(|entry| stats.get_mut(entry)) {
+                stats.self_cycles += cycles;
+            }
+        }
+
+        let mut stats: Vec<SubroutineStats> = stats.into_values().collect();
+        stats.sort_by_key(|stats| (std::cmp::Reverse(stats.inclusive_cycles), stats.entry));
+        stats
+    }
+
+    // Cycles spent in each callee, and whatever it called, on behalf of
+    // each caller
+    fn edge_cycles(&self) -> HashMap<(u16, u16), u64> {
+        let mut edge_cycles = HashMap::new();
+        for (stack, &cycles) in &self.stacks {
+            let mut seen = Vec::new();
+            for edge in stack.windows(2).map(|pair| (pair[0], pair[1])) {
+                if !seen.contains(&edge) {
+                    seen.push(edge);
+                    *edge_cycles.entry(edge).or_insert(0) += cycles;
+                }
+            }
+        }
+
+        edge_cycles
+    }
+
+    // Executions per address, the instruction mix and the subroutines
+    pub fn flat_report(&self, symbols: &SymbolTable) -> String {
+        let total = self.instructions();
+        let mut out = format!("Instructions executed: {}\n\n", total);
+
+        let mut addresses: Vec<(u16, u64)> = (0..MEMORY_SIZE)
+            .filter(|&address| self.executions[address] > 0)
+            .map(|address| (address as u16, self.executions[address]))
+            .collect();
+        addresses.sort_by_key(|&(address, count)| (std::cmp::Reverse(count), address));
+
+        out += &format!("{:<8} {:<20} {:<5} {:>12}\n", "Address", "Label", "Op", "Count");
+        for (address, count) in addresses {
+            out += &format!(
+                "x{:04X}    {:<20} {:<5} {:>12}\n",
+                address,
+                symbols.describe(address),
+                op_name(self.op_codes[address as usize]),
+                count
+            );
+        }
+
+        let mut ops: Vec<(usize, u64)> = self.ops.iter().copied().enumerate().filter(|&(_, count)| count > 0).collect();
+        ops.sort_by_key(|&(op, count)| (std::cmp::Reverse(count), op));
+
+        out += &format!("\n{:<5} {:>12} {:>7}\n", "Op", "Count", "Share");
+        for (op, count) in ops {
+            let share = 100.0 * count as f64 / total as f64;
+            out += &format!("{:<5} {:>12} {:>6.1}%\n", op_name(op as u8), count, share);
+        }
+
+        out += &format!("\n{:<20} {:>8} {:>12} {:>12}\n", "Subroutine", "Calls", "Self", "Inclusive");
+        for stats in self.subroutines() {
+            out += &format!(
+                "{:<20} {:>8} {:>12} {:>12}\n",
+                symbols.name(stats.entry),
+                stats.calls,
+                stats.self_cycles,
+                stats.inclusive_cycles
+            );
+        }
+
+        out
+    }
+
+    // Every subroutine followed by the ones it called, with the number of
+    // calls and the cycles spent in each callee
+    pub fn call_graph_report(&self, symbols: &SymbolTable) -> String {
+        let edge_cycles = self.edge_cycles();
+        let mut out = format!("{:<24} {:>8} {:>12} {:>12}\n", "Subroutine", "Calls", "Self", "Inclusive");
+
+        for stats in self.subroutines() {
+            out += &format!(
+                "{:<24} {:>8} {:>12} {:>12}\n",
+                symbols.name(stats.entry),
+                stats.calls,
+                stats.self_cycles,
+                stats.inclusive_cycles
+            );
+
+            let mut callees: Vec<(u16, u64)> = self
+                .edges
+                .iter()
+                .filter(|((caller, _), _)| *caller == stats.entry)
+                .map(|(&(_, callee), &calls)| (callee, calls))
+                .collect();
+            callees.sort_by_key(|&(callee, _)| (std::cmp::Reverse(edge_cycles.get(&(stats.entry, callee))), callee));
+
+            for (callee, calls) in callees {
+                let cycles = edge_cycles.get(&(stats.entry, callee)).copied().unwrap_or(0);
+                let name = format!("  -> {}", symbols.name(callee));
+                out += &format!("{:<24} {:>8} {:>12} {:>12}\n", name, calls, "", cycles);
+            }
+        }
+
+        out
+    }
+
+    // One line per call stack in the folded format flamegraph tools read,
+    // such as "START;PRINT 42"
+    pub fn folded_stacks(&self, symbols: &SymbolTable) -> String {
+        let mut lines: Vec<String> = self
+            .stacks
+            .iter()
+            .map(|(stack, cycles)| {
+                let names: Vec<String> = stack.iter().map(|&entry| symbols.name(entry)).collect();
+                format!("{} {}\n", names.join(";"), cycles)
+            })
+            .collect();
+        lines.sort();
+
+        lines.concat()
+    }
+}
+
+fn op_name(op_code: u8) -> String {
+    match Op::from_int(op_code) {
+        Ok(op) => format!("{:?}", op).to_uppercase(),
+        Err(_) => "?".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Profiler;
+    use crate::error::LC3Result;
+    use crate::fixtures::{print_program, sum_program};
+    use crate::io::ScriptedIOHandle;
+    use crate::symbols::SymbolTable;
+    use crate::vm::VM;
+
+    fn profile(program: &[u16]) -> LC3Result<super::ProfileRef> {
+        let profiler = Profiler::new();
+        let profile = profiler.profile();
+        let mut vm = VM::new_with_plugins(ScriptedIOHandle::new(), (profiler,));
+        vm.load_program(program)?;
+        vm.run()?;
+
+        Ok(profile)
+    }
+
+    #[test]
+    fn can_profile_subroutine() -> LC3Result<()> {
+        let mut symbols = SymbolTable::new();
+        symbols.insert(0x3000, "START");
+        symbols.insert(0x3004, "LOOP");
+        symbols.insert(0x3009, "PRINT");
+
+        let profile = profile(&print_program())?;
+        let profile = profile.borrow();
+        assert_eq!(profile.instructions(), 19);
+        assert_eq!(profile.executions(0x3004), 3);
+
+        let subroutines = profile.subroutines();
+        assert_eq!(subroutines.len(), 2);
+        assert_eq!((subroutines[0].entry, subroutines[0].self_cycles, subroutines[0].inclusive_cycles), (0x3000, 17, 19));
+        assert_eq!((subroutines[1].entry, subroutines[1].calls, subroutines[1].self_cycles), (0x3009, 1, 2));
+
+        assert_eq!(profile.folded_stacks(&symbols), "START 17\nSTART;PRINT 2\n");
+        assert!(profile.flat_report(&symbols).contains("x3005    LOOP+1               TRAP             3\n"));
+        assert!(profile.call_graph_report(&symbols).contains("  -> PRINT"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn can_profile_recursion() -> LC3Result<()> {
+        let profile = profile(&sum_program(3))?;
+        let profile = profile.borrow();
+        let sum = profile
+            .subroutines()
+            .into_iter()
+            .find(|stats| stats.entry == 0x3004)
+            .expect("SUM should have been profiled");
+        assert_eq!(sum.calls, 4);
+        // Everything but the first three instructions and the HALT
+        assert_eq!(sum.inclusive_cycles, profile.instructions() - 4);
+        assert_eq!(sum.self_cycles, sum.inclusive_cycles);
+
+        Ok(())
+    }
+}
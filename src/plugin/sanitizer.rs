@@ -0,0 +1,374 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::rc::Rc;
+
+use super::{Event, Plugin};
+use crate::error::LC3Result;
+use crate::io::IOHandle;
+use crate::op::{Instruction, Operand};
+use crate::register::Register;
+use crate::symbols::SymbolTable;
+use crate::trap::TrapCode;
+use crate::vm::{MEMORY_SIZE, VM};
+
+const R0: u8 = Register::RR0 as u8;
+const PC: u8 = Register::RPC as u8;
+const COND: u8 = Register::RCond as u8;
+// Every register but the instruction count
+const REGISTERS: usize = Register::RCount as usize;
+
+pub type SanitizerRef = Rc<RefCell<SanitizerReport>>;
+
+// Keeps a shadow bit for every register and word of memory saying whether
+// it holds a value the program put there, and reports when an undefined
+// value decides what the program does, like Valgrind's memcheck. Copying
+// an undefined value around or doing arithmetic on it isn't reported, the
+// result is just undefined too. Branching on it, using it as an address,
+// jumping to it, printing it or executing it is.
+//
+// Memory written by load_program counts as defined, so the plugin needs to
+// be added before the program is loaded. Device registers are always
+// defined.
+pub struct Sanitizer {
+    report: SanitizerRef,
+    registers: [bool; REGISTERS],
+    memory: Vec<bool>,
+    // The instruction running and the step it's running in. Events from a
+    // later step before its Command event come from fetching the next one.
+    instruction: Option<Instruction>,
+    step: u64,
+    pc: u16,
+    // Whether the value the instruction writes to its target is defined
+    result: bool,
+    // Memory reads the instruction has made so far
+    reads: usize,
+}
+
+impl Sanitizer {
+    pub fn new() -> Self {
+        let mut registers = [false; REGISTERS];
+        registers[PC as usize] = true;
+
+        Self {
+            report: Rc::new(RefCell::new(SanitizerReport::default())),
+            registers,
+            memory: vec![false; MEMORY_SIZE],
+            instruction: None,
+            step: 0,
+            pc: 0,
+            result: true,
+            reads: 0,
+        }
+    }
+
+    // A handle to the findings, which stays readable once the plugin has
+    // been handed to a VM
+    pub fn report(&self) -> SanitizerRef {
+        self.report.clone()
+    }
+
+    fn defined<IOType: IOHandle, Plugins>(&self, vm: &VM<IOType, Plugins>, address: u16) -> bool {
+        self.memory[address as usize] || vm.bus().is_mapped(address)
+    }
+
+    fn check(&self, defined: bool, kind: UseKind, source: Source) {
+        if !defined {
+            self.report.borrow_mut().record(self.pc, kind, source);
+        }
+    }
+
+    fn check_register(&self, index: u8, kind: UseKind) {
+        self.check(self.registers[index as usize], kind, Source::Register(index));
+    }
+
+    fn start_instruction(&mut self, bytes: u16) {
+        let instruction = Instruction::decode(bytes);
+        self.instruction = Some(instruction);
+        self.reads = 0;
+        self.result = true;
+
+        let registers = self.registers;
+        match instruction {
+            Instruction::Br { flags, .. } if flags != 0 && flags != 0b111 => {
+                self.check(registers[COND as usize], UseKind::Branch, Source::Flags);
+            }
+            Instruction::Add { source, operand, .. } => {
+                self.result = registers[source as usize] && operand_defined(&registers, operand);
+            }
+            // Masking with zero gives zero whatever the source held
+            Instruction::And { operand: Operand::Immediate(0), .. } => {}
+            Instruction::And { source, operand, .. } => {
+                self.result = registers[source as usize] && operand_defined(&registers, operand);
+            }
+            Instruction::Not { source, .. } => self.result = registers[source as usize],
+            Instruction::Ldr { base, .. } | Instruction::Str { base, .. } => {
+                self.check_register(base, UseKind::Address);
+            }
+            Instruction::Jmp { base } | Instruction::Jsrr { base } => {
+                self.check_register(base, UseKind::Jump);
+            }
+            Instruction::Trap { code } => match TrapCode::from_int(code) {
+                Ok(TrapCode::Out) => self.check_register(R0, UseKind::Output),
+                Ok(TrapCode::PutS | TrapCode::PutSp) => self.check_register(R0, UseKind::Address),
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
+    fn memory_read<IOType: IOHandle, Plugins>(&mut self, vm: &VM<IOType, Plugins>, location: u16) {
+        let defined = self.defined(vm, location);
+        let first = self.reads == 0;
+        self.reads += 1;
+
+        match self.instruction {
+            Some(Instruction::Ld { .. } | Instruction::Ldr { .. }) => self.result = defined,
+            Some(Instruction::Ldi { .. }) if first => {
+                self.check(defined, UseKind::Address, Source::Memory(location));
+            }
+            Some(Instruction::Ldi { .. }) => self.result = defined,
+            Some(Instruction::Sti { .. }) if first => {
+                self.check(defined, UseKind::Address, Source::Memory(location));
+            }
+            Some(Instruction::Trap { .. }) => self.check(defined, UseKind::Output, Source::Memory(location)),
+            _ => {}
+        }
+    }
+
+    fn register_written(&mut self, index: u8) {
+        let defined = match self.instruction {
+            // The flags describe the value just written to the target
+            _ if index == COND => self.result,
+            Some(
+                Instruction::Add { target, .. }
+                | Instruction::And { target, .. }
+                | Instruction::Not { target, .. }
+                | Instruction::Ld { target, .. }
+                | Instruction::Ldr { target, .. }
+                | Instruction::Ldi { target, .. }
+                | Instruction::Lea { target, .. },
+            ) if target == index => self.result,
+            // The link in R7, characters read by GETC and IN, and the PC
+            _ => true,
+        };
+        self.registers[index as usize] = defined;
+    }
+
+    fn stored_value_defined(&self) -> bool {
+        match self.instruction {
+            Some(Instruction::St { source, .. } | Instruction::Str { source, .. } | Instruction::Sti { source, .. }) => {
+                self.registers[source as usize]
+            }
+            _ => true,
+        }
+    }
+}
+
+impl Default for Sanitizer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn operand_defined(registers: &[bool; REGISTERS], operand: Operand) -> bool {
+    match operand {
+        Operand::Register(index) => registers[index as usize],
+        Operand::Immediate(_) => true,
+    }
+}
+
+impl<IOType: IOHandle, Plugins> Plugin<IOType, Plugins> for Sanitizer {
+    fn handle_event(&mut self, vm: &mut VM<IOType, Plugins>, event: &Event) -> LC3Result<()> {
+        // Anything that happens outside the running instruction: loading
+        // the program, starting the VM or fetching the next instruction
+        let fetching = self.instruction.is_none() || self.step != vm.instructions_executed();
+
+        match *event {
+            Event::Command { bytes } => {
+                self.step = vm.instructions_executed();
+                self.pc = vm.instruction_address();
+                self.start_instruction(bytes);
+            }
+            Event::MemGet { location, .. } if fetching => {
+                self.pc = vm.instruction_address();
+                let defined = self.defined(vm, location);
+                self.check(defined, UseKind::Execution, Source::Memory(location));
+            }
+            Event::MemGet { location, .. } => self.memory_read(vm, location),
+            Event::MemSet { location, .. } => {
+                self.memory[location as usize] = fetching || self.stored_value_defined();
+            }
+            Event::RegSet { index, .. } if index != PC && !fetching => self.register_written(index),
+            _ => {}
+        }
+
+        Ok(())
+    }
+}
+
+// What an undefined value was used for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum UseKind {
+    // A conditional branch tested flags set from it
+    Branch,
+    // A load, store or string trap used it as an address
+    Address,
+    // JMP, JSRR or RET jumped to it
+    Jump,
+    // OUT, PUTS or PUTSP printed it
+    Output,
+    // It was fetched and executed as an instruction
+    Execution,
+}
+
+// Where the undefined value was
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Source {
+    Register(u8),
+    Memory(u16),
+    Flags,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Source::Register(index) => write!(f, "R{}", index),
+            Source::Memory(address) => write!(f, "x{:04X}", address),
+            Source::Flags => f.write_str("condition flags"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Finding {
+    pub pc: u16,
+    pub kind: UseKind,
+    pub source: Source,
+    pub count: u64,
+}
+
+// Findings are kept once per instruction, use and source with a count, so
+// a loop over uninitialized data is one finding rather than thousands
+#[derive(Default)]
+pub struct SanitizerReport {
+    findings: BTreeMap<(u16, UseKind, Source), u64>,
+}
+
+impl SanitizerReport {
+    fn record(&mut self, pc: u16, kind: UseKind, source: Source) {
+        *self.findings.entry((pc, kind, source)).or_insert(0) += 1;
+    }
+
+    pub fn is_clean(&self) -> bool {
+        self.findings.is_empty()
+    }
+
+    // Findings ordered by address
+    pub fn findings(&self) -> Vec<Finding> {
+        self.findings
+            .iter()
+            .map(|(&(pc, kind, source), &count)| Finding { pc, kind, source, count })
+            .collect()
+    }
+
+    // One line per finding, such as "LOOP+2 (x3004): conditional branch
+    // depends on uninitialized condition flags (3 times)"
+    pub fn text_report(&self, symbols: &SymbolTable) -> String {
+        let mut report = String::new();
+        for finding in self.findings() {
+            let what = match finding.kind {
+                UseKind::Branch => "conditional branch depends on",
+                UseKind::Address => "address comes from",
+                UseKind::Jump => "jump target comes from",
+                UseKind::Output => "output comes from",
+                UseKind::Execution => "executed",
+            };
+            let times = match finding.count {
+                1 => String::new(),
+                count => format!(" ({} times)", count),
+            };
+            report.push_str(&format!(
+                "{} (x{:04X}): {} uninitialized {}{}\n",
+                symbols.describe(finding.pc),
+                finding.pc,
+                what,
+                finding.source,
+                times
+            ));
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Finding, Sanitizer, Source, UseKind};
+    use crate::error::LC3Result;
+    use crate::io::ScriptedIOHandle;
+    use crate::vm::VM;
+
+    fn sanitize(program: &[u16]) -> LC3Result<Vec<Finding>> {
+        let sanitizer = Sanitizer::new();
+        let report = sanitizer.report();
+        let mut vm = VM::new_with_plugins(ScriptedIOHandle::new(), (sanitizer,));
+        vm.load_program(program)?;
+        vm.run()?;
+
+        let findings = report.borrow().findings();
+        Ok(findings)
+    }
+
+    fn uses(findings: &[Finding]) -> Vec<(u16, UseKind, Source)> {
+        findings.iter().map(|finding| (finding.pc, finding.kind, finding.source)).collect()
+    }
+
+    #[test]
+    fn can_find_uses_of_uninitialized_registers() -> LC3Result<()> {
+        let program = [
+            // ADD R1, R1, #1 then BRp #0 tests flags from an undefined R1
+            0b0001_0010_0110_0001,
+            0b0000_0010_0000_0000,
+            // AND R2, R2, #0 then LDR R3, R2, #0 has a defined base
+            0b0101_0100_1010_0000,
+            0b0110_0110_1000_0000,
+            // OUT prints an undefined R0
+            0xF021,
+            0xF025,
+        ];
+        let findings = sanitize(&program)?;
+
+        assert_eq!(
+            uses(&findings),
+            vec![(0x3001, UseKind::Branch, Source::Flags), (0x3004, UseKind::Output, Source::Register(0))]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn can_track_memory_through_loads_and_stores() -> LC3Result<()> {
+        let program = [
+            // LD R0, x3008 copies a word the program never wrote, then
+            // ST R0, x3009 and LDI R1, x3009 dereference the copy
+            0b0010_0000_0000_0111,
+            0b0011_0000_0000_0111,
+            0b1010_0010_0000_0110,
+            // LEA R0, MESSAGE then PUTS prints a defined string
+            0b1110_0000_0000_0010,
+            0xF022,
+            0xF025,
+            // MESSAGE
+            'A' as u16,
+            0,
+        ];
+        let findings = sanitize(&program)?;
+        assert_eq!(uses(&findings), vec![(0x3002, UseKind::Address, Source::Memory(0x3009))]);
+
+        // ST R1, #0 writes an undefined word over the next instruction
+        let findings = sanitize(&[0b0011_0010_0000_0000, 0xF025, 0xF025])?;
+        assert_eq!(uses(&findings), vec![(0x3001, UseKind::Execution, Source::Memory(0x3001))]);
+
+        Ok(())
+    }
+}
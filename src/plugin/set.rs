@@ -0,0 +1,96 @@
+use super::Plugin;
+use crate::error::LC3Result;
+use crate::io::IOHandle;
+use crate::plugin::Event;
+use crate::vm::VM;
+
+// The collection of plugins a VM notifies of events. The VM is generic over
+// its plugin set, so when the set is known at compile time (NoPlugins or a
+// tuple of plugins) notifications are statically dispatched, and with
+// NoPlugins they compile away entirely.
+pub trait PluginSet<IOType: IOHandle>: Sized {
+    // When false the VM doesn't build or deliver events at all
+    const ACTIVE: bool = true;
+
+    // Lets the VM skip taking the set out of itself when there's nobody to
+    // notify
+    fn is_empty(&self) -> bool {
+        false
+    }
+
+    fn handle_event(&mut self, vm: &mut VM<IOType, Self>, event: &Event) -> LC3Result<()>;
+}
+
+// A VM with no plugins
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoPlugins;
+
+impl<IOType: IOHandle> PluginSet<IOType> for NoPlugins {
+    const ACTIVE: bool = false;
+
+    fn is_empty(&self) -> bool {
+        true
+    }
+
+    fn handle_event(&mut self, _vm: &mut VM<IOType, Self>, _event: &Event) -> LC3Result<()> {
+        Ok(())
+    }
+}
+
+// Plugins added at runtime through VM::add_plugin. This is the default
+// plugin set.
+pub struct DynPlugins<IOType: IOHandle> {
+    plugins: Vec<Box<dyn Plugin<IOType>>>,
+}
+
+impl<IOType: IOHandle> DynPlugins<IOType> {
+    pub fn new() -> Self {
+        Self {
+            plugins: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, plugin: Box<dyn Plugin<IOType>>) {
+        self.plugins.push(plugin);
+    }
+}
+
+impl<IOType: IOHandle> Default for DynPlugins<IOType> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<IOType: IOHandle> PluginSet<IOType> for DynPlugins<IOType> {
+    fn is_empty(&self) -> bool {
+        self.plugins.is_empty()
+    }
+
+    fn handle_event(&mut self, vm: &mut VM<IOType, Self>, event: &Event) -> LC3Result<()> {
+        for plugin in &mut self.plugins {
+            plugin.handle_event(vm, event)?
+        }
+
+        Ok(())
+    }
+}
+
+// Tuples of plugins are plugin sets, notified in order.
+macro_rules! impl_plugin_set_for_tuple {
+    ($($name:ident : $index:tt),+) => {
+        impl<IOType: IOHandle, $($name),+> PluginSet<IOType> for ($($name,)+)
+        where
+            $($name: Plugin<IOType, Self>),+
+        {
+            fn handle_event(&mut self, vm: &mut VM<IOType, Self>, event: &Event) -> LC3Result<()> {
+                $(self.$index.handle_event(vm, event)?;)+
+                Ok(())
+            }
+        }
+    };
+}
+
+impl_plugin_set_for_tuple!(A: 0);
+impl_plugin_set_for_tuple!(A: 0, B: 1);
+impl_plugin_set_for_tuple!(A: 0, B: 1, C: 2);
+impl_plugin_set_for_tuple!(A: 0, B: 1, C: 2, D: 3);
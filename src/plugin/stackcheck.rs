@@ -0,0 +1,360 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use super::{Event, Plugin};
+use crate::error::LC3Result;
+use crate::io::IOHandle;
+use crate::op::Instruction;
+use crate::register::Register;
+use crate::symbols::SymbolTable;
+use crate::trace::AddressRange;
+use crate::vm::VM;
+
+const SP: u8 = Register::RR6 as u8;
+const LINK: u8 = Register::RR7 as u8;
+
+pub type StackReportRef = Rc<RefCell<StackReport>>;
+
+// A subroutine call that hasn't returned yet
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frame {
+    pub entry: u16,
+    pub call_site: u16,
+    // R6 when the JSR ran, which RET has to leave it at
+    stack_pointer: u16,
+}
+
+// Checks programs keep to the LC-3 calling convention, where R6 is the
+// stack pointer and subroutines return with RET to the address JSR left in
+// R7. Each JSR or JSRR opens a frame and each RET closes the innermost
+// one, which is where R6 and R7 are checked.
+//
+// Stores through R6 into the guarded region count as the stack
+// overflowing. The region defaults to the program the VM loaded, so the
+// plugin can be added before or after loading it.
+pub struct StackChecker {
+    report: StackReportRef,
+    // None to guard the loaded program
+    guard: Option<AddressRange>,
+    frames: Vec<Frame>,
+    // The JSR waiting for its subroutine's first instruction
+    call: Option<(u16, u16)>,
+    stack_pointer: u16,
+    link: u16,
+    pc: u16,
+    // Whether the running instruction stores through R6
+    pushing: bool,
+}
+
+impl StackChecker {
+    pub fn new() -> Self {
+        Self {
+            report: Rc::new(RefCell::new(StackReport::default())),
+            guard: None,
+            frames: Vec::new(),
+            call: None,
+            stack_pointer: 0,
+            link: 0,
+            pc: 0,
+            pushing: false,
+        }
+    }
+
+    // Guard these addresses instead of the loaded program
+    pub fn with_guard(guard: AddressRange) -> Self {
+        Self {
+            guard: Some(guard),
+            ..Self::new()
+        }
+    }
+
+    // A handle to the violations, which stays readable once the plugin has
+    // been handed to a VM
+    pub fn report(&self) -> StackReportRef {
+        self.report.clone()
+    }
+
+    fn violation(&self, kind: ViolationKind) {
+        let stack = self.frames.iter().rev().copied().collect();
+        self.report.borrow_mut().record(Violation { pc: self.pc, kind, stack });
+    }
+
+    fn instruction(&mut self, pc: u16, bytes: u16) {
+        self.pc = pc;
+        if let Some((call_site, stack_pointer)) = self.call.take() {
+            self.frames.push(Frame { entry: pc, call_site, stack_pointer });
+        }
+
+        let instruction = Instruction::decode(bytes);
+        self.pushing = matches!(instruction, Instruction::Str { base: SP, .. });
+        match instruction {
+            Instruction::Jsr { .. } | Instruction::Jsrr { .. } => self.call = Some((pc, self.stack_pointer)),
+            Instruction::Jmp { base: LINK } => self.ret(),
+            _ => {}
+        }
+    }
+
+    fn ret(&mut self) {
+        let frame = match self.frames.last() {
+            Some(&frame) => frame,
+            None => return self.violation(ViolationKind::UnmatchedReturn),
+        };
+
+        let expected = frame.call_site.wrapping_add(1);
+        if self.link != expected {
+            self.violation(ViolationKind::LinkClobbered { expected, actual: self.link });
+        }
+        if self.stack_pointer != frame.stack_pointer {
+            self.violation(ViolationKind::StackPointerNotRestored {
+                expected: frame.stack_pointer,
+                actual: self.stack_pointer,
+            });
+        }
+        self.frames.pop();
+    }
+
+    fn guarded<IOType: IOHandle, Plugins>(&self, vm: &VM<IOType, Plugins>, address: u16) -> bool {
+        match self.guard {
+            Some(guard) => guard.contains(address),
+            None => vm.program().is_some_and(|program| program.contains(&address)),
+        }
+    }
+}
+
+impl Default for StackChecker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<IOType: IOHandle, Plugins> Plugin<IOType, Plugins> for StackChecker {
+    fn handle_event(&mut self, vm: &mut VM<IOType, Plugins>, event: &Event) -> LC3Result<()> {
+        match *event {
+            Event::Command { bytes } => self.instruction(vm.instruction_address(), bytes),
+            Event::RegSet { index: SP, value } => self.stack_pointer = value,
+            Event::RegSet { index: LINK, value } => self.link = value,
+            Event::MemSet { location, .. } if self.pushing && self.guarded(vm, location) => {
+                self.violation(ViolationKind::Overflow { address: location });
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ViolationKind {
+    // RET with R6 somewhere other than where the JSR left it
+    StackPointerNotRestored { expected: u16, actual: u16 },
+    // RET with R7 no longer holding the return address
+    LinkClobbered { expected: u16, actual: u16 },
+    // A store through R6 into the guarded region
+    Overflow { address: u16 },
+    // RET with no call to return from
+    UnmatchedReturn,
+}
+
+impl ViolationKind {
+    fn describe(&self, symbols: &SymbolTable) -> String {
+        match *self {
+            Self::StackPointerNotRestored { expected, actual } => {
+                format!("RET with R6 = x{:04X}, but it was x{:04X} at the call", actual, expected)
+            }
+            Self::LinkClobbered { expected, actual } => format!(
+                "RET to {} (x{:04X}) instead of the return address x{:04X}, R7 was overwritten",
+                symbols.describe(actual),
+                actual,
+                expected
+            ),
+            Self::Overflow { address } => {
+                format!("stack overflow, push to {} (x{:04X}) in the guarded region", symbols.describe(address), address)
+            }
+            Self::UnmatchedReturn => "RET without a matching JSR".to_string(),
+        }
+    }
+
+    // Violations of the same kind at the same place are only kept once
+    fn key(&self) -> u8 {
+        match self {
+            Self::StackPointerNotRestored { .. } => 0,
+            Self::LinkClobbered { .. } => 1,
+            Self::Overflow { .. } => 2,
+            Self::UnmatchedReturn => 3,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violation {
+    pub pc: u16,
+    pub kind: ViolationKind,
+    // The active calls, innermost first
+    pub stack: Vec<Frame>,
+}
+
+#[derive(Default)]
+pub struct StackReport {
+    // The first of each violation, in the order they happened, and how
+    // many times it happened
+    violations: Vec<(Violation, u64)>,
+    seen: HashMap<(u16, u8), usize>,
+}
+
+impl StackReport {
+    fn record(&mut self, violation: Violation) {
+        let key = (violation.pc, violation.kind.key());
+        match self.seen.get(&key) {
+            Some(&index) => self.violations[index].1 += 1,
+            None => {
+                self.seen.insert(key, self.violations.len());
+                self.violations.push((violation, 1));
+            }
+        }
+    }
+
+    pub fn is_clean(&self) -> bool {
+        self.violations.is_empty()
+    }
+
+    pub fn violations(&self) -> impl Iterator<Item = &Violation> {
+        self.violations.iter().map(|(violation, _)| violation)
+    }
+
+    // Each violation followed by the call stack it happened in, such as
+    //
+    // POP+3 (x3013): RET with R6 = x3FFE, but it was x4000 at the call
+    //     in POP (x3010), called from MAIN+4 (x3004)
+    pub fn text_report(&self, symbols: &SymbolTable) -> String {
+        let mut report = String::new();
+        for (violation, count) in &self.violations {
+            report.push_str(&format!(
+                "{} (x{:04X}): {}",
+                symbols.describe(violation.pc),
+                violation.pc,
+                violation.kind.describe(symbols)
+            ));
+            if *count > 1 {
+                report.push_str(&format!(" ({} times)", count));
+            }
+            report.push('\n');
+            for frame in &violation.stack {
+                report.push_str(&format!(
+                    "    in {} (x{:04X}), called from {} (x{:04X})\n",
+                    symbols.describe(frame.entry),
+                    frame.entry,
+                    symbols.describe(frame.call_site),
+                    frame.call_site
+                ));
+            }
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{StackChecker, StackReportRef, ViolationKind};
+    use crate::error::LC3Result;
+    use crate::io::ScriptedIOHandle;
+    use crate::limits::Limits;
+    use crate::vm::VM;
+
+    fn check(program: &[u16]) -> LC3Result<StackReportRef> {
+        check_attached(program, false)
+    }
+
+    fn check_attached(program: &[u16], after_load: bool) -> LC3Result<StackReportRef> {
+        let checker = StackChecker::new();
+        let report = checker.report();
+        let mut vm = VM::new_with_io(ScriptedIOHandle::new());
+        vm.set_limits(Limits::new().max_instructions(30));
+        if after_load {
+            vm.load_program(program)?;
+            vm.add_plugin(Box::new(checker));
+        } else {
+            vm.add_plugin(Box::new(checker));
+            vm.load_program(program)?;
+        }
+        // The broken program loops on its last RET until the limit
+        if let Err(err) = vm.run() {
+            assert!(err.is_limit(), "{}", err);
+        }
+
+        Ok(report)
+    }
+
+    #[test]
+    fn can_pass_nested_calls_that_save_r7() -> LC3Result<()> {
+        let program = [
+            // LD R6, STACK then JSR SUB then HALT
+            0x2C09, 0x4801, 0xF025,
+            // SUB: push R7, JSR INNER, pop R7, RET
+            0x1DBF, 0x7F80, 0x4803, 0x6F80, 0x1DA1, 0xC1C0,
+            // INNER: RET
+            0xC1C0,
+            // STACK
+            0x4000,
+        ];
+        let report = check(&program)?;
+        assert!(report.borrow().is_clean(), "{:?}", report.borrow().violations().collect::<Vec<_>>());
+
+        Ok(())
+    }
+
+    #[test]
+    fn can_report_broken_calls() -> LC3Result<()> {
+        let program = [
+            // LD R6, STACK then JSR PUSHY, JSR SUB and HALT
+            0x2C09, 0x4802, 0x4804, 0xF025,
+            // PUSHY: pushes R0 over the data at x300B and returns without
+            // popping it
+            0x1DBF, 0x7180, 0xC1C0,
+            // SUB: JSR INNER then RET with INNER's return address in R7
+            0x4801, 0xC1C0,
+            // INNER: RET
+            0xC1C0,
+            // STACK and a data word
+            0x300C, 0,
+        ];
+        let report = check(&program)?;
+        let report = report.borrow();
+        let violations: Vec<_> = report.violations().map(|violation| (violation.pc, violation.kind)).collect();
+
+        assert_eq!(
+            violations,
+            vec![
+                (0x3005, ViolationKind::Overflow { address: 0x300B }),
+                (0x3006, ViolationKind::StackPointerNotRestored { expected: 0x300C, actual: 0x300B }),
+                (0x3008, ViolationKind::LinkClobbered { expected: 0x3003, actual: 0x3008 }),
+                (0x3008, ViolationKind::UnmatchedReturn),
+            ]
+        );
+
+        let overflow = report.violations().next().unwrap();
+        assert_eq!(overflow.stack.len(), 1);
+        assert_eq!((overflow.stack[0].entry, overflow.stack[0].call_site), (0x3004, 0x3001));
+
+        Ok(())
+    }
+
+    #[test]
+    fn can_guard_program_loaded_before_or_after_attaching() -> LC3Result<()> {
+        let program = [
+            // LD R6, STACK then push R0 over the STACK word itself
+            0x2C02, 0x1DBF, 0x7180,
+            // STACK
+            0x3004,
+            // HALT
+            0xF025,
+        ];
+        for after_load in [false, true] {
+            let report = check_attached(&program, after_load)?;
+            let violations: Vec<_> = report.borrow().violations().map(|violation| violation.kind).collect();
+            assert_eq!(violations, vec![ViolationKind::Overflow { address: 0x3003 }], "after load: {}", after_load);
+        }
+
+        Ok(())
+    }
+}
@@ -2,10 +2,10 @@ use std::cell::RefCell;
 use std::rc::Rc;
 
 use crate::error::LC3Result;
-use crate::io::IOHandle;
+use crate::io::{IOHandle, ScriptedIOHandle};
 use crate::vm::VM;
 use super::Event::*;
-use super::{Event, Plugin};
+use super::{Event, NoPlugins, Plugin};
 
 type EventsReference = Rc<RefCell<Vec<Event>>>;
 
@@ -66,3 +66,48 @@ fn can_push_events_to_plugin() -> LC3Result<()> {
 
     Ok(())
 }
+
+// A plugin that works in any plugin set, to check static dispatch
+struct CountingPlugin {
+    commands: Rc<RefCell<u32>>,
+}
+
+impl<IOType: IOHandle, Plugins> Plugin<IOType, Plugins> for CountingPlugin {
+    fn handle_event(&mut self, _vm: &mut VM<IOType, Plugins>, event: &Event) -> LC3Result<()> {
+        if let Command { .. } = event {
+            *self.commands.borrow_mut() += 1;
+        }
+        Ok(())
+    }
+}
+
+// ADD R0, R0, #1 twice then HALT
+const THREE_INSTRUCTIONS: [u16; 3] = [0b0001_0000_0010_0001, 0b0001_0000_0010_0001, 0xF025];
+
+#[test]
+fn can_run_without_plugins() -> LC3Result<()> {
+    let mut vm = VM::new_with_plugins(ScriptedIOHandle::new(), NoPlugins);
+    vm.load_program(&THREE_INSTRUCTIONS)?;
+    vm.run()?;
+    assert_eq!(vm.instructions_executed(), 3);
+
+    Ok(())
+}
+
+#[test]
+fn can_dispatch_plugins_statically() -> LC3Result<()> {
+    let first = Rc::new(RefCell::new(0));
+    let second = Rc::new(RefCell::new(0));
+    let plugins = (
+        CountingPlugin { commands: first.clone() },
+        CountingPlugin { commands: second.clone() },
+    );
+
+    let mut vm = VM::new_with_plugins(ScriptedIOHandle::new(), plugins);
+    vm.load_program(&THREE_INSTRUCTIONS)?;
+    vm.run()?;
+    assert_eq!(*first.borrow(), 3);
+    assert_eq!(*second.borrow(), 3);
+
+    Ok(())
+}
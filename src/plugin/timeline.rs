@@ -0,0 +1,324 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use serde_json::{json, Value};
+
+use super::{Event, Plugin};
+use crate::device::Interrupt;
+use crate::error::LC3Result;
+use crate::io::IOHandle;
+use crate::op::{Instruction, Transfer};
+use crate::symbols::SymbolTable;
+use crate::trap::TrapCode;
+use crate::vm::VM;
+
+pub type TimelineRef = Rc<RefCell<TimelineData>>;
+
+// Records subroutine calls and traps against the number of instructions
+// executed, for export in the Chrome Trace Event format that Perfetto and
+// chrome://tracing read. A call lasts from the first instruction of the
+// subroutine up to the instruction its RET returns to, and a trap lasts
+// the one instruction that raised it. Interrupts are instants, at the
+// point the VM took them.
+pub struct Timeline {
+    data: TimelineRef,
+    transfer: Option<Transfer>,
+}
+
+impl Timeline {
+    pub fn new() -> Self {
+        Self {
+            data: Rc::new(RefCell::new(TimelineData::new())),
+            transfer: None,
+        }
+    }
+
+    // A handle to the results, which stays readable once the plugin has
+    // been handed to a VM
+    pub fn data(&self) -> TimelineRef {
+        self.data.clone()
+    }
+}
+
+impl Default for Timeline {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<IOType: IOHandle, Plugins> Plugin<IOType, Plugins> for Timeline {
+    fn handle_event(&mut self, vm: &mut VM<IOType, Plugins>, event: &Event) -> LC3Result<()> {
+        let bytes = match event {
+            Event::Command { bytes } => *bytes,
+            Event::Interrupt { vector, priority } => {
+                self.data.borrow_mut().interrupt(*vector, *priority);
+                return Ok(());
+            }
+            _ => return Ok(()),
+        };
+        let address = vm.instruction_address();
+        let mut data = self.data.borrow_mut();
+
+        match self.transfer.take() {
+            Some(Transfer::Call) => data.call(address),
+            Some(Transfer::Return) => data.ret(),
+            None => {}
+        }
+        let instruction = Instruction::decode(bytes);
+        if let Instruction::Trap { code } = instruction {
+            data.trap(code);
+        }
+        data.instructions += 1;
+
+        self.transfer = instruction.transfer();
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SliceKind {
+    // A subroutine, by entry address
+    Call(u16),
+    Trap(u8),
+}
+
+// A span of execution, timed in instructions
+#[derive(Debug, Clone, PartialEq)]
+pub struct Slice {
+    pub kind: SliceKind,
+    pub start: u64,
+    pub duration: u64,
+}
+
+pub struct TimelineData {
+    instructions: u64,
+    // Entry addresses and start times of the active subroutines, outermost
+    // first
+    stack: Vec<(u16, u64)>,
+    slices: Vec<Slice>,
+    interrupts: Vec<(u64, Interrupt)>,
+}
+
+impl TimelineData {
+    fn new() -> Self {
+        Self {
+            instructions: 0,
+            stack: Vec::new(),
+            slices: Vec::new(),
+            interrupts: Vec::new(),
+        }
+    }
+
+    fn call(&mut self, entry: u16) {
+        self.stack.push((entry, self.instructions));
+    }
+
+    // A RET with no call to return from is ignored
+    fn ret(&mut self) {
+        if let Some((entry, start)) = self.stack.pop() {
+            self.slices.push(Slice {
+                kind: SliceKind::Call(entry),
+                start,
+                duration: self.instructions - start,
+            });
+        }
+    }
+
+    fn trap(&mut self, code: u8) {
+        self.slices.push(Slice {
+            kind: SliceKind::Trap(code),
+            start: self.instructions,
+            duration: 1,
+        });
+    }
+
+    fn interrupt(&mut self, vector: u8, priority: u8) {
+        self.interrupts.push((self.instructions, Interrupt { vector, priority }));
+    }
+
+    // Each interrupt taken, with the number of instructions executed before
+    // it
+    pub fn interrupts(&self) -> &[(u64, Interrupt)] {
+        &self.interrupts
+    }
+
+    pub fn instructions(&self) -> u64 {
+        self.instructions
+    }
+
+    // Every slice in order of starting, outer slices before the slices
+    // inside them. Subroutines that never returned end with the run.
+    pub fn slices(&self) -> Vec<Slice> {
+        let mut slices = self.slices.clone();
+        slices.extend(self.stack.iter().map(|&(entry, start)| Slice {
+            kind: SliceKind::Call(entry),
+            start,
+            duration: self.instructions - start,
+        }));
+        slices.sort_by_key(|slice| (slice.start, std::cmp::Reverse(slice.duration)));
+        slices
+    }
+
+    // The timeline as Chrome Trace Event JSON. Timestamps are instruction
+    // counts, shown by viewers as microseconds.
+    pub fn chrome_trace(&self, symbols: &SymbolTable) -> String {
+        let mut events: Vec<Value> = self
+            .slices()
+            .into_iter()
+            .map(|slice| {
+                let (name, category, args) = match slice.kind {
+                    SliceKind::Call(entry) => {
+                        (symbols.name(entry), "call", json!({ "entry": format!("x{:04X}", entry) }))
+                    }
+                    SliceKind::Trap(code) => {
+                        let name = match TrapCode::from_int(code) {
+                            Ok(trap) => trap.name().to_string(),
+                            Err(_) => format!("TRAP x{:02X}", code),
+                        };
+                        (name, "trap", json!({ "code": format!("x{:02X}", code) }))
+                    }
+                };
+                json!({
+                    "name": name,
+                    "cat": category,
+                    "ph": "X",
+                    "ts": slice.start,
+                    "dur": slice.duration,
+                    "pid": 1,
+                    "tid": 1,
+                    "args": args,
+                })
+            })
+            .collect();
+        events.extend(self.interrupts.iter().map(|(at, interrupt)| {
+            json!({
+                "name": format!("INT x{:02X}", interrupt.vector),
+                "cat": "interrupt",
+                "ph": "i",
+                "s": "t",
+                "ts": at,
+                "pid": 1,
+                "tid": 1,
+                "args": {
+                    "vector": format!("x{:02X}", interrupt.vector),
+                    "priority": interrupt.priority,
+                },
+            })
+        }));
+
+        json!({ "traceEvents": events }).to_string()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::Value;
+
+    use super::{Slice, SliceKind, Timeline};
+    use crate::device::{Interrupt, Timer, TimerMode};
+    use crate::error::LC3Result;
+    use crate::fixtures::{print_program, sum_program};
+    use crate::io::ScriptedIOHandle;
+    use crate::symbols::SymbolTable;
+    use crate::vm::VM;
+
+    #[test]
+    fn can_record_calls_and_traps() -> LC3Result<()> {
+        let timeline = Timeline::new();
+        let data = timeline.data();
+        let mut vm = VM::new_with_plugins(ScriptedIOHandle::new(), (timeline,));
+        vm.load_program(&print_program())?;
+        vm.run()?;
+
+        let data = data.borrow();
+        assert_eq!(data.instructions(), 19);
+        assert_eq!(
+            data.slices(),
+            [
+                Slice { kind: SliceKind::Call(0x3009), start: 2, duration: 2 },
+                Slice { kind: SliceKind::Trap(0x22), start: 2, duration: 1 },
+                Slice { kind: SliceKind::Trap(0x21), start: 7, duration: 1 },
+                Slice { kind: SliceKind::Trap(0x21), start: 11, duration: 1 },
+                Slice { kind: SliceKind::Trap(0x21), start: 15, duration: 1 },
+                Slice { kind: SliceKind::Trap(0x25), start: 18, duration: 1 },
+            ]
+        );
+
+        let mut symbols = SymbolTable::new();
+        symbols.insert(0x3009, "PRINT");
+        let trace: Value = serde_json::from_str(&data.chrome_trace(&symbols)).unwrap();
+        let events = trace["traceEvents"].as_array().unwrap();
+        assert_eq!(events.len(), 6);
+        assert_eq!(events[0]["name"], "PRINT");
+        assert_eq!(events[0]["ph"], "X");
+        assert_eq!(events[1]["name"], "PUTS");
+        assert_eq!(events[1]["cat"], "trap");
+        assert_eq!(events[2]["name"], "OUT");
+        assert_eq!(events[5]["ts"], 18);
+
+        Ok(())
+    }
+
+    #[test]
+    fn can_record_nested_calls() -> LC3Result<()> {
+        let timeline = Timeline::new();
+        let data = timeline.data();
+        let mut vm = VM::new_with_plugins(ScriptedIOHandle::new(), (timeline,));
+        vm.load_program(&sum_program(2))?;
+        vm.run()?;
+
+        // Each call to SUM lasts until the RET back into its caller
+        let data = data.borrow();
+        assert_eq!(
+            data.slices(),
+            [
+                Slice { kind: SliceKind::Call(0x3004), start: 3, duration: 35 },
+                Slice { kind: SliceKind::Call(0x3004), start: 11, duration: 21 },
+                Slice { kind: SliceKind::Call(0x3004), start: 19, duration: 7 },
+                Slice { kind: SliceKind::Trap(0x25), start: 38, duration: 1 },
+            ]
+        );
+        let trace: Value = serde_json::from_str(&data.chrome_trace(&SymbolTable::new())).unwrap();
+        assert_eq!(trace["traceEvents"][1]["name"], "x3004");
+
+        Ok(())
+    }
+
+    #[test]
+    fn can_record_interrupts() -> LC3Result<()> {
+        let program = [
+            // LEA R0, HANDLER then STI R0, VECTOR
+            0xE00A, 0xB00B,
+            // Expire after 3 instructions with interrupts enabled
+            0x200B, 0xB00B, 0x200B, 0xB00B,
+            // Four NOPs then HALT
+            0, 0, 0, 0, 0xF025,
+            // HANDLER: clear the timer's status then RTI
+            0xB006, 0x8000,
+            // VECTOR, the reload value and address, control value and
+            // address and the status address
+            0x0181, 3, 0xFE0A, 0xC000, 0xFE09, 0xFE08,
+        ];
+        let interrupt = Interrupt { vector: 0x81, priority: 4 };
+        let timer = Timer::new(0xFE08, TimerMode::Instructions, interrupt);
+
+        let timeline = Timeline::new();
+        let data = timeline.data();
+        let mut vm = VM::new_with_plugins(ScriptedIOHandle::new(), (timeline,));
+        vm.add_device(timer.addresses(), Box::new(timer))?;
+        vm.load_program(&program)?;
+        vm.run()?;
+
+        let data = data.borrow();
+        assert_eq!(data.interrupts(), [(8, interrupt)]);
+
+        let trace: Value = serde_json::from_str(&data.chrome_trace(&SymbolTable::new())).unwrap();
+        let event = trace["traceEvents"].as_array().unwrap().last().unwrap().clone();
+        assert_eq!(event["name"], "INT x81");
+        assert_eq!(event["ph"], "i");
+        assert_eq!(event["ts"], 8);
+
+        Ok(())
+    }
+}
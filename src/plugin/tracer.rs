@@ -0,0 +1,103 @@
+use std::cell::RefCell;
+use std::io::Write;
+use std::rc::Rc;
+
+use super::{Event, Plugin};
+use crate::error::LC3Result;
+use crate::io::IOHandle;
+use crate::register::Register;
+use crate::trace::{TraceRecord, TraceWriter};
+use crate::vm::VM;
+
+// The PC's register index. Writes to it aren't traced.
+const PC_INDEX: u8 = Register::RPC as u8;
+
+pub type TraceRef<W> = Rc<RefCell<Trace<W>>>;
+
+// Writes a record of each instruction executed to a trace. An instruction's
+// writes only arrive after its Command event, so each record is held until
+// the next instruction starts or the trace is finished.
+pub struct Tracer<W: Write> {
+    trace: TraceRef<W>,
+}
+
+impl<W: Write> Tracer<W> {
+    pub fn new(sink: W, compress: bool) -> LC3Result<Self> {
+        let trace = Trace {
+            writer: Some(TraceWriter::new(sink, compress)?),
+            pending: None,
+        };
+
+        Ok(Self {
+            trace: Rc::new(RefCell::new(trace)),
+        })
+    }
+
+    // A handle to the trace, for finishing it once the plugin has been
+    // handed to a VM
+    pub fn trace(&self) -> TraceRef<W> {
+        self.trace.clone()
+    }
+
+    pub fn finish(self) -> LC3Result<Option<W>> {
+        self.trace.borrow_mut().finish()
+    }
+}
+
+pub struct Trace<W: Write> {
+    writer: Option<TraceWriter<W>>,
+    pending: Option<TraceRecord>,
+}
+
+impl<W: Write> Trace<W> {
+    fn flush_pending(&mut self) -> LC3Result<()> {
+        match (self.pending.take(), self.writer.as_mut()) {
+            (Some(record), Some(writer)) => writer.write(&record),
+            _ => Ok(()),
+        }
+    }
+
+    // Write out the last record and end the trace, handing back the sink.
+    // Anything traced after this is dropped, and finishing again gives
+    // back nothing.
+    pub fn finish(&mut self) -> LC3Result<Option<W>> {
+        self.flush_pending()?;
+        self.writer.take().map(TraceWriter::finish).transpose()
+    }
+}
+
+// A trace that's never finished is still written out as far as it can be,
+// but there's nowhere to report a failure from here. Call finish to find
+// out whether the trace is complete.
+impl<W: Write> Drop for Trace<W> {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
+
+impl<W: Write, IOType: IOHandle, Plugins> Plugin<IOType, Plugins> for Tracer<W> {
+    fn handle_event(&mut self, vm: &mut VM<IOType, Plugins>, event: &Event) -> LC3Result<()> {
+        let mut trace = self.trace.borrow_mut();
+        match *event {
+            Event::Command { bytes } => {
+                trace.flush_pending()?;
+                if trace.writer.is_some() {
+                    trace.pending = Some(TraceRecord::new(vm.instruction_address(), bytes));
+                }
+            }
+            Event::RegSet { index, value } if index != PC_INDEX => {
+                if let Some(record) = trace.pending.as_mut() {
+                    record.reg_writes.push((index, value));
+                }
+            }
+            Event::MemSet { location, value } => {
+                if let Some(record) = trace.pending.as_mut() {
+                    record.mem_writes.push((location, value));
+                }
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+}
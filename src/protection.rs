@@ -0,0 +1,168 @@
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
+use crate::vm::MEMORY_SIZE;
+
+const DENY_READ: u8 = 1;
+const DENY_WRITE: u8 = 1 << 1;
+const DENY_EXECUTE: u8 = 1 << 2;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Access {
+    Read,
+    Write,
+    Execute,
+}
+
+impl Access {
+    fn flag(self) -> u8 {
+        match self {
+            Access::Read => DENY_READ,
+            Access::Write => DENY_WRITE,
+            Access::Execute => DENY_EXECUTE,
+        }
+    }
+}
+
+impl fmt::Display for Access {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Access::Read => "Read",
+            Access::Write => "Write",
+            Access::Execute => "Execution",
+        };
+        f.write_str(name)
+    }
+}
+
+// What a region of memory allows. Protecting a region more than once
+// combines the restrictions, so ReadOnly then NoExecute leaves data that
+// can only be read.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Protection {
+    ReadOnly,
+    NoExecute,
+    // Code that can run but not be read or written as data
+    ExecuteOnly,
+    NoAccess,
+}
+
+impl Protection {
+    fn denied(self) -> u8 {
+        match self {
+            Protection::ReadOnly => DENY_WRITE,
+            Protection::NoExecute => DENY_EXECUTE,
+            Protection::ExecuteOnly => DENY_READ | DENY_WRITE,
+            Protection::NoAccess => DENY_READ | DENY_WRITE | DENY_EXECUTE,
+        }
+    }
+}
+
+impl FromStr for Protection {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text {
+            "read-only" => Ok(Self::ReadOnly),
+            "no-execute" => Ok(Self::NoExecute),
+            "execute-only" => Ok(Self::ExecuteOnly),
+            "no-access" => Ok(Self::NoAccess),
+            other => Err(format!("Unknown protection {:?}", other)),
+        }
+    }
+}
+
+// The accesses denied at each address
+pub struct ProtectionMap {
+    denied: Vec<u8>,
+}
+
+impl ProtectionMap {
+    pub fn new() -> Self {
+        Self {
+            denied: vec![0; MEMORY_SIZE],
+        }
+    }
+
+    pub fn protect(&mut self, addresses: RangeInclusive<u16>, protection: Protection) {
+        for address in addresses {
+            self.denied[address as usize] |= protection.denied();
+        }
+    }
+
+    #[inline]
+    pub fn allows(&self, address: u16, access: Access) -> bool {
+        self.denied[address as usize] & access.flag() == 0
+    }
+}
+
+impl Default for ProtectionMap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Access, Protection, ProtectionMap};
+    use crate::error::{LC3Error, LC3Result};
+    use crate::io::ScriptedIOHandle;
+    use crate::vm::VM;
+
+    #[test]
+    fn can_combine_protections() {
+        let mut map = ProtectionMap::new();
+        map.protect(0x3000..=0x30FF, Protection::ReadOnly);
+        map.protect(0x3080..=0x30FF, Protection::NoExecute);
+        map.protect(0x4000..=0x4000, Protection::ExecuteOnly);
+
+        assert!(map.allows(0x3000, Access::Execute));
+        assert!(!map.allows(0x3000, Access::Write));
+        assert!(!map.allows(0x3080, Access::Execute));
+        assert!(map.allows(0x3080, Access::Read));
+        assert!(!map.allows(0x4000, Access::Read));
+        assert!(map.allows(0x4000, Access::Execute));
+        assert!(map.allows(0x3100, Access::Write));
+    }
+
+    fn run(program: &[u16], protect: impl FnOnce(&mut VM<ScriptedIOHandle>)) -> LC3Result<()> {
+        let mut vm = VM::new_with_io(ScriptedIOHandle::new());
+        vm.load_program(program)?;
+        protect(&mut vm);
+        vm.run()
+    }
+
+    #[test]
+    fn can_stop_writes_to_read_only_memory() {
+        // ST R0, #-1 writes over its own instruction
+        let program = [0b0011_0001_1111_1111, 0xF025];
+        let result = run(&program, |vm| vm.protect(0x3000..=0x3001, Protection::ReadOnly));
+
+        match result {
+            Err(LC3Error::AccessViolation { pc, address, access }) => {
+                assert_eq!((pc, address, access), (0x3000, 0x3000, Access::Write));
+            }
+            other => panic!("Expected an access violation, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn can_stop_execution_and_reads() -> LC3Result<()> {
+        // JMP to the data after the HALT
+        let program = [0b0010_0000_0000_0010, 0b1100_0000_0000_0000, 0xF025, 0x3003, 0xF025];
+        let result = run(&program, |vm| vm.protect(0x3003..=0x3004, Protection::NoExecute));
+        assert!(matches!(
+            result,
+            Err(LC3Error::AccessViolation { pc: 0x3003, address: 0x3003, access: Access::Execute })
+        ));
+
+        // LD of a word that can only be executed
+        let program = [0b0010_0000_0000_0001, 0xF025, 0];
+        let result = run(&program, |vm| vm.protect(0x3002..=0x3002, Protection::ExecuteOnly));
+        assert!(matches!(result, Err(LC3Error::AccessViolation { access: Access::Read, .. })));
+
+        // Code that's only executed runs fine
+        run(&program, |vm| vm.protect(0x3000..=0x3001, Protection::ExecuteOnly))
+    }
+}
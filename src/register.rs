@@ -25,18 +25,47 @@ pub(crate) enum Register {
     RR5 = 5,
     RR6 = 6,
     RR7 = 7,
+    #[allow(clippy::upper_case_acronyms)]
     RPC = 8,
     RCond = 9,
     RCount = 10
 }
 
 impl Register {
-    pub(crate) fn to_u8(&self) -> u8 {
-        *self as u8
+    pub(crate) fn to_u8(self) -> u8 {
+        self as u8
+    }
+
+    pub(crate) fn from_index(index: u8) -> Option<Self> {
+        REGISTERS.get(index as usize).copied()
+    }
+
+    // Look a register up by its assembler-style name (R0-R7, PC, COND).
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        REGISTERS
+            .iter()
+            .find(|reg| reg.name().eq_ignore_ascii_case(name))
+            .copied()
+    }
+
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Register::RR0 => "R0",
+            Register::RR1 => "R1",
+            Register::RR2 => "R2",
+            Register::RR3 => "R3",
+            Register::RR4 => "R4",
+            Register::RR5 => "R5",
+            Register::RR6 => "R6",
+            Register::RR7 => "R7",
+            Register::RPC => "PC",
+            Register::RCond => "COND",
+            Register::RCount => "COUNT",
+        }
     }
 
     #[cfg(test)]
-    pub(crate) fn index(&self) -> usize {
+    pub(crate) fn index(self) -> usize {
         self.to_u8() as usize
     }
 }
@@ -0,0 +1,227 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::error::{BoxErrors, LC3Error, LC3Result};
+use crate::symbols::SymbolTable;
+
+const OP_NAMES: [&str; 24] = [
+    "ADD", "AND", "NOT", "BR", "JMP", "JSR", "JSRR", "LD", "LDI", "LDR", "LEA", "ST", "STI", "STR", "TRAP", "RET",
+    "RTI", "GETC", "OUT", "PUTS", "IN", "PUTSP", "HALT", "NOP",
+];
+
+// The line of assembly source each instruction was assembled from, with
+// lines numbered from 1
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LineMap {
+    lines: BTreeMap<u16, usize>,
+}
+
+impl LineMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // A line map file has an address and a line number on each line, such
+    // as "x3000 12". Blank lines and lines starting with ; are skipped.
+    pub fn parse(text: &str) -> LC3Result<Self> {
+        let mut map = Self::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with(';') {
+                continue;
+            }
+
+            let bad_line = || LC3Error::LineMap(format!("line {} should be an address and a line number", index + 1));
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let (address, source_line) = match fields[..] {
+                [address, source_line] => (address, source_line),
+                _ => return Err(bad_line()),
+            };
+            let address = parse_number(address).ok_or_else(bad_line)?;
+            let source_line = source_line.parse().map_err(|_| bad_line())?;
+            map.insert(address, source_line);
+        }
+
+        Ok(map)
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> LC3Result<Self> {
+        let text = std::fs::read_to_string(path).map_io_error()?;
+        Self::parse(&text)
+    }
+
+    // Work out the address of every instruction in an assembly source file
+    // the way the assembler's first pass does, from .ORIG and the size of
+    // each instruction and directive. Wherever a line defines a label in
+    // the symbol table the address is taken from the table instead, so a
+    // directive sized differently here only throws off the lines up to the
+    // next label.
+    pub fn from_assembly(source: &str, symbols: &SymbolTable) -> Self {
+        let labels: BTreeMap<&str, u16> = symbols.iter().map(|(address, label)| (label, address)).collect();
+        let mut map = Self::new();
+        let mut address: u16 = 0x3000;
+
+        for (index, line) in source.lines().enumerate() {
+            let code = strip_comment(line);
+            let mut tokens = code.split(|ch: char| ch.is_whitespace() || ch == ',').filter(|token| !token.is_empty());
+            let mut token = match tokens.next() {
+                Some(token) => token,
+                None => continue,
+            };
+
+            if !is_op(token) && !token.starts_with('.') {
+                if let Some(&label_address) = labels.get(token.trim_end_matches(':')) {
+                    address = label_address;
+                }
+                token = match tokens.next() {
+                    Some(token) => token,
+                    None => continue,
+                };
+            }
+
+            let directive = token.to_uppercase();
+            match directive.as_str() {
+                ".ORIG" => address = tokens.next().and_then(parse_number).unwrap_or(address),
+                ".END" => break,
+                ".FILL" => address = address.wrapping_add(1),
+                ".BLKW" => address = address.wrapping_add(tokens.next().and_then(parse_number).unwrap_or(1)),
+                ".STRINGZ" => address = address.wrapping_add(stringz_len(code)),
+                _ if is_op(token) => {
+                    map.insert(address, index + 1);
+                    address = address.wrapping_add(1);
+                }
+                _ => {}
+            }
+        }
+
+        map
+    }
+
+    pub fn insert(&mut self, address: u16, line: usize) {
+        self.lines.insert(address, line);
+    }
+
+    pub fn line(&self, address: u16) -> Option<usize> {
+        self.lines.get(&address).copied()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (u16, usize)> + '_ {
+        self.lines.iter().map(|(&address, &line)| (address, line))
+    }
+}
+
+fn is_op(token: &str) -> bool {
+    let upper = token.to_uppercase();
+    let is_branch = upper
+        .strip_prefix("BR")
+        .is_some_and(|conditions| conditions.chars().all(|ch| "NZP".contains(ch)));
+
+    is_branch || OP_NAMES.contains(&upper.as_str())
+}
+
+// Numbers in assembler notation: x3000, #12 or 12
+fn parse_number(text: &str) -> Option<u16> {
+    if let Some(hex) = text.strip_prefix(['x', 'X']) {
+        return u16::from_str_radix(hex, 16).ok();
+    }
+    text.trim_start_matches('#').parse::<i32>().ok().map(|value| value as u16)
+}
+
+// A line up to its comment, leaving any ; inside a string alone
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    let mut escaped = false;
+    for (index, ch) in line.char_indices() {
+        match ch {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            ';' if !quoted => return &line[..index],
+            _ => {}
+        }
+    }
+
+    line
+}
+
+// Words taken by a .STRINGZ line: one per character between the quotes,
+// counting an escape such as \n, \" or \\ as one, plus the terminator
+fn stringz_len(code: &str) -> u16 {
+    let mut chars = match code.find('"') {
+        Some(start) => code[start + 1..].chars(),
+        None => return 1,
+    };
+    let mut len = 1;
+    while let Some(ch) = chars.next() {
+        match ch {
+            '"' => break,
+            '\\' => {
+                chars.next();
+            }
+            _ => {}
+        }
+        len += 1;
+    }
+
+    len
+}
+
+#[cfg(test)]
+mod test {
+    use super::LineMap;
+    use crate::error::LC3Result;
+    use crate::symbols::SymbolTable;
+
+    const SOURCE: &str = "; Prints a message then three stars\n\
+                          .ORIG x3000\n\
+                          START   LEA R0, MSG\n\
+                          \x20       JSR PRINT\n\
+                          \x20       HALT\n\
+                          MSG     .STRINGZ \"hi\\n\"\n\
+                          BUF     .BLKW 2\n\
+                          PRINT   PUTS ; print R0\n\
+                          \x20       RET\n\
+                          .END\n";
+
+    #[test]
+    fn can_map_assembly_lines() {
+        let map = LineMap::from_assembly(SOURCE, &SymbolTable::new());
+
+        let lines: Vec<(u16, usize)> = map.iter().collect();
+        assert_eq!(lines, vec![(0x3000, 3), (0x3001, 4), (0x3002, 5), (0x3009, 8), (0x300A, 9)]);
+    }
+
+    #[test]
+    fn can_size_strings_with_semicolons_and_escapes() {
+        let source = ".ORIG x3000\n\
+                      \x20       .STRINGZ \"a;b\" ; four words\n\
+                      \x20       HALT\n\
+                      \x20       .STRINGZ \"\\\\\\\"\"\n\
+                      \x20       HALT\n\
+                      .END\n";
+        let map = LineMap::from_assembly(source, &SymbolTable::new());
+
+        let lines: Vec<(u16, usize)> = map.iter().collect();
+        assert_eq!(lines, vec![(0x3004, 3), (0x3008, 5)]);
+    }
+
+    #[test]
+    fn can_resync_on_labels() {
+        let mut symbols = SymbolTable::new();
+        symbols.insert(0x3010, "PRINT");
+
+        let map = LineMap::from_assembly(SOURCE, &symbols);
+        assert_eq!(map.line(0x3010), Some(8));
+        assert_eq!(map.line(0x3011), Some(9));
+    }
+
+    #[test]
+    fn can_parse_line_map() -> LC3Result<()> {
+        let map = LineMap::parse("; address line\nx3000 3\n#12289 4\n")?;
+        assert_eq!(map.line(0x3000), Some(3));
+        assert_eq!(map.line(0x3001), Some(4));
+        assert!(LineMap::parse("x3000").is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,51 @@
+use std::fmt;
+use std::time::Duration;
+
+// How much work a run did and how fast, as reported by --stats
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RunStats {
+    pub instructions: u64,
+    pub elapsed: Duration,
+}
+
+impl RunStats {
+    pub fn new(instructions: u64, elapsed: Duration) -> Self {
+        Self { instructions, elapsed }
+    }
+
+    // Millions of instructions per second. Zero for runs too short to
+    // time.
+    pub fn mips(&self) -> f64 {
+        let seconds = self.elapsed.as_secs_f64();
+        if seconds == 0.0 {
+            return 0.0;
+        }
+        self.instructions as f64 / seconds / 1_000_000.0
+    }
+}
+
+impl fmt::Display for RunStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Executed {} instructions in {:.3}s ({:.2} MIPS)",
+            self.instructions,
+            self.elapsed.as_secs_f64(),
+            self.mips()
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn can_report_mips() {
+        let stats = RunStats::new(3_000_000, Duration::from_millis(1500));
+
+        assert_eq!(stats.mips(), 2.0);
+        assert_eq!(stats.to_string(), "Executed 3000000 instructions in 1.500s (2.00 MIPS)");
+        assert_eq!(RunStats::new(10, Duration::ZERO).mips(), 0.0);
+    }
+}
@@ -0,0 +1,108 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::error::{BoxErrors, LC3Result};
+
+// Labels from the .sym file the LC-3 assembler writes next to a program,
+// which looks like
+//
+//     // Symbol table
+//     // Scope level 0:
+//     //	Symbol Name       Page Address
+//     //	----------------  ------------
+//     //	START             3000
+//
+// Lines that aren't a label and a hex address are skipped.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SymbolTable {
+    labels: BTreeMap<u16, String>,
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn parse(text: &str) -> Self {
+        let mut symbols = Self::new();
+        for line in text.lines() {
+            let line = line.trim_start_matches("//");
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if let [label, address] = fields[..] {
+                let digits = address.trim_start_matches(['x', 'X']);
+                if let Ok(address) = u16::from_str_radix(digits, 16) {
+                    symbols.insert(address, label);
+                }
+            }
+        }
+
+        symbols
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> LC3Result<Self> {
+        let text = std::fs::read_to_string(path).map_io_error()?;
+        Ok(Self::parse(&text))
+    }
+
+    pub fn insert(&mut self, address: u16, label: &str) {
+        self.labels.insert(address, label.to_string());
+    }
+
+    pub fn label(&self, address: u16) -> Option<&str> {
+        self.labels.get(&address).map(String::as_str)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (u16, &str)> {
+        self.labels.iter().map(|(&address, label)| (address, label.as_str()))
+    }
+
+    // The label at an address, or the nearest label before it with an
+    // offset (LOOP+2), or the address itself when there's no label before
+    // it
+    pub fn describe(&self, address: u16) -> String {
+        match self.labels.range(..=address).next_back() {
+            Some((&start, label)) if start == address => label.clone(),
+            Some((&start, label)) => format!("{}+{}", label, address - start),
+            None => format!("x{:04X}", address),
+        }
+    }
+
+    // The label at an address or the address itself
+    pub fn name(&self, address: u16) -> String {
+        match self.label(address) {
+            Some(label) => label.to_string(),
+            None => format!("x{:04X}", address),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::SymbolTable;
+
+    const SYM_FILE: &str = "// Symbol table\n\
+                            // Scope level 0:\n\
+                            //\tSymbol Name       Page Address\n\
+                            //\t----------------  ------------\n\
+                            //\tSTART             3000\n\
+                            //\tLOOP              3004\n";
+
+    #[test]
+    fn can_parse_sym_file() {
+        let symbols = SymbolTable::parse(SYM_FILE);
+
+        assert_eq!(symbols.label(0x3000), Some("START"));
+        assert_eq!(symbols.label(0x3004), Some("LOOP"));
+        assert_eq!(symbols.label(0x3001), None);
+    }
+
+    #[test]
+    fn can_describe_addresses() {
+        let symbols = SymbolTable::parse(SYM_FILE);
+
+        assert_eq!(symbols.describe(0x3004), "LOOP");
+        assert_eq!(symbols.describe(0x3006), "LOOP+2");
+        assert_eq!(symbols.describe(0x2FFF), "x2FFF");
+        assert_eq!(symbols.name(0x3006), "x3006");
+    }
+}
@@ -0,0 +1,105 @@
+use std::str::FromStr;
+
+use super::TraceRecord;
+use crate::op::Instruction;
+
+// An inclusive range of addresses, written x4000-x4FFF or as a single
+// address
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AddressRange {
+    pub start: u16,
+    pub end: u16,
+}
+
+impl AddressRange {
+    pub fn contains(&self, address: u16) -> bool {
+        self.start <= address && address <= self.end
+    }
+}
+
+// An address as x4000, #16384 or 16384
+pub fn parse_address(text: &str) -> Result<u16, String> {
+    let text = text.trim();
+    let parsed = match text.strip_prefix(['x', 'X']).or_else(|| text.strip_prefix("0x")) {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => text.trim_start_matches('#').parse(),
+    };
+
+    parsed.map_err(|_| format!("{:?} is not an address", text))
+}
+
+impl FromStr for AddressRange {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let (start, end) = match text.split_once('-') {
+            Some((start, end)) => (parse_address(start)?, parse_address(end)?),
+            None => {
+                let address = parse_address(text)?;
+                (address, address)
+            }
+        };
+        if start > end {
+            return Err(format!("{} starts after it ends", text));
+        }
+
+        Ok(Self { start, end })
+    }
+}
+
+// Picks records out of a trace. Every condition that is set has to hold.
+// after and until select a window of execution: it opens at the first
+// instruction at after and closes once the instruction at until has run.
+#[derive(Debug, Clone, Default)]
+pub struct TraceFilter {
+    pub pc: Option<AddressRange>,
+    pub writes: Option<AddressRange>,
+    pub after: Option<u16>,
+    pub until: Option<u16>,
+    // Text the disassembled instruction has to contain, ignoring case
+    pub search: Option<String>,
+}
+
+// Where a trace is relative to the filter's window
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Window {
+    Before,
+    Open,
+    Closed,
+}
+
+impl TraceFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn start(&self) -> Window {
+        if self.after.is_some() {
+            Window::Before
+        } else {
+            Window::Open
+        }
+    }
+
+    // Whether a record passes, given the window so far. Moves the window on
+    // past the record.
+    pub fn matches(&self, record: &TraceRecord, window: &mut Window) -> bool {
+        if *window == Window::Before && self.after == Some(record.pc) {
+            *window = Window::Open;
+        }
+        let in_window = *window == Window::Open;
+        if in_window && self.until == Some(record.pc) {
+            *window = Window::Closed;
+        }
+
+        in_window
+            && self.pc.is_none_or(|range| range.contains(record.pc))
+            && self
+                .writes
+                .is_none_or(|range| record.mem_writes.iter().any(|&(address, _)| range.contains(address)))
+            && self.search.as_ref().is_none_or(|text| {
+                let instruction = Instruction::decode(record.bytes).disassemble(record.pc.wrapping_add(1));
+                instruction.to_lowercase().contains(&text.to_lowercase())
+            })
+    }
+}
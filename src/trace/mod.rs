@@ -0,0 +1,255 @@
+pub mod filter;
+mod render;
+#[cfg(test)]
+mod test;
+
+use std::io::{Read, Write};
+
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+
+use crate::error::{BoxErrors, LC3Error, LC3Result};
+use crate::vm::PC_START;
+
+pub use filter::{parse_address, AddressRange, TraceFilter};
+pub use render::{to_json, to_text};
+
+// A trace file starts with these four bytes, a version byte and a flags
+// byte, followed by the records, deflated if FLAG_COMPRESSED is set.
+//
+// Each record starts with a tag byte. Its low two bits say how the PC is
+// stored: not at all when it follows on from the last record's PC, as a
+// signed byte added to that, or in full. The next three bits are the
+// number of register writes and the top three the number of memory
+// writes. A count of 7 says the real count follows as a word, for devices
+// that move whole blocks of memory in one instruction. Then come the PC if
+// it's stored, the instruction word, the long register and memory counts
+// if there are any, each register write as an index byte and a value and
+// each memory write as an address and a value. Words are little endian.
+const MAGIC: &[u8; 4] = b"LC3T";
+const VERSION: u8 = 1;
+const FLAG_COMPRESSED: u8 = 1;
+
+const PC_NEXT: u8 = 0;
+const PC_DELTA: u8 = 1;
+const PC_FULL: u8 = 2;
+const LONG_COUNT: u8 = 7;
+
+// One executed instruction and what it changed. Writes to the PC aren't
+// recorded, since the next record's PC says where execution went.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TraceRecord {
+    pub pc: u16,
+    pub bytes: u16,
+    pub reg_writes: Vec<(u8, u16)>,
+    pub mem_writes: Vec<(u16, u16)>,
+}
+
+impl TraceRecord {
+    pub fn new(pc: u16, bytes: u16) -> Self {
+        Self {
+            pc,
+            bytes,
+            ..Self::default()
+        }
+    }
+}
+
+enum Sink<W: Write> {
+    Plain(W),
+    Deflate(DeflateEncoder<W>),
+}
+
+impl<W: Write> Sink<W> {
+    fn get_mut(&mut self) -> &mut dyn Write {
+        match self {
+            Sink::Plain(sink) => sink,
+            Sink::Deflate(sink) => sink,
+        }
+    }
+}
+
+pub struct TraceWriter<W: Write> {
+    sink: Sink<W>,
+    last_pc: u16,
+}
+
+impl<W: Write> TraceWriter<W> {
+    pub fn new(mut sink: W, compress: bool) -> LC3Result<Self> {
+        let flags = if compress { FLAG_COMPRESSED } else { 0 };
+        sink.write_all(MAGIC).map_io_error()?;
+        sink.write_all(&[VERSION, flags]).map_io_error()?;
+
+        let sink = if compress {
+            Sink::Deflate(DeflateEncoder::new(sink, Compression::default()))
+        } else {
+            Sink::Plain(sink)
+        };
+
+        Ok(Self {
+            sink,
+            last_pc: PC_START.wrapping_sub(1),
+        })
+    }
+
+    pub fn write(&mut self, record: &TraceRecord) -> LC3Result<()> {
+        let too_many = |len: usize| len > u16::MAX as usize;
+        if too_many(record.reg_writes.len()) || too_many(record.mem_writes.len()) {
+            return Err(LC3Error::Trace(format!(
+                "the instruction at x{:04X} made more than {} writes",
+                record.pc,
+                u16::MAX
+            )));
+        }
+        let count = |len: usize| len.min(LONG_COUNT as usize) as u8;
+        let (reg_count, mem_count) = (count(record.reg_writes.len()), count(record.mem_writes.len()));
+
+        let mut buffer = Vec::with_capacity(16);
+        let delta = record.pc.wrapping_sub(self.last_pc.wrapping_add(1)) as i16;
+        let pc_mode = match delta {
+            0 => PC_NEXT,
+            -128..=127 => PC_DELTA,
+            _ => PC_FULL,
+        };
+        buffer.push(pc_mode | reg_count << 2 | mem_count << 5);
+        match pc_mode {
+            PC_DELTA => buffer.push(delta as i8 as u8),
+            PC_FULL => buffer.extend_from_slice(&record.pc.to_le_bytes()),
+            _ => {}
+        }
+        buffer.extend_from_slice(&record.bytes.to_le_bytes());
+        if reg_count == LONG_COUNT {
+            buffer.extend_from_slice(&(record.reg_writes.len() as u16).to_le_bytes());
+        }
+        if mem_count == LONG_COUNT {
+            buffer.extend_from_slice(&(record.mem_writes.len() as u16).to_le_bytes());
+        }
+        for &(index, value) in &record.reg_writes {
+            buffer.push(index);
+            buffer.extend_from_slice(&value.to_le_bytes());
+        }
+        for &(address, value) in &record.mem_writes {
+            buffer.extend_from_slice(&address.to_le_bytes());
+            buffer.extend_from_slice(&value.to_le_bytes());
+        }
+
+        self.sink.get_mut().write_all(&buffer).map_io_error()?;
+        self.last_pc = record.pc;
+
+        Ok(())
+    }
+
+    // Finish the compressed stream, if there is one, and hand back the sink
+    pub fn finish(self) -> LC3Result<W> {
+        let mut sink = match self.sink {
+            Sink::Plain(sink) => sink,
+            Sink::Deflate(sink) => sink.finish().map_io_error()?,
+        };
+        sink.flush().map_io_error()?;
+
+        Ok(sink)
+    }
+}
+
+enum Source<R: Read> {
+    Plain(R),
+    Deflate(DeflateDecoder<R>),
+}
+
+impl<R: Read> Source<R> {
+    fn get_mut(&mut self) -> &mut dyn Read {
+        match self {
+            Source::Plain(source) => source,
+            Source::Deflate(source) => source,
+        }
+    }
+}
+
+// Reads the records back out of a trace, in the order they were written
+pub struct TraceReader<R: Read> {
+    source: Source<R>,
+    last_pc: u16,
+}
+
+impl<R: Read> TraceReader<R> {
+    pub fn new(mut source: R) -> LC3Result<Self> {
+        let mut header = [0u8; 6];
+        source.read_exact(&mut header).map_io_error()?;
+        if &header[..4] != MAGIC {
+            return Err(LC3Error::Trace("not an lc3rs trace".to_string()));
+        }
+        if header[4] != VERSION {
+            return Err(LC3Error::Trace(format!("unsupported version {}", header[4])));
+        }
+
+        let source = if header[5] & FLAG_COMPRESSED != 0 {
+            Source::Deflate(DeflateDecoder::new(source))
+        } else {
+            Source::Plain(source)
+        };
+
+        Ok(Self {
+            source,
+            last_pc: PC_START.wrapping_sub(1),
+        })
+    }
+
+    fn read_count(&mut self, count: u8) -> LC3Result<u16> {
+        match count {
+            LONG_COUNT => self.read_u16(),
+            count => Ok(count as u16),
+        }
+    }
+
+    fn read_u8(&mut self) -> LC3Result<u8> {
+        let mut byte = [0u8; 1];
+        self.source.get_mut().read_exact(&mut byte).map_io_error()?;
+        Ok(byte[0])
+    }
+
+    fn read_u16(&mut self) -> LC3Result<u16> {
+        let mut bytes = [0u8; 2];
+        self.source.get_mut().read_exact(&mut bytes).map_io_error()?;
+        Ok(u16::from_le_bytes(bytes))
+    }
+
+    // The next record, or None at the end of the trace
+    pub fn read(&mut self) -> LC3Result<Option<TraceRecord>> {
+        let mut tag = [0u8; 1];
+        if self.source.get_mut().read(&mut tag).map_io_error()? == 0 {
+            return Ok(None);
+        }
+        let tag = tag[0];
+
+        let next_pc = self.last_pc.wrapping_add(1);
+        let pc = match tag & 0b11 {
+            PC_NEXT => next_pc,
+            PC_DELTA => next_pc.wrapping_add(self.read_u8()? as i8 as u16),
+            PC_FULL => self.read_u16()?,
+            mode => return Err(LC3Error::Trace(format!("unknown PC encoding {}", mode))),
+        };
+        let mut record = TraceRecord::new(pc, self.read_u16()?);
+        let reg_count = self.read_count((tag >> 2) & 0b111)?;
+        let mem_count = self.read_count(tag >> 5)?;
+        for _ in 0..reg_count {
+            let index = self.read_u8()?;
+            record.reg_writes.push((index, self.read_u16()?));
+        }
+        for _ in 0..mem_count {
+            let address = self.read_u16()?;
+            record.mem_writes.push((address, self.read_u16()?));
+        }
+        self.last_pc = pc;
+
+        Ok(Some(record))
+    }
+}
+
+impl<R: Read> Iterator for TraceReader<R> {
+    type Item = LC3Result<TraceRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read().transpose()
+    }
+}
@@ -0,0 +1,57 @@
+use serde_json::{json, Map, Value};
+
+use super::TraceRecord;
+use crate::op::Instruction;
+use crate::register::Register;
+
+fn register_name(index: u8) -> String {
+    match Register::from_index(index) {
+        Some(register) => register.name().to_string(),
+        None => format!("R?{}", index),
+    }
+}
+
+fn disassemble(record: &TraceRecord) -> String {
+    Instruction::decode(record.bytes).disassemble(record.pc.wrapping_add(1))
+}
+
+// One line per record, numbered from 0 in execution order, such as
+// "12 x3004 LD R0, x300B  R0=x002A COND=x0001"
+pub fn to_text(step: u64, record: &TraceRecord) -> String {
+    let mut line = format!("{} x{:04X} {}", step, record.pc, disassemble(record));
+    if !record.reg_writes.is_empty() || !record.mem_writes.is_empty() {
+        line.push(' ');
+    }
+    for &(index, value) in &record.reg_writes {
+        line += &format!(" {}=x{:04X}", register_name(index), value);
+    }
+    for &(address, value) in &record.mem_writes {
+        line += &format!(" [x{:04X}]=x{:04X}", address, value);
+    }
+
+    line
+}
+
+// One JSON object per record
+pub fn to_json(step: u64, record: &TraceRecord) -> String {
+    let registers: Map<String, Value> = record
+        .reg_writes
+        .iter()
+        .map(|&(index, value)| (register_name(index), json!(value)))
+        .collect();
+    let memory: Vec<Value> = record
+        .mem_writes
+        .iter()
+        .map(|&(address, value)| json!({"address": address, "value": value}))
+        .collect();
+
+    json!({
+        "step": step,
+        "pc": record.pc,
+        "word": record.bytes,
+        "instruction": disassemble(record),
+        "registers": registers,
+        "memory": memory,
+    })
+    .to_string()
+}
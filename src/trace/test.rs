@@ -0,0 +1,210 @@
+use std::cell::RefCell;
+use std::io::Write;
+use std::rc::Rc;
+
+use super::{to_json, to_text, AddressRange, TraceFilter, TraceReader, TraceRecord, TraceWriter};
+use crate::device::{Disk, Interrupt, SECTOR_WORDS};
+use crate::error::{BoxErrors, LC3Result};
+use crate::io::ScriptedIOHandle;
+use crate::plugin::tracer::Tracer;
+use crate::vm::VM;
+
+// Stores R1 counting down from 3 to x4000 on each pass of a loop
+const PROGRAM: [u16; 8] = [
+    // AND R1, R1, #0 then ADD R1, R1, #3
+    0b0101_0010_0110_0000,
+    0b0001_0010_0110_0011,
+    // LOOP: STI R1, ADDR then ADD R1, R1, #-1
+    0b1011_0010_0000_0100,
+    0b0001_0010_0111_1111,
+    // BRp LOOP
+    0b0000_0011_1111_1101,
+    // HALT
+    0xF025,
+    0,
+    // ADDR
+    0x4000,
+];
+
+// A sink that's still readable once the tracer writing to it is gone
+#[derive(Clone, Default)]
+struct SharedSink(Rc<RefCell<Vec<u8>>>);
+
+impl Write for SharedSink {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+fn trace(compress: bool) -> LC3Result<Vec<TraceRecord>> {
+    let sink = SharedSink::default();
+    let tracer = Tracer::new(sink.clone(), compress)?;
+    let trace = tracer.trace();
+    let mut vm = VM::new_with_plugins(ScriptedIOHandle::new(), (tracer,));
+    vm.load_program(&PROGRAM)?;
+    vm.run()?;
+    // Writes out the last record
+    trace.borrow_mut().finish()?;
+
+    let bytes = sink.0.borrow().clone();
+    TraceReader::new(bytes.as_slice())?.collect()
+}
+
+#[test]
+fn can_trace_a_run() -> LC3Result<()> {
+    let records = trace(false)?;
+
+    let pcs: Vec<u16> = records.iter().map(|record| record.pc).collect();
+    assert_eq!(pcs, [0x3000, 0x3001, 0x3002, 0x3003, 0x3004, 0x3002, 0x3003, 0x3004, 0x3002, 0x3003, 0x3004, 0x3005]);
+    assert_eq!(records[1].reg_writes, [(1, 3), (9, 1)]);
+    assert_eq!(records[2].mem_writes, [(0x4000, 3)]);
+    assert_eq!(records[8].mem_writes, [(0x4000, 1)]);
+    assert_eq!(records[11].bytes, 0xF025);
+
+    assert_eq!(trace(true)?, records);
+
+    Ok(())
+}
+
+// Takes everything written to it, then fails to flush as a full disk would
+struct FullSink;
+
+impl Write for FullSink {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Err(std::io::Error::other("No space left on device"))
+    }
+}
+
+#[test]
+fn finishing_reports_sink_errors() -> LC3Result<()> {
+    let tracer = Tracer::new(FullSink, false)?;
+    let trace = tracer.trace();
+    let mut vm = VM::new_with_plugins(ScriptedIOHandle::new(), (tracer,));
+    vm.load_program(&PROGRAM)?;
+    vm.run()?;
+
+    assert!(trace.borrow_mut().finish().is_err());
+    // The writer is gone once finishing has been tried
+    assert!(trace.borrow_mut().finish()?.is_none());
+
+    Ok(())
+}
+
+#[test]
+fn can_round_trip_records() -> LC3Result<()> {
+    let mut records = vec![TraceRecord::new(0x3000, 0x1234), TraceRecord::new(0x2F90, 0x0000)];
+    records.push(TraceRecord::new(0x8000, 0xF025));
+    records[1].reg_writes.push((7, 0x3001));
+    records[2].mem_writes.extend([(0xFE06, 0x41), (0x4000, 0xFFFF)]);
+    // Seven writes and more take a long count
+    let mut long = TraceRecord::new(0x8001, 0xB015);
+    long.reg_writes.extend((0..7).map(|index| (index, index as u16)));
+    long.mem_writes.extend((0..300).map(|offset| (0x4000 + offset, offset)));
+    records.push(long);
+
+    for compress in [false, true] {
+        let mut writer = TraceWriter::new(Vec::new(), compress)?;
+        for record in &records {
+            writer.write(record)?;
+        }
+        let bytes = writer.finish()?;
+        let read: Vec<TraceRecord> = TraceReader::new(bytes.as_slice())?.collect::<LC3Result<_>>()?;
+        assert_eq!(read, records);
+    }
+
+    // Two bytes of tag and word for a record that follows on
+    let mut writer = TraceWriter::new(Vec::new(), false)?;
+    writer.write(&TraceRecord::new(0x3000, 0x1234))?;
+    assert_eq!(writer.finish()?.len(), 6 + 3);
+
+    assert!(TraceReader::new(&b"LC3X\x01\x00"[..]).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn can_filter_records() -> LC3Result<()> {
+    let records = trace(false)?;
+    let select = |filter: &TraceFilter| -> Vec<usize> {
+        let mut window = filter.start();
+        records
+            .iter()
+            .enumerate()
+            .filter(|(_, record)| filter.matches(record, &mut window))
+            .map(|(step, _)| step)
+            .collect()
+    };
+
+    let mut filter = TraceFilter::new();
+    filter.writes = Some("x4000-x4FFF".parse().unwrap());
+    assert_eq!(select(&filter), [2, 5, 8]);
+
+    let mut filter = TraceFilter::new();
+    filter.after = Some(0x3003);
+    filter.until = Some(0x3002);
+    assert_eq!(select(&filter), [3, 4, 5]);
+
+    let mut filter = TraceFilter::new();
+    filter.pc = Some("x3004".parse().unwrap());
+    filter.search = Some("brP".to_string());
+    assert_eq!(select(&filter), [4, 7, 10]);
+
+    assert_eq!("x10-#32".parse(), Ok(AddressRange { start: 0x10, end: 32 }));
+    assert!("x20-x10".parse::<AddressRange>().is_err());
+
+    Ok(())
+}
+
+#[test]
+fn can_render_records() -> LC3Result<()> {
+    let records = trace(false)?;
+
+    assert_eq!(to_text(1, &records[1]), "1 x3001 ADD R1, R1, #3  R1=x0003 COND=x0001");
+    assert_eq!(to_text(2, &records[2]), "2 x3002 STI R1, x3007  [x4000]=x0003");
+    assert_eq!(
+        to_json(2, &records[2]),
+        r#"{"instruction":"STI R1, x3007","memory":[{"address":16384,"value":3}],"pc":12290,"registers":{},"step":2,"word":45572}"#
+    );
+
+    Ok(())
+}
+
+#[test]
+fn can_trace_disk_transfers() -> LC3Result<()> {
+    let program = vec![
+        // LD R1, ONE then STI R1, COMMAND reads sector 0 to x0000
+        0x2202, 0xB202,
+        // HALT
+        0xF025,
+        // ONE and COMMAND
+        1, 0xFE15,
+    ];
+
+    let path = std::env::temp_dir().join(format!("lc3rs-trace-disk-{}.img", std::process::id()));
+    let disk = Disk::open(0xFE14, &path, 0, Interrupt { vector: 0x82, priority: 3 });
+    let sink = SharedSink::default();
+    let result = disk.and_then(|disk| {
+        let mut vm = VM::new_with_plugins(ScriptedIOHandle::new(), (Tracer::new(sink.clone(), false)?,));
+        vm.add_device(disk.addresses(), Box::new(disk))?;
+        vm.load_program(&program)?;
+        vm.run()
+    });
+    std::fs::remove_file(&path).map_io_error()?;
+    result?;
+
+    let bytes = sink.0.borrow().clone();
+    let records: Vec<TraceRecord> = TraceReader::new(bytes.as_slice())?.collect::<LC3Result<_>>()?;
+    // The command register's own write, then the sector
+    assert_eq!(records[1].mem_writes.len(), 1 + SECTOR_WORDS);
+    assert_eq!(records[1].mem_writes[0], (0xFE15, 1));
+
+    Ok(())
+}
@@ -0,0 +1,146 @@
+use std::collections::BTreeSet;
+
+use crate::error::{LC3Error, LC3Result};
+use crate::op::Instruction;
+use crate::trap::TrapCode;
+use crate::vm::PC_START;
+
+// Which words of a program are code and where its blocks start.
+pub(crate) struct Analysis {
+    // Address of every instruction reachable from the start of the program
+    pub(crate) code: BTreeSet<u16>,
+    // Addresses control can arrive at other than by falling through from
+    // the previous instruction. Each one gets an entry in the dispatch
+    // table.
+    pub(crate) leaders: BTreeSet<u16>,
+}
+
+impl Analysis {
+    // The code as a list of [start, end] ranges. The end is inclusive so
+    // code running up to xFFFF still fits in a u16.
+    pub(crate) fn code_ranges(&self) -> Vec<(u16, u16)> {
+        let mut ranges: Vec<(u16, u16)> = Vec::new();
+        for &address in &self.code {
+            match ranges.last_mut() {
+                // The set is sorted, so end is below address here
+                Some((_, end)) if *end + 1 == address => *end = address,
+                _ => ranges.push((address, address)),
+            }
+        }
+
+        ranges
+    }
+}
+
+pub(crate) fn word_at(program: &[u16], address: u16) -> Option<u16> {
+    let index = address.checked_sub(PC_START)?;
+    program.get(index as usize).copied()
+}
+
+// Find the program's code and check it can be translated
+pub(crate) fn analyze(program: &[u16]) -> LC3Result<Analysis> {
+    let analysis = find_code(program);
+    check_stores(program, &analysis)?;
+
+    Ok(analysis)
+}
+
+// Follow every path through the program from its first instruction. JMP
+// and JSRR targets aren't known until runtime, so subroutines are assumed
+// to return to the instruction after the call, and code only reachable
+// through a register jump isn't found.
+pub(crate) fn find_code(program: &[u16]) -> Analysis {
+    let mut code = BTreeSet::new();
+    let mut leaders = BTreeSet::new();
+    let mut pending = vec![PC_START];
+    leaders.insert(PC_START);
+
+    while let Some(address) = pending.pop() {
+        let bytes = match word_at(program, address) {
+            Some(bytes) => bytes,
+            // Running off the end of the program is reported at runtime
+            None => continue,
+        };
+        if !code.insert(address) {
+            continue;
+        }
+
+        let next = address.wrapping_add(1);
+        let mut successors = Vec::new();
+        match Instruction::decode(bytes) {
+            Instruction::Br { flags, offset } => {
+                if flags != 0 {
+                    successors.push((next.wrapping_add(offset), true));
+                }
+                if flags != 0b111 {
+                    successors.push((next, flags != 0));
+                }
+            }
+            Instruction::Jsr { offset } => {
+                successors.push((next.wrapping_add(offset), true));
+                successors.push((next, true));
+            }
+            Instruction::Jsrr { .. } => successors.push((next, true)),
+            Instruction::Jmp { .. } | Instruction::Rti | Instruction::Res => {}
+            Instruction::Trap { code } if code == TrapCode::Halt as u8 => {}
+            _ => successors.push((next, false)),
+        }
+
+        for (successor, is_leader) in successors {
+            if is_leader {
+                leaders.insert(successor);
+            }
+            pending.push(successor);
+        }
+    }
+
+    let mut analysis = Analysis { code, leaders };
+    add_taken_addresses(program, &mut analysis);
+
+    analysis
+}
+
+// JMP and JSRR usually go to code whose address was taken with LEA or
+// written into the program as data, so those addresses get dispatch table
+// entries too.
+fn add_taken_addresses(program: &[u16], analysis: &mut Analysis) {
+    let taken: Vec<u16> = program
+        .iter()
+        .enumerate()
+        .filter_map(|(index, &word)| {
+            let address = PC_START + index as u16;
+            if !analysis.code.contains(&address) {
+                return Some(word);
+            }
+            match Instruction::decode(word) {
+                Instruction::Lea { offset, .. } => Some(address.wrapping_add(1).wrapping_add(offset)),
+                _ => None,
+            }
+        })
+        .filter(|target| analysis.code.contains(target))
+        .collect();
+
+    analysis.leaders.extend(taken);
+}
+
+// Stores to a fixed address can be checked now. Stores through registers
+// are checked when they happen.
+fn check_stores(program: &[u16], analysis: &Analysis) -> LC3Result<()> {
+    for &address in &analysis.code {
+        let next = address.wrapping_add(1);
+        let target = match word_at(program, address).map(Instruction::decode) {
+            Some(Instruction::St { offset, .. }) => Some(next.wrapping_add(offset)),
+            Some(Instruction::Sti { offset, .. }) => word_at(program, next.wrapping_add(offset)),
+            _ => None,
+        };
+
+        if let Some(target) = target.filter(|target| analysis.code.contains(target)) {
+            return Err(LC3Error::Translate(format!(
+                "the store at x{:04X} overwrites the code at x{:04X}",
+                address, target
+            )));
+        }
+    }
+
+    Ok(())
+}
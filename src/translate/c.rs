@@ -0,0 +1,93 @@
+use super::{hex, words, Syntax};
+
+// A standalone C program with the runtime copied into it
+pub(super) struct C;
+
+const RUNTIME: &str = include_str!("runtime.c");
+
+impl Syntax for C {
+    const INDENT: usize = 12;
+
+    fn prelude(&self, program: &[u16], code: &[(u16, u16)]) -> String {
+        format!(
+            "/* Translated from an LC-3 program by lc3rs */\n\
+             \n\
+             {}\n\
+             static const uint16_t program[{}] = {{\n{}}};\n\
+             \n\
+             /* Address ranges holding translated code */\n\
+             static const uint16_t code[{}][2] = {{\n{}}};\n\
+             \n\
+             static lc3_machine m;\n\
+             \n\
+             int main(void) {{\n\
+             \x20   lc3_init(&m, program, {}, code, {});\n\
+             \x20   uint16_t pc = LC3_PC_START;\n\
+             \x20   for (;;) {{\n\
+             \x20       switch (pc) {{\n",
+            RUNTIME,
+            program.len().max(1),
+            words(program.iter().map(|&word| hex(word))),
+            code.len().max(1),
+            words(code.iter().map(|&(start, end)| format!("{{{}, {}}}", hex(start), hex(end)))),
+            program.len(),
+            code.len(),
+        )
+    }
+
+    fn case_start(&self, address: u16) -> String {
+        format!("        case {}: {{\n", hex(address))
+    }
+
+    fn case_end(&self) -> String {
+        "        }\n".to_string()
+    }
+
+    fn postlude(&self) -> String {
+        "        default:\n            return lc3_untranslated(pc);\n        }\n    }\n}\n".to_string()
+    }
+
+    fn add(&self, left: &str, right: &str) -> String {
+        format!("(uint16_t)({} + {})", left, right)
+    }
+
+    fn not(&self, value: &str) -> String {
+        format!("(uint16_t)~{}", value)
+    }
+
+    fn mem_read(&self, address: &str) -> String {
+        format!("lc3_mem_read(&m, {})", address)
+    }
+
+    fn mem_write(&self, address: &str, value: &str) -> String {
+        format!("lc3_mem_write(&m, {}, {});", address, value)
+    }
+
+    fn set_flags(&self, value: &str) -> String {
+        format!("lc3_set_flags(&m, {});", value)
+    }
+
+    fn declare(&self, name: &str, value: &str) -> String {
+        format!("uint16_t {} = {};", name, value)
+    }
+
+    fn jump(&self, target: &str) -> String {
+        format!("pc = {};\nbreak;", target)
+    }
+
+    fn branch(&self, flags: u16, target: &str) -> String {
+        format!("if (m.cond & {}) {{\n    pc = {};\n    break;\n}}", flags, target)
+    }
+
+    fn trap(&self, code: u8) -> String {
+        format!("if (lc3_trap(&m, 0x{:02X})) {{\n    return 0;\n}}", code)
+    }
+
+    fn halt(&self) -> String {
+        "lc3_trap(&m, 0x25);\nreturn 0;".to_string()
+    }
+
+    fn unimplemented(&self) -> String {
+        "return lc3_unimplemented();".to_string()
+    }
+}
@@ -0,0 +1,188 @@
+pub(crate) mod analysis;
+mod c;
+pub mod runtime;
+mod rust;
+#[cfg(test)]
+mod test;
+
+use std::str::FromStr;
+
+use analysis::{analyze, word_at, Analysis};
+use crate::error::LC3Result;
+use crate::op::{Instruction, Operand};
+use crate::trap::TrapCode;
+
+// Ahead-of-time translation of LC-3 programs to source code. Every block of
+// the program becomes one arm of a dispatch on the program counter, so
+// branches and calls go straight to their arm and JMP, JSRR and RET look
+// the target up at runtime. Memory and traps are left to a small runtime
+// that behaves like the VM.
+
+pub enum Language {
+    Rust,
+    C,
+}
+
+impl FromStr for Language {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text {
+            "rust" => Ok(Self::Rust),
+            "c" => Ok(Self::C),
+            other => Err(format!("Unknown language {:?}", other)),
+        }
+    }
+}
+
+// Translate a program loaded at the usual start address. Programs that
+// store over their own code at a fixed address are rejected. Stores
+// through a register are checked at runtime and stop the program.
+pub fn translate(program: &[u16], language: Language) -> LC3Result<String> {
+    let analysis = analyze(program)?;
+    let source = match language {
+        Language::Rust => emit(&rust::Rust, program, &analysis),
+        Language::C => emit(&c::C, program, &analysis),
+    };
+
+    Ok(source)
+}
+
+// The parts of the output that differ between languages. Expressions and
+// statements refer to the machine as m and to the program counter as pc.
+trait Syntax {
+    // Indentation of the statements inside a dispatch arm
+    const INDENT: usize;
+
+    fn prelude(&self, program: &[u16], code: &[(u16, u16)]) -> String;
+    fn case_start(&self, address: u16) -> String;
+    fn case_end(&self) -> String;
+    fn postlude(&self) -> String;
+
+    fn add(&self, left: &str, right: &str) -> String;
+    fn not(&self, value: &str) -> String;
+    fn mem_read(&self, address: &str) -> String;
+    fn mem_write(&self, address: &str, value: &str) -> String;
+    fn set_flags(&self, value: &str) -> String;
+    fn declare(&self, name: &str, value: &str) -> String;
+    fn jump(&self, target: &str) -> String;
+    fn branch(&self, flags: u16, target: &str) -> String;
+    fn trap(&self, code: u8) -> String;
+    fn halt(&self) -> String;
+    fn unimplemented(&self) -> String;
+}
+
+fn reg(index: u8) -> String {
+    format!("m.r[{}]", index)
+}
+
+fn hex(value: u16) -> String {
+    format!("0x{:04X}", value)
+}
+
+// One value per line, for the initializer of an array
+fn words(values: impl Iterator<Item = String>) -> String {
+    values.map(|value| format!("    {},\n", value)).collect()
+}
+
+fn operand(operand: Operand) -> String {
+    match operand {
+        Operand::Register(index) => reg(index),
+        Operand::Immediate(value) => hex(value),
+    }
+}
+
+fn emit<S: Syntax>(syntax: &S, program: &[u16], analysis: &Analysis) -> String {
+    let mut out = syntax.prelude(program, &analysis.code_ranges());
+    let indent = " ".repeat(S::INDENT);
+
+    for &leader in analysis.leaders.iter().filter(|address| analysis.code.contains(address)) {
+        out += &syntax.case_start(leader);
+
+        let mut address = leader;
+        loop {
+            let instruction = Instruction::decode(word_at(program, address).unwrap_or_default());
+            let next = address.wrapping_add(1);
+            out += &format!("{}// {}\n", indent, instruction.disassemble(next));
+            let (statements, ends_block) = translate_instruction(syntax, instruction, address);
+
+            // Blocks that run into another block or off the end of the
+            // code finish with an explicit jump
+            let leaves_block = analysis.leaders.contains(&next) || !analysis.code.contains(&next);
+            let mut lines = statements;
+            if leaves_block && !ends_block {
+                lines.push(syntax.jump(&hex(next)));
+            }
+            for line in lines.iter().flat_map(|line| line.lines()) {
+                out += &format!("{}{}\n", indent, line);
+            }
+
+            if ends_block || leaves_block {
+                break;
+            }
+            address = next;
+        }
+
+        out += &syntax.case_end();
+    }
+
+    out + &syntax.postlude()
+}
+
+// The statements for one instruction and whether it always leaves the
+// current block
+fn translate_instruction<S: Syntax>(syntax: &S, instruction: Instruction, address: u16) -> (Vec<String>, bool) {
+    let next = address.wrapping_add(1);
+    let relative = |offset: u16| hex(next.wrapping_add(offset));
+    let write_flagged = |target: u8, value: String| {
+        vec![format!("{} = {};", reg(target), value), syntax.set_flags(&reg(target))]
+    };
+    // Name for a temporary that is unique within the arm
+    let temporary = format!("address_{:04x}", address);
+
+    match instruction {
+        Instruction::Br { flags: 0, .. } => (vec![], false),
+        Instruction::Br { flags: 0b111, offset } => (vec![syntax.jump(&relative(offset))], true),
+        Instruction::Br { flags, offset } => (vec![syntax.branch(flags, &relative(offset))], false),
+        Instruction::Add { target, source, operand: right } => {
+            (write_flagged(target, syntax.add(&reg(source), &operand(right))), false)
+        }
+        Instruction::And { target, source, operand: right } => {
+            (write_flagged(target, format!("{} & {}", reg(source), operand(right))), false)
+        }
+        Instruction::Not { target, source } => (write_flagged(target, syntax.not(&reg(source))), false),
+        Instruction::Ld { target, offset } => (write_flagged(target, syntax.mem_read(&relative(offset))), false),
+        Instruction::Ldi { target, offset } => {
+            let mut statements = vec![syntax.declare(&temporary, &syntax.mem_read(&relative(offset)))];
+            statements.extend(write_flagged(target, syntax.mem_read(&temporary)));
+            (statements, false)
+        }
+        Instruction::Ldr { target, base, offset } => {
+            let address = syntax.add(&reg(base), &hex(offset));
+            (write_flagged(target, syntax.mem_read(&address)), false)
+        }
+        Instruction::Lea { target, offset } => (write_flagged(target, relative(offset)), false),
+        Instruction::St { source, offset } => (vec![syntax.mem_write(&relative(offset), &reg(source))], false),
+        Instruction::Sti { source, offset } => {
+            let statements = vec![
+                syntax.declare(&temporary, &syntax.mem_read(&relative(offset))),
+                syntax.mem_write(&temporary, &reg(source)),
+            ];
+            (statements, false)
+        }
+        Instruction::Str { source, base, offset } => {
+            let address = syntax.add(&reg(base), &hex(offset));
+            (vec![syntax.mem_write(&address, &reg(source))], false)
+        }
+        Instruction::Jsr { offset } => {
+            (vec![format!("{} = {};", reg(7), hex(next)), syntax.jump(&relative(offset))], true)
+        }
+        // R7 is written first, like the VM does, so JSRR R7 returns to the
+        // instruction after it
+        Instruction::Jsrr { base } => (vec![format!("{} = {};", reg(7), hex(next)), syntax.jump(&reg(base))], true),
+        Instruction::Jmp { base } => (vec![syntax.jump(&reg(base))], true),
+        Instruction::Trap { code } if code == TrapCode::Halt as u8 => (vec![syntax.halt()], true),
+        Instruction::Trap { code } => (vec![syntax.trap(code)], false),
+        Instruction::Rti | Instruction::Res => (vec![syntax.unimplemented()], true),
+    }
+}
@@ -0,0 +1,149 @@
+/* Runtime for LC-3 programs translated to C by lc3rs. Memory, flags and
+ * traps behave the way they do in the lc3rs VM. Needs a POSIX system for
+ * the keyboard status register. */
+
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/select.h>
+
+#define LC3_PC_START 0x3000
+#define LC3_KBSR 0xFE00
+#define LC3_KBDR 0xFE02
+#define LC3_DSR 0xFE04
+#define LC3_DDR 0xFE06
+#define LC3_FL_POS 1
+#define LC3_FL_ZRO 2
+#define LC3_FL_NEG 4
+
+typedef struct {
+    uint16_t r[8];
+    uint16_t cond;
+    uint16_t memory[65536];
+    /* Addresses holding translated code, which the program may not write to */
+    uint8_t code[65536];
+} lc3_machine;
+
+static void lc3_fail(const char *message, unsigned value) {
+    fflush(stdout);
+    fprintf(stderr, message, value);
+    fputc('\n', stderr);
+    exit(1);
+}
+
+static void lc3_init(lc3_machine *m, const uint16_t *program, size_t len,
+                     const uint16_t (*code)[2], size_t ranges) {
+    memset(m, 0, sizeof *m);
+    memcpy(&m->memory[LC3_PC_START], program, len * sizeof *program);
+    for (size_t i = 0; i < ranges; i++) {
+        for (uint32_t address = code[i][0]; address <= code[i][1]; address++) {
+            m->code[address] = 1;
+        }
+    }
+}
+
+static int lc3_key_waiting(void) {
+    fd_set fds;
+    struct timeval timeout = {0, 0};
+    FD_ZERO(&fds);
+    FD_SET(0, &fds);
+    return select(1, &fds, NULL, NULL, &timeout) > 0;
+}
+
+static uint16_t lc3_getchar(void) {
+    int ch = getchar();
+    if (ch == EOF) {
+        lc3_fail("Input was exhausted", 0);
+    }
+    return (uint16_t)ch;
+}
+
+static void lc3_putchar(uint16_t ch) {
+    putchar((char)ch);
+    fflush(stdout);
+}
+
+static uint16_t lc3_mem_read(lc3_machine *m, uint16_t address) {
+    if (address == LC3_KBSR) {
+        if (lc3_key_waiting()) {
+            m->memory[LC3_KBSR] = 1 << 15;
+            m->memory[LC3_KBDR] = lc3_getchar();
+        } else {
+            m->memory[LC3_KBSR] = 0;
+        }
+    } else if (address == LC3_DSR) {
+        return 1 << 15;
+    }
+    return m->memory[address];
+}
+
+static void lc3_mem_write(lc3_machine *m, uint16_t address, uint16_t value) {
+    if (m->code[address]) {
+        lc3_fail("Write to x%04X would modify the program's own code", address);
+    }
+    if (address == LC3_DDR) {
+        lc3_putchar(value);
+    }
+    m->memory[address] = value;
+}
+
+static void lc3_set_flags(lc3_machine *m, uint16_t value) {
+    if (value == 0) {
+        m->cond = LC3_FL_ZRO;
+    } else if (value >> 15) {
+        m->cond = LC3_FL_NEG;
+    } else {
+        m->cond = LC3_FL_POS;
+    }
+}
+
+/* Run a trap routine. Returns non-zero when the program should stop. */
+static int lc3_trap(lc3_machine *m, uint8_t code) {
+    uint16_t address = m->r[0];
+    switch (code) {
+    case 0x20:
+        m->r[0] = lc3_getchar();
+        return 0;
+    case 0x21:
+        lc3_putchar(m->r[0] & 0xFF);
+        return 0;
+    case 0x22:
+        for (uint16_t value; (value = lc3_mem_read(m, address)) != 0; address++) {
+            lc3_putchar(value & 0xFF);
+        }
+        return 0;
+    case 0x23:
+        printf("Enter a character: \n");
+        m->r[0] = lc3_getchar();
+        lc3_putchar(m->r[0]);
+        return 0;
+    case 0x24:
+        for (;; address++) {
+            uint16_t value = lc3_mem_read(m, address);
+            if ((value & 0xFF) == 0) {
+                return 0;
+            }
+            lc3_putchar(value & 0xFF);
+            if ((value >> 8) == 0) {
+                return 0;
+            }
+            lc3_putchar(value >> 8);
+        }
+    case 0x25:
+        return 1;
+    default:
+        lc3_fail("Bad trap code %u encountered during command parsing", code);
+        return 1;
+    }
+}
+
+static int lc3_untranslated(uint16_t target) {
+    lc3_fail("Jumped to x%04X, which wasn't translated", target);
+    return 1;
+}
+
+static int lc3_unimplemented(void) {
+    lc3_fail("Attempt to execute unimplemented op code", 0);
+    return 1;
+}
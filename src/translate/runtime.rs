@@ -0,0 +1,157 @@
+use crate::condition_flags::{FL_NEG, FL_POS, FL_ZRO};
+use crate::error::{LC3Error, LC3Result};
+use crate::io::{IOHandle, RealIOHandle};
+use crate::trap::TrapCode;
+use crate::device::{DDR, DSR, KBDR, KBSR};
+use crate::vm::{MEMORY_SIZE, PC_START};
+
+// The machine state and services programs translated to Rust run against.
+// Memory, flags and traps behave the way they do in the VM. The registers
+// are public so translated code can use them directly.
+pub struct Machine<IOType: IOHandle = RealIOHandle> {
+    pub r: [u16; 8],
+    pub cond: u16,
+    memory: Vec<u16>,
+    // Addresses holding translated code, which the program may not write to
+    code: Vec<bool>,
+    io_handle: IOType,
+}
+
+impl Machine<RealIOHandle> {
+    pub fn new() -> Self {
+        Self::new_with_io(RealIOHandle::new())
+    }
+}
+
+impl Default for Machine<RealIOHandle> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<IOType: IOHandle> Machine<IOType> {
+    pub fn new_with_io(io_handle: IOType) -> Self {
+        Self {
+            r: [0; 8],
+            cond: 0,
+            memory: vec![0; MEMORY_SIZE],
+            code: vec![false; MEMORY_SIZE],
+            io_handle,
+        }
+    }
+
+    // Load the program at the usual start address. code lists the
+    // [start, end] ranges of it that were translated.
+    pub fn load(&mut self, program: &[u16], code: &[(u16, u16)]) -> LC3Result<()> {
+        let max_len = MEMORY_SIZE - PC_START as usize;
+        if program.len() > max_len {
+            return Err(LC3Error::ProgramSize {
+                len: program.len(),
+                max_len,
+            });
+        }
+
+        let start = PC_START as usize;
+        self.memory[start..start + program.len()].copy_from_slice(program);
+        for &(start, end) in code {
+            self.code[start as usize..=end as usize].fill(true);
+        }
+
+        Ok(())
+    }
+
+    pub fn mem_read(&mut self, address: u16) -> LC3Result<u16> {
+        if address == KBSR {
+            if self.io_handle.is_key_down()? {
+                self.memory[KBSR as usize] = 1 << 15;
+                self.memory[KBDR as usize] = self.io_handle.getchar()? as u16;
+            } else {
+                self.memory[KBSR as usize] = 0;
+            }
+        } else if address == DSR {
+            return Ok(1 << 15);
+        }
+
+        Ok(self.memory[address as usize])
+    }
+
+    pub fn mem_write(&mut self, address: u16, value: u16) -> LC3Result<()> {
+        if self.code[address as usize] {
+            return Err(LC3Error::SelfModifyingCode { address });
+        }
+        if address == DDR {
+            self.io_handle.putchar(value as u8 as char)?;
+        }
+        self.memory[address as usize] = value;
+
+        Ok(())
+    }
+
+    pub fn set_flags(&mut self, value: u16) {
+        self.cond = if value == 0 {
+            FL_ZRO
+        } else if (value >> 15) == 1 {
+            FL_NEG
+        } else {
+            FL_POS
+        };
+    }
+
+    // Run a trap routine. Returns true when the program should stop.
+    pub fn trap(&mut self, code: u8) -> LC3Result<bool> {
+        match TrapCode::from_int(code)? {
+            TrapCode::GetC => self.r[0] = self.io_handle.getchar()? as u16,
+            TrapCode::Out => self.io_handle.putchar(self.r[0] as u8 as char)?,
+            TrapCode::PutS => {
+                let mut address = self.r[0];
+                loop {
+                    let value = self.mem_read(address)?;
+                    if value == 0 {
+                        break;
+                    }
+                    self.io_handle.putchar(value as u8 as char)?;
+                    address = address.wrapping_add(1);
+                }
+            }
+            TrapCode::In => {
+                println!("Enter a character: ");
+                let ch = self.io_handle.getchar()?;
+                self.r[0] = ch as u16;
+                self.io_handle.putchar(ch)?;
+            }
+            TrapCode::PutSp => {
+                let mut address = self.r[0];
+                'outer: loop {
+                    let value = self.mem_read(address)?;
+                    for byte in [value as u8, (value >> 8) as u8] {
+                        if byte == 0 {
+                            break 'outer;
+                        }
+                        self.io_handle.putchar(byte as char)?;
+                    }
+                    address = address.wrapping_add(1);
+                }
+            }
+            TrapCode::Halt => return Ok(true),
+        }
+
+        self.io_handle.halt_requested()
+    }
+
+    // The error for a jump to code that wasn't translated
+    pub fn untranslated(&self, target: u16) -> LC3Error {
+        LC3Error::UntranslatedJump { target }
+    }
+
+    pub fn unimplemented(&self) -> LC3Error {
+        LC3Error::Internal("Attempt to execute unimplemented op code".to_string())
+    }
+
+    pub fn io_handle(&self) -> &IOType {
+        &self.io_handle
+    }
+
+    pub fn into_io_handle(self) -> IOType {
+        self.io_handle
+    }
+}
@@ -0,0 +1,94 @@
+use super::{hex, words, Syntax};
+
+// A Rust program that depends on the lc3rs crate for its runtime
+pub(super) struct Rust;
+
+impl Syntax for Rust {
+    const INDENT: usize = 16;
+
+    fn prelude(&self, program: &[u16], code: &[(u16, u16)]) -> String {
+        format!(
+            "// Translated from an LC-3 program by lc3rs\n\
+             #![allow(unused_mut, unreachable_code, clippy::all)]\n\
+             \n\
+             use lc3rs::translate::runtime::Machine;\n\
+             use lc3rs::{{IOHandle, LC3Result}};\n\
+             \n\
+             const PROGRAM: [u16; {}] = [\n{}];\n\
+             \n\
+             // Address ranges holding translated code\n\
+             const CODE: [(u16, u16); {}] = [\n{}];\n\
+             \n\
+             fn main() -> LC3Result<()> {{\n\
+             \x20   let mut m = Machine::new();\n\
+             \x20   m.load(&PROGRAM, &CODE)?;\n\
+             \x20   run(&mut m)\n\
+             }}\n\
+             \n\
+             fn run<IOType: IOHandle>(m: &mut Machine<IOType>) -> LC3Result<()> {{\n\
+             \x20   let mut pc: u16 = 0x3000;\n\
+             \x20   loop {{\n\
+             \x20       match pc {{\n",
+            program.len(),
+            words(program.iter().map(|&word| hex(word))),
+            code.len(),
+            words(code.iter().map(|&(start, end)| format!("({}, {})", hex(start), hex(end)))),
+        )
+    }
+
+    fn case_start(&self, address: u16) -> String {
+        format!("            {} => {{\n", hex(address))
+    }
+
+    fn case_end(&self) -> String {
+        "            }\n".to_string()
+    }
+
+    fn postlude(&self) -> String {
+        "            _ => return Err(m.untranslated(pc)),\n        }\n    }\n}\n".to_string()
+    }
+
+    fn add(&self, left: &str, right: &str) -> String {
+        format!("{}.wrapping_add({})", left, right)
+    }
+
+    fn not(&self, value: &str) -> String {
+        format!("!{}", value)
+    }
+
+    fn mem_read(&self, address: &str) -> String {
+        format!("m.mem_read({})?", address)
+    }
+
+    fn mem_write(&self, address: &str, value: &str) -> String {
+        format!("m.mem_write({}, {})?;", address, value)
+    }
+
+    fn set_flags(&self, value: &str) -> String {
+        format!("m.set_flags({});", value)
+    }
+
+    fn declare(&self, name: &str, value: &str) -> String {
+        format!("let {} = {};", name, value)
+    }
+
+    fn jump(&self, target: &str) -> String {
+        format!("pc = {};\ncontinue;", target)
+    }
+
+    fn branch(&self, flags: u16, target: &str) -> String {
+        format!("if m.cond & {} != 0 {{\n    pc = {};\n    continue;\n}}", flags, target)
+    }
+
+    fn trap(&self, code: u8) -> String {
+        format!("if m.trap(0x{:02X})? {{\n    return Ok(());\n}}", code)
+    }
+
+    fn halt(&self) -> String {
+        "m.trap(0x25)?;\nreturn Ok(());".to_string()
+    }
+
+    fn unimplemented(&self) -> String {
+        "return Err(m.unimplemented());".to_string()
+    }
+}
@@ -0,0 +1,105 @@
+use std::process::Command;
+
+use super::analysis::analyze;
+use super::runtime::Machine;
+use super::{translate, Language};
+use crate::error::{BoxErrors, LC3Error, LC3Result};
+use crate::fixtures::print_program;
+use crate::io::ScriptedIOHandle;
+use crate::plugin::NoPlugins;
+use crate::vm::VM;
+
+#[test]
+fn can_find_blocks() -> LC3Result<()> {
+    let analysis = analyze(&print_program())?;
+
+    let leaders: Vec<u16> = analysis.leaders.iter().copied().collect();
+    assert_eq!(leaders, vec![0x3000, 0x3002, 0x3004, 0x3008, 0x3009]);
+    assert_eq!(analysis.code_ranges(), vec![(0x3000, 0x300A)]);
+
+    Ok(())
+}
+
+#[test]
+fn can_find_code_up_to_the_last_address() -> LC3Result<()> {
+    // ADD R0, R0, #0 all the way to a HALT at xFFFF
+    let mut program = vec![0b0001_0000_0010_0000; 0xCFFF];
+    program.push(0xF025);
+    let analysis = analyze(&program)?;
+
+    assert_eq!(analysis.code_ranges(), vec![(0x3000, 0xFFFF)]);
+
+    Ok(())
+}
+
+#[test]
+fn can_reject_store_into_code() {
+    // ST R0 over itself then HALT
+    let program = vec![0b0011_0001_1111_1111, 0xF025];
+
+    let result = translate(&program, Language::Rust);
+    assert!(matches!(result, Err(LC3Error::Translate(_))));
+}
+
+#[test]
+fn can_translate_to_rust() -> LC3Result<()> {
+    let source = translate(&print_program(), Language::Rust)?;
+
+    assert!(source.contains("0x3009 => {"));
+    assert!(source.contains("// JSR x3009"));
+    assert!(source.contains("m.r[7] = 0x3002;"));
+    assert!(source.contains("if m.cond & 1 != 0 {"));
+    assert!(source.contains("_ => return Err(m.untranslated(pc)),"));
+
+    Ok(())
+}
+
+#[test]
+fn can_run_machine_traps() -> LC3Result<()> {
+    let program = print_program();
+    let mut machine = Machine::new_with_io(ScriptedIOHandle::new());
+    machine.load(&program, &[(0x3000, 0x300A)])?;
+
+    machine.r[0] = 0x300C;
+    assert!(!machine.trap(0x22)?);
+    assert!(machine.trap(0x25)?);
+    assert_eq!(machine.io_handle().output(), "hi\n");
+
+    let result = machine.mem_write(0x3004, 0);
+    assert!(matches!(result, Err(LC3Error::SelfModifyingCode { address: 0x3004 })));
+    machine.mem_write(0x300B, '+' as u16)?;
+
+    Ok(())
+}
+
+// Compiles the C translation with the system compiler and checks it prints
+// the same as the VM. Skipped when there is no C compiler.
+#[test]
+fn can_run_translated_c() -> LC3Result<()> {
+    let program = print_program();
+    let mut vm = VM::new_with_plugins(ScriptedIOHandle::new(), NoPlugins);
+    vm.load_program(&program)?;
+    vm.run()?;
+
+    let dir = std::env::temp_dir().join(format!("lc3rs-translate-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).map_io_error()?;
+    let source_path = dir.join("program.c");
+    let binary_path = dir.join("program");
+    std::fs::write(&source_path, translate(&program, Language::C)?).map_io_error()?;
+
+    let compiled = Command::new("cc").arg("-o").arg(&binary_path).arg(&source_path).status();
+    match compiled {
+        Ok(status) => assert!(status.success()),
+        Err(err) => {
+            eprintln!("Skipping, couldn't run cc: {}", err);
+            return Ok(());
+        }
+    }
+    let output = Command::new(&binary_path).output().map_io_error()?;
+    std::fs::remove_dir_all(&dir).map_io_error()?;
+
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), vm.io_handle().output());
+
+    Ok(())
+}
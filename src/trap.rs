@@ -23,4 +23,16 @@ impl TrapCode {
 
         Ok(code)
     }
+
+    // The assembler's name for the trap
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Self::GetC => "GETC",
+            Self::Out => "OUT",
+            Self::PutS => "PUTS",
+            Self::In => "IN",
+            Self::PutSp => "PUTSP",
+            Self::Halt => "HALT",
+        }
+    }
 }
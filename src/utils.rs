@@ -8,12 +8,12 @@ pub(crate) fn sign_extend(x: u16, bit_count: u8) -> u16 {
         // correct, since the leftmost bit is already how we want it but this
         // causes bit shift overflow when the bit count is at 16 so this is the
         // neatest way to express it safely.
-        return x | (0xFFFF << (bit_count - 1));
+        x | (0xFFFF << (bit_count - 1))
     } else {
         // Set everything to the left to zero
         // Probably redundant (they should be zero if the bit count is
         // accurate), but best to be safe.
-        return x & !(0xFFFF << (bit_count - 1));
+        x & !(0xFFFF << (bit_count - 1))
     }
 }
 
@@ -1,35 +1,72 @@
+#[cfg(test)]
 use crate::command::Command;
 use crate::condition_flags::{FL_NEG, FL_POS, FL_ZRO};
-use crate::error::{BoxErrors, LC3Error, LC3Result};
+use crate::device::{Device, DeviceContext, Interrupt, MemoryBus};
+use crate::error::{LC3Error, LC3Result};
 use crate::io::{IOHandle, RealIOHandle};
-use crate::op::{handler, Op};
-use crate::plugin::{Event, Plugin};
-use crate::register::Register::{RCond, RPC};
+#[cfg(feature = "jit")]
+use crate::jit::{Jit, MMIO_START};
+use crate::limits::Limits;
+use crate::op::{handler, Instruction};
+use crate::plugin::{DynPlugins, Event, Plugin, PluginSet};
+use crate::protection::{Access, Protection, ProtectionMap};
+use crate::register::Register::{RCond, RPC, RR6};
 use crate::register::{Register, NUM_REGISTERS};
 
-const MEMORY_SIZE: usize = (u16::MAX as usize) + 1;
+use std::ops::RangeInclusive;
+use std::time::Instant;
+
+pub(crate) const MEMORY_SIZE: usize = (u16::MAX as usize) + 1;
+
+pub(crate) const PC_START: u16 = 0x3000; // Initial program counter
 
-const PC_START: u16 = 0x3000; // Initial program counter
+// Interrupt handler addresses, indexed by the interrupt's vector
+pub const INTERRUPT_VECTOR_TABLE: u16 = 0x0100;
+// The supervisor stack grows down from just below the program
+pub const SUPERVISOR_STACK_START: u16 = 0x3000;
 
-// Mem Mapped Register Locations
-// There are 3 registers listed in the spec
-// (https://courses.engr.illinois.edu/ece411/fa2019/mp/LC3b_ISA.pdf
-// or https://justinmeiners.github.io/lc3-vm/supplies/lc3-isa.pdf) we don't
-// implement here yet, the display status register, display data register and
-// the machine control register.
-const KB_STATUS_POS: u16 = 0xFE00; // Keyboard Status Register
-const KB_DATA_POS: u16 = 0xFE02; // Keyboard Data Register
+const PSR_USER: u16 = 1 << 15;
 
-pub struct VM<IOType: IOHandle> {
-    // TODO: Splitting the state between a VM state component and
-    // a  plugin manager component would make it easier for the compiler to
-    // reason about mutability during plugin notifications and push some of
-    // the fiddly plugin management logic into a class where it's more relevant.
+// Reading the clock on every instruction would be a noticeable cost, so the
+// time limit is only checked this often.
+const TIME_CHECK_INTERVAL: u64 = 1024;
+
+// Plugins is the set of plugins notified of every event. The default set
+// takes plugins at runtime through add_plugin. VMs built with
+// new_with_plugins can use NoPlugins, where notifying compiles to nothing,
+// or a tuple of plugins, which are dispatched statically.
+pub struct VM<IOType: IOHandle, Plugins = DynPlugins<IOType>> {
     memory: [u16; MEMORY_SIZE],
+    // Decoded form of each memory word that has been executed, so a loop
+    // only pays for decoding once. Writing to an address clears its entry.
+    decoded: Vec<Option<Instruction>>,
+    decode_cache: bool,
     registers: [u16; NUM_REGISTERS],
+    // Memory mapped devices, starting with the keyboard and display
+    bus: MemoryBus,
+    // None until a region is protected, so unprotected VMs skip the checks
+    protection: Option<ProtectionMap>,
+    // Where load_program last put a program
+    program: Option<RangeInclusive<u16>>,
     running: bool,
     io_handle: IOType,
-    plugins: Option<Vec<Box<dyn Plugin<IOType>>>>,
+    // None while the plugins are being notified, see notify_plugins
+    plugins: Option<Plugins>,
+    limits: Limits,
+    instructions_executed: u64,
+    output_chars: u64,
+    started_at: Option<Instant>,
+    // Address of the instruction currently being executed
+    instruction_address: u16,
+    // The rest of the processor status register. Programs run in user
+    // mode at priority 0 until an interrupt is taken.
+    priority: u8,
+    supervisor: bool,
+    // R6 for whichever of the two stacks isn't in use
+    saved_ssp: u16,
+    saved_usp: u16,
+    #[cfg(feature = "jit")]
+    jit: Option<Jit>,
 }
 
 impl VM<RealIOHandle> {
@@ -40,41 +77,341 @@ impl VM<RealIOHandle> {
     }
 }
 
+impl Default for VM<RealIOHandle> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<IOType: IOHandle> VM<IOType> {
     pub fn add_plugin(&mut self, plugin: Box<dyn Plugin<IOType>>) {
-        self.plugins.as_mut().map(|s| s.push(plugin));
+        if let Some(plugins) = self.plugins.as_mut() {
+            plugins.push(plugin);
+        }
     }
 
     // If there end up being more options to tweak might want to break out
     // a builder for this one, but right now this is fine.
     pub fn new_with_io(io_handle: IOType) -> Self {
+        Self::new_with_plugins(io_handle, DynPlugins::new())
+    }
+}
+
+// Accessors that plugins can use whatever plugin set they're in
+impl<IOType: IOHandle, Plugins> VM<IOType, Plugins> {
+    // Address of the instruction being executed, or of the last one
+    // executed between steps
+    pub fn instruction_address(&self) -> u16 {
+        self.instruction_address
+    }
+
+    // The condition flags, read without raising an event
+    pub fn condition_flags(&self) -> u16 {
+        self.registers[RCond.to_u8() as usize]
+    }
+
+    // Number of instructions executed since the last call to start
+    pub fn instructions_executed(&self) -> u64 {
+        self.instructions_executed
+    }
+
+    // A word of memory, read without raising an event or polling the
+    // keyboard
+    pub fn peek_memory(&self, address: u16) -> u16 {
+        self.memory[address as usize]
+    }
+
+    pub fn bus(&self) -> &MemoryBus {
+        &self.bus
+    }
+
+    // The addresses load_program last wrote, if it has loaded anything
+    pub fn program(&self) -> Option<RangeInclusive<u16>> {
+        self.program.clone()
+    }
+}
+
+impl<IOType: IOHandle, Plugins: PluginSet<IOType>> VM<IOType, Plugins> {
+    pub fn new_with_plugins(io_handle: IOType, plugins: Plugins) -> Self {
         let memory = [0u16; MEMORY_SIZE];
         let registers = [0u16; NUM_REGISTERS];
         VM {
             memory,
+            decoded: vec![None; MEMORY_SIZE],
+            decode_cache: true,
             registers,
+            bus: MemoryBus::with_console(),
+            protection: None,
+            program: None,
             running: false,
             io_handle,
-            plugins: Some(Vec::new()),
+            plugins: Some(plugins),
+            limits: Limits::default(),
+            instructions_executed: 0,
+            output_chars: 0,
+            started_at: None,
+            instruction_address: PC_START,
+            priority: 0,
+            supervisor: false,
+            saved_ssp: SUPERVISOR_STACK_START,
+            saved_usp: 0,
+            #[cfg(feature = "jit")]
+            jit: None,
+        }
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    // Turn the decode cache off to decode every instruction each time it's
+    // executed, for measuring what the cache saves. The JIT only finds code
+    // through the cache, so it compiles nothing while the cache is off.
+    pub fn set_decode_cache(&mut self, enabled: bool) {
+        self.decode_cache = enabled;
+        if !enabled {
+            self.decoded.iter_mut().for_each(|decoded| *decoded = None);
         }
     }
 
+    // Restrict how a range of memory can be accessed. Breaking the
+    // restriction stops the program with LC3Error::AccessViolation. Loading
+    // a program isn't checked.
+    pub fn protect(&mut self, addresses: RangeInclusive<u16>, protection: Protection) {
+        self.protection
+            .get_or_insert_with(ProtectionMap::new)
+            .protect(addresses, protection);
+    }
+
+    // Map a device over a range of addresses, which can't overlap any
+    // device already there
+    pub fn add_device(&mut self, addresses: RangeInclusive<u16>, device: Box<dyn Device>) -> LC3Result<()> {
+        self.bus.add(addresses, device)
+    }
+
     pub fn run(&mut self) -> LC3Result<()> {
+        self.start()?;
+
+        while self.get_running()? {
+            #[cfg(feature = "jit")]
+            if self.run_compiled()? {
+                continue;
+            }
+            self.step()?;
+        }
+
+        Ok(())
+    }
+
+    // Compile hot blocks to native code during run. Compiled code is only
+    // used while no plugins are listening, since it doesn't raise events,
+    // and while no devices tick, so timers see every instruction and
+    // interrupts are taken where they would be when interpreted.
+    // Stepping through a program with step is always interpreted.
+    #[cfg(feature = "jit")]
+    pub fn enable_jit(&mut self) -> LC3Result<()> {
+        let mut jit = Jit::new()?;
+        for (address, decoded) in self.decoded.iter().enumerate() {
+            if decoded.is_some() {
+                jit.mark_code(address as u16);
+            }
+        }
+        self.jit = Some(jit);
+
+        Ok(())
+    }
+
+    #[cfg(all(feature = "jit", test))]
+    pub(crate) fn jit(&self) -> Option<&Jit> {
+        self.jit.as_ref()
+    }
+
+    // Run the compiled block at the program counter, if there is one and
+    // it's safe to. Returns false when the interpreter should take the next
+    // instruction instead.
+    #[cfg(feature = "jit")]
+    fn run_compiled(&mut self) -> LC3Result<bool> {
+        let plugins_idle = self.plugins.as_ref().is_none_or(|plugins| plugins.is_empty());
+        // Compiled code leaves everything from MMIO_START up to the
+        // interpreter, so devices below that rule it out
+        let devices_clear = self.bus.lowest_address().is_none_or(|address| address >= MMIO_START);
+        let devices_idle = !self.bus.has_ticking_devices() && self.bus.pending_interrupt().is_none();
+        // Nor does it check memory protection
+        let unprotected = self.protection.is_none();
+        let jit = match self.jit.as_mut() {
+            Some(jit) if (!Plugins::ACTIVE || plugins_idle) && devices_clear && devices_idle && unprotected => jit,
+            _ => return Ok(false),
+        };
+
+        let pc = self.registers[RPC.to_u8() as usize];
+        let block = match jit.block_at(pc, &self.memory)? {
+            Some(block) => block,
+            None => return Ok(false),
+        };
+
+        // Leave the last few instructions before the limit to the
+        // interpreter so it stops in exactly the same place
+        let len = block.len() as u64;
+        if let Some(limit) = self.limits.max_instructions {
+            if self.instructions_executed + len > limit {
+                return Ok(false);
+            }
+        }
+        if let (Some(limit), Some(started_at)) = (self.limits.max_duration, self.started_at) {
+            let interval = self.instructions_executed / TIME_CHECK_INTERVAL;
+            let crosses_check = (self.instructions_executed + len) / TIME_CHECK_INTERVAL != interval;
+            if crosses_check && started_at.elapsed() >= limit {
+                return Err(LC3Error::TimeLimit { limit, pc });
+            }
+        }
+
+        let completed = jit.run(block, &mut self.registers, &mut self.memory);
+        if completed == 0 {
+            return Ok(false);
+        }
+
+        self.instructions_executed += completed as u64;
+        for _ in 0..completed {
+            self.io_handle.tick()?;
+        }
+        if self.io_handle.halt_requested()? {
+            self.set_running(false)?;
+        }
+
+        Ok(true)
+    }
+
+    // Put the VM in the running state with the program counter at the start
+    // of the loaded program, ready to be driven one instruction at a time
+    // with step.
+    pub fn start(&mut self) -> LC3Result<()> {
         self.set_running(true)?;
         self.reg_write(RPC, PC_START)?;
+        self.priority = 0;
+        self.supervisor = false;
+        self.saved_ssp = SUPERVISOR_STACK_START;
+        self.instructions_executed = 0;
+        self.output_chars = 0;
+        self.started_at = Some(Instant::now());
 
-        while self.get_running()? {
-            let program_count = self.reg_read(RPC)?;
-            self.reg_write(RPC, program_count + 1)?;
+        Ok(())
+    }
+
+    // Fetch and execute a single instruction, first taking any interrupt a
+    // device is raising above the running priority.
+    pub fn step(&mut self) -> LC3Result<()> {
+        if let Some(interrupt) = self.bus.pending_interrupt() {
+            if interrupt.priority > self.priority {
+                self.take_interrupt(interrupt)?;
+            }
+        }
+
+        let program_count = self.reg_read(RPC)?;
+        self.instruction_address = program_count;
+        self.check_limits()?;
+        self.instructions_executed += 1;
+        self.reg_write(RPC, program_count + 1)?;
+
+        self.check_access(program_count, Access::Execute)?;
+        let bytes = self.read_word(program_count)?;
+        let instruction = self.decode_at(program_count, bytes);
+        self.notify_plugins(&Event::Command { bytes })?;
+        self.execute(instruction)?;
+
+        self.io_handle.tick()?;
+        if self.bus.has_ticking_devices() {
+            self.bus.tick()?;
+        }
+        if self.io_handle.halt_requested()? {
+            self.set_running(false)?;
+        }
+
+        Ok(())
+    }
+
+    // Switch to the supervisor stack if need be, push the PSR and PC, and
+    // jump to the handler in the vector table at the interrupt's priority
+    fn take_interrupt(&mut self, interrupt: Interrupt) -> LC3Result<()> {
+        self.notify_plugins(&Event::Interrupt {
+            vector: interrupt.vector,
+            priority: interrupt.priority,
+        })?;
+
+        let psr = self.psr();
+        if !self.supervisor {
+            self.saved_usp = self.reg_read(RR6)?;
+            self.reg_write(RR6, self.saved_ssp)?;
+            self.supervisor = true;
+        }
+        self.priority = interrupt.priority;
+        let pc = self.reg_read(RPC)?;
+        self.push(psr)?;
+        self.push(pc)?;
 
-            let command = Command::new(self.mem_read(program_count)?);
-            self.run_command(&command)?;
+        let handler = self.read_word(INTERRUPT_VECTOR_TABLE + interrupt.vector as u16)?;
+        self.reg_write(RPC, handler)
+    }
+
+    // Pop the PC and PSR an interrupt pushed, going back to the user stack
+    // if the interrupt came from user mode
+    pub(crate) fn return_from_interrupt(&mut self) -> LC3Result<()> {
+        if !self.supervisor {
+            return Err(LC3Error::PrivilegeViolation { pc: self.instruction_address });
+        }
+
+        let pc = self.pop()?;
+        let psr = self.pop()?;
+        self.reg_write(RPC, pc)?;
+        self.reg_write(RCond, psr & 0x7)?;
+        self.priority = ((psr >> 8) & 0x7) as u8;
+        if psr & PSR_USER != 0 {
+            self.saved_ssp = self.reg_read(RR6)?;
+            self.reg_write(RR6, self.saved_usp)?;
+            self.supervisor = false;
         }
 
         Ok(())
     }
 
-    pub fn load_program(&mut self, program: &Vec<u16>) -> LC3Result<()> {
+    fn psr(&self) -> u16 {
+        let mode = if self.supervisor { 0 } else { PSR_USER };
+        mode | (self.priority as u16) << 8 | self.condition_flags()
+    }
+
+    // The supervisor stack is pushed and popped by the processor itself,
+    // so memory protection doesn't apply
+    fn push(&mut self, value: u16) -> LC3Result<()> {
+        let sp = self.reg_read(RR6)?.wrapping_sub(1);
+        self.reg_write(RR6, sp)?;
+        self.write_word(sp, value)
+    }
+
+    fn pop(&mut self) -> LC3Result<u16> {
+        let sp = self.reg_read(RR6)?;
+        let value = self.read_word(sp)?;
+        self.reg_write(RR6, sp.wrapping_add(1))?;
+        Ok(value)
+    }
+
+    fn check_limits(&self) -> LC3Result<()> {
+        let pc = self.instruction_address;
+        if let Some(limit) = self.limits.max_instructions {
+            if self.instructions_executed >= limit {
+                return Err(LC3Error::InstructionLimit { limit, pc });
+            }
+        }
+
+        if let (Some(limit), Some(started_at)) = (self.limits.max_duration, self.started_at) {
+            let due_check = self.instructions_executed % TIME_CHECK_INTERVAL == 0;
+            if due_check && started_at.elapsed() >= limit {
+                return Err(LC3Error::TimeLimit { limit, pc });
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn load_program(&mut self, program: &[u16]) -> LC3Result<()> {
         let max_len = MEMORY_SIZE - PC_START as usize;
         if program.len() > max_len {
             let err = LC3Error::ProgramSize {
@@ -85,31 +422,50 @@ impl<IOType: IOHandle> VM<IOType> {
         }
 
         for (index, instruction) in program.iter().enumerate() {
-            self.mem_write(PC_START + index as u16, *instruction)?;
+            self.write_word(PC_START + index as u16, *instruction)?;
         }
+        self.program = match program.len() {
+            0 => None,
+            len => Some(PC_START..=PC_START + (len - 1) as u16),
+        };
 
         Ok(())
     }
 
+    #[inline]
+    fn check_access(&self, address: u16, access: Access) -> LC3Result<()> {
+        match &self.protection {
+            Some(protection) if !protection.allows(address, access) => Err(LC3Error::AccessViolation {
+                pc: self.instruction_address,
+                address,
+                access,
+            }),
+            _ => Ok(()),
+        }
+    }
+
     pub(crate) fn mem_read(&mut self, pos: u16) -> LC3Result<u16> {
-        // Deal with the mem-mapped device registers
-        if pos == KB_STATUS_POS {
-            if self.is_key_down()? {
-                // TODO: Right now, I think there's a bug here. If the key
-                // being pressed is not a key handled by getchar()
-                // then the vm will fill the status register and pause
-                // waiting for the user to press one of those keys before
-                // actually doing anything. Not a show stopper, but one to
-                // watch.
-                self.mem_write(KB_STATUS_POS, 1 << 15)?;
-                let ch = self.getchar()?;
-                self.mem_write(KB_DATA_POS, ch as u16)?;
-            } else {
-                self.mem_write(KB_STATUS_POS, 0)?;
+        self.check_access(pos, Access::Read)?;
+        self.read_word(pos)
+    }
+
+    pub(crate) fn mem_write(&mut self, pos: u16, val: u16) -> LC3Result<()> {
+        self.check_access(pos, Access::Write)?;
+        self.write_word(pos, val)
+    }
+
+    // Reads and writes without the protection check
+    fn read_word(&mut self, pos: u16) -> LC3Result<u16> {
+        let val = match self.bus.device_at(pos) {
+            Some(index) => {
+                let mut device = self.bus.take(index)?;
+                let result = device.read(pos, self);
+                self.bus.put_back(index, device);
+                result?
             }
+            None => self.memory[pos as usize],
         };
 
-        let val = self.memory[pos as usize];
         self.notify_plugins(&Event::MemGet {
             location: pos,
             value: val,
@@ -117,12 +473,26 @@ impl<IOType: IOHandle> VM<IOType> {
         Ok(val)
     }
 
-    pub(crate) fn mem_write(&mut self, pos: u16, val: u16) -> LC3Result<()> {
+    fn write_word(&mut self, pos: u16, val: u16) -> LC3Result<()> {
         self.notify_plugins(&Event::MemSet {
             location: pos,
             value: val,
         })?;
+        if let Some(index) = self.bus.device_at(pos) {
+            let mut device = self.bus.take(index)?;
+            let result = device.write(pos, val, self);
+            self.bus.put_back(index, device);
+            return result;
+        }
+
         self.memory[pos as usize] = val;
+        self.decoded[pos as usize] = None;
+        #[cfg(feature = "jit")]
+        if let Some(jit) = self.jit.as_mut() {
+            if jit.is_code(pos) {
+                jit.invalidate(pos);
+            }
+        }
         Ok(())
     }
 
@@ -149,6 +519,14 @@ impl<IOType: IOHandle> VM<IOType> {
     }
 
     pub(crate) fn putchar(&mut self, ch: char) -> LC3Result<()> {
+        if let Some(limit) = self.limits.max_output_chars {
+            if self.output_chars >= limit {
+                let pc = self.instruction_address;
+                return Err(LC3Error::OutputLimit { limit, pc });
+            }
+        }
+        self.output_chars += 1;
+
         self.notify_plugins(&Event::CharPut { ch })?;
         self.io_handle.putchar(ch)?;
         Ok(())
@@ -161,12 +539,12 @@ impl<IOType: IOHandle> VM<IOType> {
     }
 
     pub(crate) fn is_key_down(&mut self) -> LC3Result<bool> {
-        let key_down = self.io_handle.is_key_down().map_io_error()?;
+        let key_down = self.io_handle.is_key_down()?;
         self.notify_plugins(&Event::KeyDownGet { value: key_down })?;
         Ok(key_down)
     }
 
-    pub(crate) fn get_running(&mut self) -> LC3Result<bool> {
+    pub fn get_running(&mut self) -> LC3Result<bool> {
         let value = self.running;
         self.notify_plugins(&Event::RunningGet { value })?;
         Ok(value)
@@ -192,6 +570,7 @@ impl<IOType: IOHandle> VM<IOType> {
         Ok(())
     }
 
+    #[inline]
     pub(crate) fn notify_plugins(&mut self, event: &Event) -> LC3Result<()> {
         // This memory swapping dance prevents a safety issue.
         // Basically, if we were iterating over the plugins vector contained
@@ -214,68 +593,134 @@ impl<IOType: IOHandle> VM<IOType> {
         // running, it prevents the issue, at the cost of not being able to
         // get notifications on what the other plugins are doing.
 
-        if self.plugins.is_none() {
-            // We're in the notifications loop, don't push the event
+        //
+        // When the plugin set is known to be empty at compile time
+        // (NoPlugins) all of this folds away.
+
+        let idle = self.plugins.as_ref().is_none_or(|plugins| plugins.is_empty());
+        if !Plugins::ACTIVE || idle {
+            // Either there's nobody to notify or we're in the notifications
+            // loop, so don't push the event
             return Ok(());
         }
 
-        let mut plugins_option = None;
-        std::mem::swap(&mut plugins_option, &mut self.plugins);
-
-        // The option should never be None by here, but this ok_or call
+        // The option can't be None by here, but this ok_or_else call
         // handles that just in case.
-        let mut plugins = plugins_option.ok_or(LC3Error::Internal(
-            "None was returned for plugins after None check".to_string(),
-        ))?;
-
-        for plugin in &mut plugins {
-            plugin.handle_event(self, event)?
-        }
+        let mut plugins = self.plugins.take().ok_or_else(|| {
+            LC3Error::Internal("None was returned for plugins after None check".to_string())
+        })?;
 
+        let result = plugins.handle_event(self, event);
         self.plugins = Some(plugins);
 
-        Ok(())
+        result
     }
 
+    // Look the instruction at an address up in the decode cache, decoding
+    // and caching it on a miss. Words read from devices can change without
+    // a write, so they're never cached.
+    fn decode_at(&mut self, address: u16, bytes: u16) -> Instruction {
+        match self.decoded[address as usize] {
+            Some(instruction) => instruction,
+            None if !self.decode_cache || self.bus.is_mapped(address) => Instruction::decode(bytes),
+            None => {
+                #[cfg(feature = "jit")]
+                if let Some(jit) = self.jit.as_mut() {
+                    jit.mark_code(address);
+                }
+                let instruction = Instruction::decode(bytes);
+                self.decoded[address as usize] = Some(instruction);
+                instruction
+            }
+        }
+    }
+
+    // Execute a single word without going through the fetch or the decode
+    // cache. Only the op tests drive the VM this way.
+    #[cfg(test)]
     pub(crate) fn run_command(&mut self, command: &Command) -> LC3Result<()> {
         let event = Event::Command {
             bytes: command.get_bytes(),
         };
         self.notify_plugins(&event)?;
 
-        let op = Op::from_int(command.op_code()?)?;
-        match op {
-            Op::Br => handler::branch(self, command),
-            Op::Add => handler::add(self, command),
-            Op::Ld => handler::load(self, command),
-            Op::St => handler::store(self, command),
-            Op::Jsr => handler::jump_register(self, command),
-            Op::And => handler::and(self, command),
-            Op::Ldr => handler::load_register(self, command),
-            Op::Str => handler::store_register(self, command),
-            Op::Rti => handler::rti(self, command),
-            Op::Not => handler::not(self, command),
-            Op::Ldi => handler::load_indirect(self, command),
-            Op::Sti => handler::store_indirect(self, command),
-            Op::Jmp => handler::jump(self, command),
-            Op::Res => handler::reserved(self, command),
-            Op::Lea => handler::load_effective_address(self, command),
-            Op::Trap => handler::trap(self, command),
+        self.execute(Instruction::decode(command.get_bytes()))
+    }
+
+    fn execute(&mut self, instruction: Instruction) -> LC3Result<()> {
+        match instruction {
+            Instruction::Br { flags, offset } => handler::branch(self, flags, offset),
+            Instruction::Add { target, source, operand } => handler::add(self, target, source, operand),
+            Instruction::Ld { target, offset } => handler::load(self, target, offset),
+            Instruction::St { source, offset } => handler::store(self, source, offset),
+            Instruction::Jsr { offset } => handler::jump_subroutine(self, offset),
+            Instruction::Jsrr { base } => handler::jump_register(self, base),
+            Instruction::And { target, source, operand } => handler::and(self, target, source, operand),
+            Instruction::Ldr { target, base, offset } => handler::load_register(self, target, base, offset),
+            Instruction::Str { source, base, offset } => handler::store_register(self, source, base, offset),
+            Instruction::Rti => handler::rti(self),
+            Instruction::Not { target, source } => handler::not(self, target, source),
+            Instruction::Ldi { target, offset } => handler::load_indirect(self, target, offset),
+            Instruction::Sti { source, offset } => handler::store_indirect(self, source, offset),
+            Instruction::Jmp { base } => handler::jump(self, base),
+            Instruction::Res => handler::reserved(self),
+            Instruction::Lea { target, offset } => handler::load_effective_address(self, target, offset),
+            Instruction::Trap { code } => handler::trap(self, code),
         }
     }
 
-    #[cfg(test)]
-    pub(crate) fn into_io_handle(self) -> IOType {
+    pub fn io_handle(&self) -> &IOType {
+        &self.io_handle
+    }
+
+    pub fn io_handle_mut(&mut self) -> &mut IOType {
+        &mut self.io_handle
+    }
+
+    pub fn into_io_handle(self) -> IOType {
         self.io_handle
     }
 }
 
+impl<IOType: IOHandle, Plugins: PluginSet<IOType>> DeviceContext for VM<IOType, Plugins> {
+    fn getchar(&mut self) -> LC3Result<char> {
+        VM::getchar(self)
+    }
+
+    fn putchar(&mut self, ch: char) -> LC3Result<()> {
+        VM::putchar(self, ch)
+    }
+
+    fn is_key_down(&mut self) -> LC3Result<bool> {
+        VM::is_key_down(self)
+    }
+
+    fn instructions_executed(&self) -> u64 {
+        self.instructions_executed
+    }
+
+    fn peek_memory(&self, address: u16) -> u16 {
+        VM::peek_memory(self, address)
+    }
+
+    fn dma_read(&mut self, address: u16) -> LC3Result<u16> {
+        self.read_word(address)
+    }
+
+    fn dma_write(&mut self, address: u16, value: u16) -> LC3Result<()> {
+        self.write_word(address, value)
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::VM;
     use crate::condition_flags::{FL_NEG, FL_POS, FL_ZRO};
-    use crate::error::LC3Result;
-    use crate::io::TestIOHandle;
+    use crate::device::{KBDR, KBSR};
+    use crate::error::{LC3Error, LC3Result};
+    use crate::io::{ScriptedIOHandle, TestIOHandle};
+    use crate::limits::Limits;
+    use std::time::Duration;
     use crate::register::Register::RCond;
 
     #[test]
@@ -309,9 +754,9 @@ mod test {
         // register read fails (and should, since we're not on a physical
         // machine there's nothing independently updating the registers
         // on its own schedule).
-        assert_eq!(vm.mem_read(super::KB_STATUS_POS)?, 1 << 15);
+        assert_eq!(vm.mem_read(KBSR)?, 1 << 15);
         assert_eq!(
-            vm.mem_read(super::KB_DATA_POS)? as u8 as char,
+            vm.mem_read(KBDR)? as u8 as char,
             test_char
         );
 
@@ -344,4 +789,70 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn can_run_self_modifying_program() -> LC3Result<()> {
+        let program = vec![
+            // LD R1 with the replacement instruction at the end
+            0b0010_0010_0000_0100,
+            // ADD R0, R0, #1. Overwritten with ADD R0, R0, #2 the second time round
+            0b0001_0000_0010_0001,
+            // ST R1 over the ADD above
+            0b0011_0011_1111_1110,
+            // BRp back to the ADD
+            0b0000_0011_1111_1101,
+            // HALT
+            0xF025,
+            // ADD R0, R0, #2
+            0b0001_0000_0010_0010,
+        ];
+
+        let mut vm = VM::new_with_io(ScriptedIOHandle::new());
+        vm.load_program(&program)?;
+        vm.start()?;
+        for _ in 0..4 {
+            vm.step()?;
+        }
+        assert_eq!(vm.reg_index_read(0)?, 1);
+
+        // The ADD was executed and cached before the store replaced it
+        vm.step()?;
+        assert_eq!(vm.reg_index_read(0)?, 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn can_enforce_limits() -> LC3Result<()> {
+        // Prints 'a' forever: LD R0 from the word after the loop, OUT, BRnzp back
+        let program = vec![0b0010_0000_0000_0010, 0xF021, 0b0000_1111_1111_1101, 'a' as u16];
+
+        let mut vm = VM::new_with_io(ScriptedIOHandle::new());
+        vm.set_limits(Limits::new().max_instructions(10));
+        vm.load_program(&program)?;
+        match vm.run() {
+            Err(LC3Error::InstructionLimit { limit: 10, pc: 0x3001 }) => {}
+            other => panic!("Expected an instruction limit error, got {:?}", other),
+        }
+        assert_eq!(vm.instructions_executed(), 10);
+
+        let mut vm = VM::new_with_io(ScriptedIOHandle::new());
+        vm.set_limits(Limits::new().max_output_chars(3));
+        vm.load_program(&program)?;
+        match vm.run() {
+            Err(LC3Error::OutputLimit { limit: 3, pc: 0x3001 }) => {}
+            other => panic!("Expected an output limit error, got {:?}", other),
+        }
+        assert_eq!(vm.io_handle().output(), "aaa");
+
+        let mut vm = VM::new_with_io(ScriptedIOHandle::new());
+        vm.set_limits(Limits::new().max_duration(Duration::from_millis(20)));
+        vm.load_program(&program)?;
+        match vm.run() {
+            Err(err @ LC3Error::TimeLimit { .. }) => assert!(err.is_limit()),
+            other => panic!("Expected a time limit error, got {:?}", other),
+        }
+
+        Ok(())
+    }
 }